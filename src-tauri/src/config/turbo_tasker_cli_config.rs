@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use crate::state::visitor_config::VisitorConfig;
 
 #[derive(Debug, Parser)]
 #[command(name = "tt", version = "0.1.0", author = "toaler", about = "Turbo Tasker - Keeping PC's organized since 2024!")]
@@ -15,6 +16,37 @@ pub enum Command {
 
         #[arg(long = "root", short = 'r', required = true, help = "root path to start resource analysis")]
         root: String,
+
+        #[arg(long = "visitor", short = 'v', required = false, help = "visitor to run, by name with an optional JSON config, e.g. top_k or text_similarity:{\"threshold\":0.9}")]
+        visitors: Vec<VisitorConfig>,
+
+        #[arg(long = "visitors_file", required = false, help = "JSON file listing the visitors to run")]
+        visitors_file: Option<String>,
     },
     Cpu,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::visitor_config::{TextSimilarityConfig, TopKConfig};
+
+    #[test]
+    fn test_parses_visitors() {
+        let app = TurboTaskerApp::try_parse_from(["tt", "disk", "--root", "/data", "--visitor", "top_k", "-v", r#"text_similarity:{"threshold":0.9}"#]).unwrap();
+
+        match app.cmd {
+            Some(Command::Disk { root, visitors, visitors_file, duplicate_detection }) => {
+                assert_eq!(root, "/data");
+                assert_eq!(visitors, vec![
+                    VisitorConfig::TopK(TopKConfig::default()),
+                    VisitorConfig::TextSimilarity(TextSimilarityConfig { threshold: 0.9, ..Default::default() }),
+                ]);
+                assert_eq!(visitors_file, None);
+                assert!(!duplicate_detection);
+            }
+            cmd => panic!("unexpected command {:?}", cmd),
+        }
+        assert!(TurboTaskerApp::try_parse_from(["tt", "disk", "--root", "/data", "--visitor", "nope"]).is_err());
+        assert!(TurboTaskerApp::try_parse_from(["tt"]).unwrap().cmd.is_none());
+    }
+}
@@ -4,19 +4,33 @@ mod config;
 mod ui;
 mod services;
 
-use crate::ui::command::scan_filesystem::scan_filesystem;
+use crate::config::turbo_tasker_cli_config::{Command, TurboTaskerApp};
+use crate::ui::command::scan_filesystem::{cancel_scan, scan_filesystem, scan_from_cli};
 use crate::ui::command::staged_resource_manager::commit;
-use log::{debug, error, info};
-use std::{env, io};
-use std::collections::HashMap;
+use crate::ui::command::snapshot_manager::{compare_snapshots, directory_history, list_snapshots};
+use crate::ui::command::registry_exchange::{export_manifest, export_ncdu, import_ncdu};
+use crate::ui::command::registry_verification::verify_registry;
+use crate::ui::command::directory_tree::directory_tree;
+use crate::ui::command::type_breakdown::type_breakdown;
+use log::{debug, error, info, warn};
+use std::env;
 use std::fs::File;
-use std::io::{BufReader, ErrorKind};
+use std::io::BufReader;
 use std::path::{PathBuf};
 use std::error::Error;
-use csv::{ReaderBuilder, WriterBuilder};
+use std::time::Duration;
+use clap::Parser;
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
 use tauri::{Manager};
-use state::resource_metadata::ResourceMetadata;
+use state::fingerprint::Fingerprint;
+use state::registry::Registry;
+use state::resource_metadata::{Ownership, ResourceMetadata};
+use services::scanner_api::event_handler::EventHandler;
 use services::scanner_api::visitable::Visitable;
+use services::scanner_impl::resource_scanner::ResourceScanner;
+use services::registry_impl::registry_lock::RegistryLock;
+use services::registry_impl::registry_verifier::{write_checksum, ChecksumWriter};
+use util::util::trim_dir;
 
 fn main() {
     // Initialize the logger.jsx
@@ -24,6 +38,22 @@ fn main() {
     info!("Starting Turbo Tasker");
     debug!("Debug mode enabled");
 
+    // Subcommands run headless, the window only opens without one
+    if let Some(cmd) = TurboTaskerApp::parse().cmd {
+        let code = match run_command(cmd) {
+            Ok(message) => {
+                info!("{}", message);
+                0
+            }
+            Err(e) => {
+                error!("{}", e);
+                eprintln!("{}", e);
+                1
+            }
+        };
+        std::process::exit(code);
+    }
+
     tauri::Builder::default()
         .setup(|app| {
             let splash_window = app.get_window("splashscreen").expect("Failed to find splash window");
@@ -41,12 +71,55 @@ fn main() {
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![scan_filesystem, commit])
+        .invoke_handler(tauri::generate_handler![scan_filesystem, cancel_scan, commit, list_snapshots, compare_snapshots, directory_history, import_ncdu, export_ncdu, export_manifest, verify_registry, directory_tree, type_breakdown])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
-fn save_registry(registry: &mut HashMap<String, ResourceMetadata>, file_path: &PathBuf) -> Result<(), std::io::Error> {
+fn run_command(cmd: Command) -> Result<&'static str, String> {
+    match cmd {
+        Command::Disk { duplicate_detection, root, visitors, visitors_file } => scan_from_cli(&root, visitors, visitors_file, duplicate_detection),
+        Command::Cpu => Err("The cpu command isn't supported yet".to_string()),
+    }
+}
+
+const REGISTRY_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn registry_path() -> PathBuf {
+    env::temp_dir().join("output.csv")
+}
+
+/// Locks the registry for a load, modify, save cycle, see `RegistryLock`. Waiting for another
+/// process happens on the blocking pool, not on the async runtime.
+async fn lock_registry(owner: &str) -> Result<RegistryLock, std::io::Error> {
+    let owner = owner.to_string();
+    tokio::task::spawn_blocking(move || RegistryLock::acquire(&registry_path(), &owner, REGISTRY_LOCK_TIMEOUT))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Loads the registry and replays everything below `path` through the visitor `new_visitor`
+/// makes, then recaps it, so a view can be built again without scanning. A trailing `/` of
+/// `path` is ignored.
+async fn replay_registry<V: Visitable>(uid: &str, path: &str, new_visitor: impl FnOnce() -> V, logger: &(dyn EventHandler + Sync)) -> Result<(), String> {
+    let path = trim_dir(path);
+    let mut registry = Registry::new();
+    {
+        let _registry_lock = lock_registry(uid).await.map_err(|e| format!("Failed to lock registry: {}", e))?;
+        load_registry(&mut registry, &registry_path()).map_err(|e| format!("Failed to load registry: {}", e))?;
+    }
+    if !registry.contains_key(&path) {
+        return Err(format!("{} is not in the registry", path));
+    }
+
+    // Built once the lock is released, visitors needn't be sent across threads
+    let mut visitor = new_visitor();
+    ResourceScanner::new().replay(&path, &registry, &mut [&mut visitor], &mut std::io::sink(), logger);
+    visitor.recap(&mut std::io::sink(), logger);
+    Ok(())
+}
+
+fn save_registry(registry: &mut Registry, file_path: &PathBuf) -> Result<(), std::io::Error> {
     info!("Saving registry");
 
     let file = match File::create(file_path) {
@@ -58,114 +131,118 @@ fn save_registry(registry: &mut HashMap<String, ResourceMetadata>, file_path: &P
         }
     };
 
-    // Create a CSV writer
-    let mut writer = WriterBuilder::new().from_writer(file);
+    // Create a CSV writer, checksumming the content as it's written
+    let mut writer = WriterBuilder::new().from_writer(ChecksumWriter::new(file));
 
     // Write header
-    for m in registry.values() {
+    for m in registry.iter() {
         let t = m.modified().to_string();
         let path = m.get_path().clone();
         let dir = m.is_dir().to_string();
         let sym = m.is_symlink().to_string();
         let size = m.size_bytes().to_string();
+        let fingerprint = m.fingerprint().map(|f| f.to_string()).unwrap_or_default();
+        let (uid, gid, mode) = match m.ownership() {
+            Some(o) => (o.uid.to_string(), o.gid.to_string(), o.mode.to_string()),
+            None => Default::default(),
+        };
 
-        writer.write_record(&[path, dir, sym, t, size])?;
+        writer.write_record(&[path, dir, sym, t, size, fingerprint, uid, gid, mode])?;
     }
 
     writer.flush()?;
+    let checksum = writer.into_inner().map_err(|e| e.into_error())?.finish();
+    write_checksum(file_path, checksum)?;
 
     info!("Persisted registry");
 
     Ok(())
 }
 
-fn load_registry(registry: &mut HashMap<String, ResourceMetadata>, file_path: &PathBuf) -> Result<HashMap<String, ResourceMetadata>, Box<dyn Error>> {
+/// Parses a registry record, [path, dir, sym, t, size, fingerprint, uid, gid, mode]. The
+/// fingerprint is empty for entries that were never hashed, it and the ownership are absent in
+/// registries written before they were recorded.
+fn parse_registry_record(record: &StringRecord) -> Result<ResourceMetadata, String> {
+    let path = match record.get(0) {
+        Some(v) => v.to_string(),
+        None => return Err("Missing path in CSV record".to_string()),
+    };
+
+    let is_dir = match record.get(1).map(|v| v.parse::<bool>()) {
+        Some(Ok(b)) => b,
+        Some(Err(e)) => return Err(format!("Error parsing is_dir: {}", e)),
+        None => return Err("Missing is_dir in CSV record".to_string()),
+    };
+
+    let is_symlink = match record.get(2).map(|v| v.parse::<bool>()) {
+        Some(Ok(b)) => b,
+        Some(Err(e)) => return Err(format!("Error parsing is_symlink: {}", e)),
+        None => return Err("Missing is_symlink in CSV record".to_string()),
+    };
+
+    let modified_time = match record.get(3).map(|v| v.parse::<i64>()) {
+        Some(Ok(t)) => t,
+        Some(Err(e)) => return Err(format!("Error parsing modified_time: {}", e)),
+        None => return Err("Missing modified_time in CSV record".to_string()),
+    };
+
+    let size_bytes = match record.get(4).map(|v| v.parse::<u64>()) {
+        Some(Ok(s)) => s,
+        Some(Err(e)) => return Err(format!("Error parsing size_bytes: {}", e)),
+        None => return Err("Missing size in CSV record".to_string()),
+    };
+
+    let fingerprint = match record.get(5).filter(|v| !v.is_empty()).map(|v| v.parse::<Fingerprint>()) {
+        Some(Ok(f)) => Some(f),
+        Some(Err(e)) => return Err(format!("Error parsing fingerprint: {}", e)),
+        None => None,
+    };
+
+    let ownership = match (record.get(6), record.get(7), record.get(8)) {
+        (Some(uid), Some(gid), Some(mode)) if !uid.is_empty() => match (uid.parse(), gid.parse(), mode.parse()) {
+            (Ok(uid), Ok(gid), Ok(mode)) => Some(Ownership { uid, gid, mode }),
+            _ => return Err(format!("Error parsing ownership {},{},{}", uid, gid, mode)),
+        },
+        _ => None,
+    };
+
+    Ok(ResourceMetadata::new(&path, is_dir, is_symlink, modified_time, size_bytes, false).with_fingerprint(fingerprint).with_ownership(ownership))
+}
+
+fn load_registry(registry: &mut Registry, file_path: &PathBuf) -> Result<(), Box<dyn Error>> {
     // TODO : Filter what is loaded to match the root dir that was passed in
     // Open the file using BufReader for efficiency
     let file = File::open(file_path)?;
     let reader = BufReader::new(file);
 
     // Create a CSV reader
-    let mut csv_reader = ReaderBuilder::new().has_headers(false).from_reader(reader);
+    let mut csv_reader = ReaderBuilder::new().has_headers(false).flexible(true).from_reader(reader);
+    let mut skipped = 0;
     // Iterate over CSV records
     for record in csv_reader.records() {
-
         let record = match record {
             Ok(record) => record,
             Err(e) => {
-                eprintln!("Failed to read a record: {:?}", e);
-                continue; // Skip this record and continue with the next
-            }
-        };
-
-        // Assuming the CSV file structure is [path, dir, sym, t]
-        let path = match record.get(0).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Missing path in CSV record")) {
-            Ok(v) => v.to_string(),
-            Err(e) => {
-                eprintln!("Error parsing path: {}", e);
+                error!("Failed to read a record: {:?}", e);
+                skipped += 1;
                 continue; // Skip this record and continue with the next
             }
         };
 
-        let is_dir = match record.get(1).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Missing is_dir in CSV record")) {
-            Ok(v) => match v.parse::<bool>() {
-                Ok(b) => b,
-                Err(e) => {
-                    eprintln!("Error parsing is_dir: {}", e);
-                    continue;
-                }
-            },
-            Err(e) => {
-                eprintln!("Error retrieving is_dir: {}", e);
-                continue;
+        match parse_registry_record(&record) {
+            Ok(resource_metadata) => {
+                registry.insert(&resource_metadata);
             }
-        };
-
-        let is_symlink = match record.get(2).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Missing is_symlink in CSV record")) {
-            Ok(v) => match v.parse::<bool>() {
-                Ok(b) => b,
-                Err(e) => {
-                    eprintln!("Error parsing is_symlink: {}", e);
-                    continue;
-                }
-            },
-            Err(e) => {
-                eprintln!("Error retrieving is_symlink: {}", e);
-                continue;
-            }
-        };
-
-        let modified_time = match record.get(3).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Missing modified_time in CSV record")) {
-            Ok(v) => match v.parse::<i64>() {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("Error parsing modified_time: {}", e);
-                    continue;
-                }
-            },
             Err(e) => {
-                eprintln!("Error retrieving modified_time: {}", e);
-                continue;
+                error!("Skipping registry record at line {}: {}", record.position().map_or(0, |p| p.line()), e);
+                skipped += 1;
             }
-        };
+        }
+    }
 
-        let size_bytes = match record.get(4).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Missing size in CSV record")) {
-            Ok(v) => match v.parse::<u64>() {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Error parsing size_bytes: {}", e);
-                    continue;
-                }
-            },
-            Err(e) => {
-                eprintln!("Error retrieving size_bytes: {}", e);
-                continue;
-            }
-        };
-        // Create ResourceMetadata and insert into the registry
-        let resource_metadata = ResourceMetadata::new(&path, is_dir, is_symlink, modified_time, size_bytes, false);
-        registry.insert(path, resource_metadata);
+    if skipped > 0 {
+        warn!("Skipped {} malformed registry records, verify the registry to repair it", skipped);
     }
     info!("Incremental scan detected");
-    Ok(registry.clone()) // Use clone() to return a new HashMap
+    Ok(())
 }
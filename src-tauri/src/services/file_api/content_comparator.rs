@@ -0,0 +1,8 @@
+use std::io;
+
+/// Compares file contents byte for byte.
+pub trait ContentComparator {
+    /// Whether both files hold exactly the same bytes. `progress` is called with the bytes read
+    /// from both files so far. Cancellation is reported as an `Interrupted` error.
+    fn identical(&self, a: &str, b: &str, progress: &mut dyn FnMut(u64)) -> io::Result<bool>;
+}
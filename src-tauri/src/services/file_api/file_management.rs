@@ -1,3 +1,5 @@
+use crate::state::resource_metadata::ResourceMetadata;
+
 pub enum DeletionStatus {
     Success,
     Failure(String),
@@ -6,4 +8,11 @@ pub enum DeletionStatus {
 pub trait FileManagement {
     fn delete_file(&self, file_path: &str) -> DeletionStatus;
     fn delete_files(&self, files: &[&str]) -> Vec<DeletionStatus>;
+    /// Replaces the copy at `duplicate` with a hardlink to `keeper`. Both are passed as they were
+    /// scanned and are left alone unless they're regular files on the same device that still
+    /// have that size and modification time.
+    fn replace_with_hardlink(&self, duplicate: &ResourceMetadata, keeper: &ResourceMetadata) -> DeletionStatus;
+    /// Deletes the directory at `dir_path` with everything below it. A symlink to a directory
+    /// isn't followed, it's refused.
+    fn delete_directory(&self, dir_path: &str) -> DeletionStatus;
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+use std::{fmt, io};
+use crate::state::fingerprint::{Fingerprint, FingerprintAlgorithm};
+
+#[derive(Debug)]
+pub enum FingerprintError {
+    Io(io::Error),
+    Cancelled,
+}
+
+impl fmt::Display for FingerprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FingerprintError::Io(e) => write!(f, "{}", e),
+            FingerprintError::Cancelled => write!(f, "Fingerprinting was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for FingerprintError {}
+
+impl From<io::Error> for FingerprintError {
+    fn from(e: io::Error) -> Self {
+        FingerprintError::Io(e)
+    }
+}
+
+/// Hashes file content. `progress` is called with the bytes hashed so far and the total.
+pub trait FingerprintService {
+    fn algorithm(&self) -> FingerprintAlgorithm;
+
+    /// Fingerprint of the whole file.
+    fn fingerprint(&self, path: &str, progress: &mut dyn FnMut(u64, u64)) -> Result<Fingerprint, FingerprintError>;
+
+    /// Fingerprint of the given `(offset, len)` ranges of the file, hashed in order.
+    fn fingerprint_ranges(&self, path: &str, ranges: &[(u64, u64)]) -> Result<Fingerprint, FingerprintError>;
+}
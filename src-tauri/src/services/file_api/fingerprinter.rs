@@ -0,0 +1,8 @@
+use crate::state::fingerprint::Fingerprint;
+
+/// Incremental content hash. Bytes are fed with `update` and the digest is taken once.
+pub trait Fingerprinter {
+    fn update(&mut self, bytes: &[u8]);
+
+    fn finish(self: Box<Self>) -> Fingerprint;
+}
@@ -1,4 +1,9 @@
 pub(crate) mod file_type_detector;
 pub(crate) mod compression_checker;
 pub(crate) mod file_management;
-pub(crate) mod compressor;
\ No newline at end of file
+pub(crate) mod compressor;
+pub(crate) mod fingerprint_service;
+pub(crate) mod fingerprinter;
+pub(crate) mod content_comparator;
+pub(crate) mod perceptual_hasher;
+pub(crate) mod text_signature_service;
\ No newline at end of file
@@ -0,0 +1,10 @@
+use std::io;
+use crate::state::image_cluster::PerceptualHashAlgorithm;
+
+/// Hashes what an image looks like rather than its bytes, so resized and re-encoded copies
+/// hash close together. Files that can't be decoded fail with `InvalidData`.
+pub trait PerceptualHasher {
+    fn algorithm(&self) -> PerceptualHashAlgorithm;
+
+    fn hash(&self, path: &str) -> io::Result<u64>;
+}
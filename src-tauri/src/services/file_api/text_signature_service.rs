@@ -0,0 +1,10 @@
+use std::io;
+use crate::state::text_cluster::{TextSignature, TextSignatureAlgorithm};
+
+/// Signs the text of a file so near-identical files can be found without comparing them.
+/// Binary files fail with `InvalidData`.
+pub trait TextSignatureService {
+    fn algorithm(&self) -> TextSignatureAlgorithm;
+
+    fn signature(&self, path: &str) -> io::Result<TextSignature>;
+}
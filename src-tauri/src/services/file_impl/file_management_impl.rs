@@ -1,9 +1,53 @@
 use std::fs;
-use log::{error, info};
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use log::{error, info, warn};
+use uuid::Uuid;
 use crate::services::file_api::file_management::{DeletionStatus, FileManagement};
+use crate::state::resource_metadata::ResourceMetadata;
 
 pub struct FileManagementImpl;
 
+impl FileManagementImpl {
+    /// Checks `scanned` still describes the regular file on disk and returns its current metadata.
+    fn unchanged(scanned: &ResourceMetadata) -> io::Result<fs::Metadata> {
+        let current = fs::symlink_metadata(scanned.get_path())?;
+        if !current.is_file() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} is not a regular file", scanned.get_path())));
+        }
+        if current.mtime() != scanned.modified() || current.len() != scanned.size_bytes() {
+            return Err(io::Error::other(format!("{} changed since the scan", scanned.get_path())));
+        }
+        Ok(current)
+    }
+
+    fn link(duplicate: &ResourceMetadata, keeper: &ResourceMetadata) -> io::Result<()> {
+        let duplicate_current = Self::unchanged(duplicate)?;
+        let keeper_current = Self::unchanged(keeper)?;
+        if duplicate_current.dev() != keeper_current.dev() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} and {} are on different devices", duplicate.get_path(), keeper.get_path())));
+        }
+        if duplicate_current.ino() == keeper_current.ino() {
+            return Err(io::Error::new(ErrorKind::AlreadyExists, format!("{} is already linked to {}", duplicate.get_path(), keeper.get_path())));
+        }
+
+        // The link is made under a fresh name, which fails rather than replacing anything, and
+        // then renamed over the copy in one step
+        let path = Path::new(duplicate.get_path());
+        let file_name = path.file_name().ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, format!("{} has no file name", duplicate.get_path())))?;
+        let temp = path.with_file_name(format!(".{}.{}.tt-hardlink", file_name.to_string_lossy(), Uuid::new_v4()));
+        fs::hard_link(keeper.get_path(), &temp)?;
+        if let Err(e) = fs::rename(&temp, path) {
+            if let Err(remove) = fs::remove_file(&temp) {
+                warn!("Failed to remove {:?}: {}", temp, remove);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
 impl FileManagement for FileManagementImpl {
     fn delete_file(&self, file_path: &str) -> DeletionStatus {
         match fs::remove_file(file_path) {
@@ -21,6 +65,37 @@ impl FileManagement for FileManagementImpl {
     fn delete_files(&self, files: &[&str]) -> Vec<DeletionStatus> {
         files.iter().map(|&file| self.delete_file(file)).collect()
     }
+
+    fn delete_directory(&self, dir_path: &str) -> DeletionStatus {
+        let result = match fs::symlink_metadata(dir_path) {
+            Ok(m) if m.is_dir() => fs::remove_dir_all(dir_path),
+            Ok(_) => Err(io::Error::new(ErrorKind::InvalidInput, format!("{} is not a directory", dir_path))),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_) => {
+                info!("Successfully deleted directory: {}", dir_path);
+                DeletionStatus::Success
+            }
+            Err(e) => {
+                error!("Failed to delete directory: {}. Error: {}", dir_path, e);
+                DeletionStatus::Failure(e.to_string())
+            }
+        }
+    }
+
+    fn replace_with_hardlink(&self, duplicate: &ResourceMetadata, keeper: &ResourceMetadata) -> DeletionStatus {
+        match Self::link(duplicate, keeper) {
+            Ok(_) => {
+                info!("Replaced {} with a hardlink to {}", duplicate.get_path(), keeper.get_path());
+                DeletionStatus::Success
+            }
+            Err(e) => {
+                error!("Failed to replace {} with a hardlink to {}. Error: {}", duplicate.get_path(), keeper.get_path(), e);
+                DeletionStatus::Failure(e.to_string())
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -65,5 +140,75 @@ mod tests {
         assert!(!Path::new(&temp_path1).exists());
         assert!(!Path::new(&temp_path2).exists());
     }
+
+    #[test]
+    fn test_delete_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let junk = dir.path().join("node_modules");
+        fs::create_dir_all(junk.join("left-pad")).unwrap();
+        fs::write(junk.join("left-pad/index.js"), "content").unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&junk, &link).unwrap();
+
+        assert!(matches!(FileManagementImpl.delete_directory(&link.to_string_lossy()), DeletionStatus::Failure(_)));
+        assert!(matches!(FileManagementImpl.delete_directory(&junk.to_string_lossy()), DeletionStatus::Success));
+        assert!(!junk.exists());
+    }
+
+    fn scanned(path: &Path) -> ResourceMetadata {
+        let m = fs::symlink_metadata(path).unwrap();
+        ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, m.mtime(), m.len(), false)
+    }
+
+    #[test]
+    fn test_replace_with_hardlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let keeper_path = dir.path().join("keeper");
+        let duplicate_path = dir.path().join("duplicate");
+        fs::write(&keeper_path, "content").unwrap();
+        fs::write(&duplicate_path, "content").unwrap();
+        let (keeper, duplicate) = (scanned(&keeper_path), scanned(&duplicate_path));
+
+        assert!(matches!(FileManagementImpl.replace_with_hardlink(&duplicate, &keeper), DeletionStatus::Success));
+
+        assert_eq!(fs::metadata(&duplicate_path).unwrap().ino(), fs::metadata(&keeper_path).unwrap().ino());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+        assert!(matches!(FileManagementImpl.replace_with_hardlink(&duplicate, &keeper), DeletionStatus::Failure(_)));
+    }
+
+    #[test]
+    fn test_replace_with_hardlink_keeps_files_named_like_the_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let keeper_path = dir.path().join("keeper");
+        let duplicate_path = dir.path().join("duplicate");
+        let bystander = dir.path().join(".duplicate.tt-hardlink");
+        fs::write(&keeper_path, "content").unwrap();
+        fs::write(&duplicate_path, "content").unwrap();
+        fs::write(&bystander, "mine").unwrap();
+        let (keeper, duplicate) = (scanned(&keeper_path), scanned(&duplicate_path));
+
+        assert!(matches!(FileManagementImpl.replace_with_hardlink(&duplicate, &keeper), DeletionStatus::Success));
+
+        assert_eq!(fs::metadata(&duplicate_path).unwrap().ino(), fs::metadata(&keeper_path).unwrap().ino());
+        assert_eq!(fs::read_to_string(&bystander).unwrap(), "mine");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_replace_with_hardlink_refuses_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let keeper_path = dir.path().join("keeper");
+        let duplicate_path = dir.path().join("duplicate");
+        fs::write(&keeper_path, "content").unwrap();
+        fs::write(&duplicate_path, "content").unwrap();
+        let (keeper, duplicate) = (scanned(&keeper_path), scanned(&duplicate_path));
+        fs::write(&duplicate_path, "content changed").unwrap();
+
+        match FileManagementImpl.replace_with_hardlink(&duplicate, &keeper) {
+            DeletionStatus::Failure(msg) => assert!(msg.contains("changed since the scan")),
+            DeletionStatus::Success => panic!("Changed file was replaced"),
+        }
+        assert_eq!(fs::read_to_string(&duplicate_path).unwrap(), "content changed");
+    }
 }
 
@@ -0,0 +1,88 @@
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+use crate::services::file_api::fingerprinter::Fingerprinter;
+use crate::state::fingerprint::{Fingerprint, FingerprintAlgorithm};
+
+/// Fast non-cryptographic 64 bit hash, the default for duplicate detection.
+pub struct Xxh3_64Fingerprinter(Xxh3);
+
+/// 128 bit xxh3, still fast but with a far lower chance of collisions on large trees.
+pub struct Xxh3_128Fingerprinter(Xxh3);
+
+/// Cryptographic hash for audits, faster than sha256.
+pub struct Blake3Fingerprinter(blake3::Hasher);
+
+/// Cryptographic hash comparable with `sha256sum` manifests.
+pub struct Sha256Fingerprinter(Sha256);
+
+/// Creates a fresh fingerprinter for `algorithm`.
+pub fn new_fingerprinter(algorithm: FingerprintAlgorithm) -> Box<dyn Fingerprinter> {
+    match algorithm {
+        FingerprintAlgorithm::Xxh3_64 => Box::new(Xxh3_64Fingerprinter(Xxh3::new())),
+        FingerprintAlgorithm::Xxh3_128 => Box::new(Xxh3_128Fingerprinter(Xxh3::new())),
+        FingerprintAlgorithm::Blake3 => Box::new(Blake3Fingerprinter(blake3::Hasher::new())),
+        FingerprintAlgorithm::Sha256 => Box::new(Sha256Fingerprinter(Sha256::new())),
+    }
+}
+
+// xxh3 digests use the canonical big endian byte order so hex output matches `xxhsum`
+impl Fingerprinter for Xxh3_64Fingerprinter {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Fingerprint {
+        Fingerprint::new(FingerprintAlgorithm::Xxh3_64, self.0.digest().to_be_bytes().to_vec())
+    }
+}
+
+impl Fingerprinter for Xxh3_128Fingerprinter {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Fingerprint {
+        Fingerprint::new(FingerprintAlgorithm::Xxh3_128, self.0.digest128().to_be_bytes().to_vec())
+    }
+}
+
+impl Fingerprinter for Blake3Fingerprinter {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Fingerprint {
+        Fingerprint::new(FingerprintAlgorithm::Blake3, self.0.finalize().as_bytes().to_vec())
+    }
+}
+
+impl Fingerprinter for Sha256Fingerprinter {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Fingerprint {
+        Fingerprint::new(FingerprintAlgorithm::Sha256, self.0.finalize().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(algorithm: FingerprintAlgorithm, chunks: &[&[u8]]) -> String {
+        let mut fingerprinter = new_fingerprinter(algorithm);
+        for chunk in chunks {
+            fingerprinter.update(chunk);
+        }
+        fingerprinter.finish().to_string()
+    }
+
+    #[test]
+    fn test_known_digests() {
+        assert_eq!(fingerprint(FingerprintAlgorithm::Xxh3_64, &[b"test ", b"content"]), format!("xxh3-64:{:016x}", 1307564309130158671u64));
+        assert_eq!(fingerprint(FingerprintAlgorithm::Xxh3_128, &[b""]), "xxh3-128:99aa06d3014798d86001c324468d497f");
+        assert_eq!(fingerprint(FingerprintAlgorithm::Blake3, &[b""]), "blake3:af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+        assert_eq!(fingerprint(FingerprintAlgorithm::Sha256, &[b"ab", b"c"]), "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
@@ -0,0 +1,129 @@
+use std::f64::consts::PI;
+use std::io::{self, ErrorKind};
+use image::io::Reader;
+use image::{DynamicImage, GrayImage};
+use log::debug;
+use crate::services::file_api::perceptual_hasher::PerceptualHasher;
+use crate::state::image_cluster::PerceptualHashAlgorithm;
+
+const PHASH_SIZE: u32 = 32;
+const HASH_SIDE: usize = 8;
+
+/// Decodes JPEG, PNG and WebP with the `image` crate and hashes a grayscale thumbnail.
+pub struct ImagePerceptualHasher {
+    algorithm: PerceptualHashAlgorithm,
+}
+
+impl ImagePerceptualHasher {
+    pub fn new(algorithm: PerceptualHashAlgorithm) -> Self {
+        ImagePerceptualHasher { algorithm }
+    }
+
+    fn thumbnail(image: &DynamicImage, width: u32, height: u32) -> GrayImage {
+        image.thumbnail_exact(width, height).to_luma8()
+    }
+
+    /// One bit per pixel of a 9x8 thumbnail, set when it's brighter than its right neighbour.
+    fn dhash(image: &DynamicImage) -> u64 {
+        let thumbnail = Self::thumbnail(image, HASH_SIDE as u32 + 1, HASH_SIDE as u32);
+        let mut hash = 0u64;
+        for y in 0..HASH_SIDE as u32 {
+            for x in 0..HASH_SIDE as u32 {
+                let bit = thumbnail.get_pixel(x, y)[0] > thumbnail.get_pixel(x + 1, y)[0];
+                hash = (hash << 1) | bit as u64;
+            }
+        }
+        hash
+    }
+
+    /// One bit per 8x8 lowest DCT frequencies of a 32x32 thumbnail, set when above their median.
+    fn phash(image: &DynamicImage) -> u64 {
+        let size = PHASH_SIZE as usize;
+        let thumbnail = Self::thumbnail(image, PHASH_SIZE, PHASH_SIZE);
+        let pixels: Vec<f64> = thumbnail.pixels().map(|p| p[0] as f64).collect();
+
+        // Separable DCT-II, only the frequencies the hash keeps are computed
+        let cosines: Vec<f64> = (0..HASH_SIDE * size)
+            .map(|i| ((2 * (i % size) + 1) as f64 * (i / size) as f64 * PI / (2 * size) as f64).cos())
+            .collect();
+        let mut rows = vec![0f64; size * HASH_SIDE];
+        for y in 0..size {
+            for u in 0..HASH_SIDE {
+                rows[y * HASH_SIDE + u] = (0..size).map(|x| pixels[y * size + x] * cosines[u * size + x]).sum();
+            }
+        }
+        let mut coefficients = vec![0f64; HASH_SIDE * HASH_SIDE];
+        for v in 0..HASH_SIDE {
+            for u in 0..HASH_SIDE {
+                coefficients[v * HASH_SIDE + u] = (0..size).map(|y| rows[y * HASH_SIDE + u] * cosines[v * size + y]).sum();
+            }
+        }
+
+        let mut sorted = coefficients.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let median = (sorted[sorted.len() / 2 - 1] + sorted[sorted.len() / 2]) / 2.0;
+        coefficients.iter().fold(0u64, |hash, &c| (hash << 1) | (c > median) as u64)
+    }
+}
+
+impl PerceptualHasher for ImagePerceptualHasher {
+    fn algorithm(&self) -> PerceptualHashAlgorithm {
+        self.algorithm
+    }
+
+    fn hash(&self, path: &str) -> io::Result<u64> {
+        debug!("Perceptual hashing {}", path);
+        let image = Reader::open(path)?
+            .with_guessed_format()?
+            .decode()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        Ok(match self.algorithm {
+            PerceptualHashAlgorithm::DHash => Self::dhash(&image),
+            PerceptualHashAlgorithm::PHash => Self::phash(&image),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use tempfile::tempdir;
+
+    fn landscape(width: u32, height: u32, flipped: bool) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let x = if flipped { width - 1 - x } else { x } as f64 / width as f64;
+            let y = y as f64 / height as f64;
+            let v = 128.0 + 60.0 * (x * 7.0).sin() + 40.0 * (y * 5.0).cos() + 20.0 * ((x + y) * 13.0).sin();
+            Rgb([v as u8, (v * 0.8) as u8, (255.0 - v) as u8])
+        })
+    }
+
+    #[test]
+    fn test_resized_copies_hash_close() {
+        let dir = tempdir().unwrap();
+        let original = dir.path().join("original.png");
+        let resized = dir.path().join("resized.jpg");
+        let other = dir.path().join("other.webp");
+        landscape(640, 480, false).save_with_format(&original, ImageFormat::Png).unwrap();
+        DynamicImage::ImageRgb8(landscape(640, 480, false)).thumbnail_exact(200, 150).to_rgb8().save_with_format(&resized, ImageFormat::Jpeg).unwrap();
+        landscape(640, 480, true).save_with_format(&other, ImageFormat::WebP).unwrap();
+
+        for algorithm in [PerceptualHashAlgorithm::DHash, PerceptualHashAlgorithm::PHash] {
+            let hasher = ImagePerceptualHasher::new(algorithm);
+            let h = |p: &std::path::Path| hasher.hash(&p.to_string_lossy()).unwrap();
+            assert!((h(&original) ^ h(&resized)).count_ones() <= 6, "{} resized copy too far", algorithm);
+            assert!((h(&original) ^ h(&other)).count_ones() > 16, "{} other image too close", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_undecodable() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("broken.jpg");
+        std::fs::write(&path, b"not an image").unwrap();
+
+        let err = ImagePerceptualHasher::new(PerceptualHashAlgorithm::DHash).hash(&path.to_string_lossy()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
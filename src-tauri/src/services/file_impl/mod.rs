@@ -1,4 +1,9 @@
 pub(crate) mod mime_guess_file_type_detector;
 pub(crate) mod mime_compression_checker;
 pub(crate) mod file_management_impl;
-pub(crate) mod zip_compressor;
\ No newline at end of file
+pub(crate) mod zip_compressor;
+pub(crate) mod streaming_fingerprint_service;
+pub(crate) mod hash_fingerprinters;
+pub(crate) mod streaming_content_comparator;
+pub(crate) mod image_perceptual_hasher;
+pub(crate) mod shingling_text_signature_service;
\ No newline at end of file
@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use log::debug;
+use xxhash_rust::xxh3::xxh3_128;
+use crate::services::file_api::text_signature_service::TextSignatureService;
+use crate::state::text_cluster::{TextSignature, TextSignatureAlgorithm};
+
+const MINHASH_PERMUTATIONS: usize = 128;
+const SHINGLE_WORDS: usize = 5;
+const BINARY_PROBE_BYTES: usize = 8 * 1024;
+
+/// Splits the text into overlapping shingles of consecutive words and signs their hashes.
+/// Whitespace is normalized, so reflowed text signs the same. Files with a NUL byte near the
+/// start are taken for binary.
+pub struct ShinglingTextSignatureService {
+    algorithm: TextSignatureAlgorithm,
+}
+
+impl ShinglingTextSignatureService {
+    pub fn new(algorithm: TextSignatureAlgorithm) -> Self {
+        ShinglingTextSignatureService { algorithm }
+    }
+
+    fn shingle_hashes(text: &str) -> Vec<u128> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() <= SHINGLE_WORDS {
+            return if words.is_empty() { vec![] } else { vec![xxh3_128(words.join(" ").as_bytes())] };
+        }
+        words.windows(SHINGLE_WORDS).map(|w| xxh3_128(w.join(" ").as_bytes())).collect()
+    }
+
+    /// Permutation `i` hashes a shingle to `low + i * high`, cheap stand-ins for independent hashes.
+    fn minhash(shingles: &[u128]) -> TextSignature {
+        let mut minimums = vec![u64::MAX; MINHASH_PERMUTATIONS];
+        for &shingle in shingles {
+            let (low, high) = (shingle as u64, (shingle >> 64) as u64 | 1);
+            for (i, minimum) in minimums.iter_mut().enumerate() {
+                *minimum = (*minimum).min(low.wrapping_add((i as u64).wrapping_mul(high)));
+            }
+        }
+        TextSignature::MinHash(minimums)
+    }
+
+    fn simhash(shingles: &[u128]) -> TextSignature {
+        let mut weights = [0i64; 64];
+        for &shingle in shingles {
+            for (bit, weight) in weights.iter_mut().enumerate() {
+                *weight += if (shingle >> bit) & 1 == 1 { 1 } else { -1 };
+            }
+        }
+        TextSignature::SimHash(weights.iter().enumerate().fold(0u64, |hash, (bit, &w)| hash | (((w > 0) as u64) << bit)))
+    }
+}
+
+impl TextSignatureService for ShinglingTextSignatureService {
+    fn algorithm(&self) -> TextSignatureAlgorithm {
+        self.algorithm
+    }
+
+    fn signature(&self, path: &str) -> io::Result<TextSignature> {
+        debug!("Signing text of {}", path);
+        let bytes = fs::read(path)?;
+        if bytes[..bytes.len().min(BINARY_PROBE_BYTES)].contains(&0) {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("{} is binary", path)));
+        }
+
+        let shingles = Self::shingle_hashes(&String::from_utf8_lossy(&bytes));
+        Ok(match self.algorithm {
+            TextSignatureAlgorithm::MinHash => Self::minhash(&shingles),
+            TextSignatureAlgorithm::SimHash => Self::simhash(&shingles),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn lines(count: usize, edited: &[usize], salt: &str) -> String {
+        (0..count)
+            .map(|i| if edited.contains(&i) { format!("edited line {} {}\n", i, salt) } else { format!("line {} value {} {}\n", i, i * 7, salt) })
+            .collect()
+    }
+
+    #[test]
+    fn test_similarity() {
+        let dir = tempdir().unwrap();
+        let write = |name: &str, content: String| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let original = write("original.log", lines(300, &[], "ok"));
+        let edited = write("edited.log", lines(300, &[10, 100, 200], "ok"));
+        let reflowed = write("reflowed.log", lines(300, &[], "ok").replace('\n', "  "));
+        let unrelated = write("unrelated.log", lines(300, &[], "other").chars().rev().collect());
+
+        for (algorithm, unrelated_below) in [(TextSignatureAlgorithm::MinHash, 0.3), (TextSignatureAlgorithm::SimHash, 0.8)] {
+            let service = ShinglingTextSignatureService::new(algorithm);
+            let signature = |p: &str| service.signature(p).unwrap();
+            let base = signature(&original);
+
+            assert_eq!(base.similarity(&signature(&reflowed)), 1.0, "{}", algorithm);
+            assert!(base.similarity(&signature(&edited)) > 0.85, "{} edited", algorithm);
+            assert!(base.similarity(&signature(&unrelated)) < unrelated_below, "{} unrelated", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_binary() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("binary.txt");
+        fs::write(&path, b"text\0more").unwrap();
+
+        let err = ShinglingTextSignatureService::new(TextSignatureAlgorithm::MinHash).signature(&path.to_string_lossy()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use log::debug;
+use crate::services::file_api::content_comparator::ContentComparator;
+use crate::util::cancellation_token::CancellationToken;
+
+const DEFAULT_BUFFER_BYTES: usize = 1024 * 1024;
+
+/// Streams both files through fixed size buffers and stops at the first differing chunk.
+pub struct StreamingContentComparator {
+    buffer_bytes: usize,
+    cancellation: CancellationToken,
+}
+
+impl Default for StreamingContentComparator {
+    fn default() -> Self {
+        StreamingContentComparator::new(DEFAULT_BUFFER_BYTES, CancellationToken::new())
+    }
+}
+
+impl StreamingContentComparator {
+    pub fn new(buffer_bytes: usize, cancellation: CancellationToken) -> Self {
+        StreamingContentComparator { buffer_bytes: buffer_bytes.max(1), cancellation }
+    }
+}
+
+/// Reads until `buffer` is full or the file ends, returning the bytes read.
+fn fill(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+impl ContentComparator for StreamingContentComparator {
+    fn identical(&self, a: &str, b: &str, progress: &mut dyn FnMut(u64)) -> io::Result<bool> {
+        debug!("Comparing {} with {}", a, b);
+        let mut file_a = File::open(a)?;
+        let mut file_b = File::open(b)?;
+        if file_a.metadata()?.len() != file_b.metadata()?.len() {
+            return Ok(false);
+        }
+
+        let mut buffer_a = vec![0u8; self.buffer_bytes];
+        let mut buffer_b = vec![0u8; self.buffer_bytes];
+        let mut read = 0;
+        loop {
+            if self.cancellation.is_cancelled() {
+                return Err(io::Error::new(ErrorKind::Interrupted, "Comparison was cancelled"));
+            }
+
+            let n_a = fill(&mut file_a, &mut buffer_a)?;
+            let n_b = fill(&mut file_b, &mut buffer_b)?;
+            read += (n_a + n_b) as u64;
+            progress(read);
+            if buffer_a[..n_a] != buffer_b[..n_b] {
+                return Ok(false);
+            }
+            if n_a == 0 {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_identical() {
+        let dir = tempdir().unwrap();
+        let write = |name: &str, content: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let a = write("a", b"0123456789");
+        let b = write("b", b"0123456789");
+        let c = write("c", b"0123456788");
+        let d = write("d", b"012345678");
+
+        let comparator = StreamingContentComparator::new(4, CancellationToken::new());
+
+        let mut read = 0;
+        assert!(comparator.identical(&a, &b, &mut |r| read = r).unwrap());
+        assert_eq!(read, 20);
+        assert!(!comparator.identical(&a, &c, &mut |_| {}).unwrap());
+        assert!(!comparator.identical(&a, &d, &mut |_| {}).unwrap());
+        assert!(comparator.identical(&a, "/does/not/exist", &mut |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a");
+        fs::write(&path, b"content").unwrap();
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+
+        let p = path.to_string_lossy();
+        let err = StreamingContentComparator::new(4, cancellation).identical(&p, &p, &mut |_| {}).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+    }
+}
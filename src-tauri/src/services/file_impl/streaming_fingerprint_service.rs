@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use log::debug;
+use crate::services::file_api::fingerprint_service::{FingerprintError, FingerprintService};
+use crate::services::file_api::fingerprinter::Fingerprinter;
+use crate::services::file_impl::hash_fingerprinters::new_fingerprinter;
+use crate::state::fingerprint::{Fingerprint, FingerprintAlgorithm};
+use crate::util::cancellation_token::CancellationToken;
+
+const DEFAULT_BUFFER_BYTES: usize = 64 * 1024;
+const DEFAULT_PROGRESS_INTERVAL_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Hashes files with `algorithm` through a fixed size buffer, so memory use doesn't depend on
+/// file size. The cancellation token is checked between reads and progress is reported every
+/// `progress_interval_bytes` and once the file is done.
+pub struct StreamingFingerprintService {
+    algorithm: FingerprintAlgorithm,
+    buffer_bytes: usize,
+    progress_interval_bytes: u64,
+    cancellation: CancellationToken,
+}
+
+impl Default for StreamingFingerprintService {
+    fn default() -> Self {
+        StreamingFingerprintService::new(FingerprintAlgorithm::default(), DEFAULT_BUFFER_BYTES, DEFAULT_PROGRESS_INTERVAL_BYTES, CancellationToken::new())
+    }
+}
+
+impl StreamingFingerprintService {
+    pub fn new(algorithm: FingerprintAlgorithm, buffer_bytes: usize, progress_interval_bytes: u64, cancellation: CancellationToken) -> Self {
+        StreamingFingerprintService { algorithm, buffer_bytes: buffer_bytes.max(1), progress_interval_bytes: progress_interval_bytes.max(1), cancellation }
+    }
+
+    /// Feeds up to `len` bytes from the file's current position to the hasher, returning how many
+    /// were read.
+    fn stream(&self, file: &mut File, hasher: &mut dyn Fingerprinter, len: u64, buffer: &mut [u8], on_read: &mut dyn FnMut(u64)) -> Result<u64, FingerprintError> {
+        let mut read = 0;
+        while read < len {
+            if self.cancellation.is_cancelled() {
+                return Err(FingerprintError::Cancelled);
+            }
+
+            let want = (len - read).min(buffer.len() as u64) as usize;
+            let n = file.read(&mut buffer[..want])?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            read += n as u64;
+            on_read(n as u64);
+        }
+        Ok(read)
+    }
+}
+
+impl FingerprintService for StreamingFingerprintService {
+    fn algorithm(&self) -> FingerprintAlgorithm {
+        self.algorithm
+    }
+
+    fn fingerprint(&self, path: &str, progress: &mut dyn FnMut(u64, u64)) -> Result<Fingerprint, FingerprintError> {
+        debug!("Fingerprinting {}", path);
+        let mut file = File::open(path)?;
+        let total_bytes = file.metadata()?.len();
+        let mut hasher = new_fingerprinter(self.algorithm);
+        let mut buffer = vec![0u8; self.buffer_bytes];
+
+        let mut hashed_bytes = 0;
+        let mut next_report = self.progress_interval_bytes;
+        self.stream(&mut file, hasher.as_mut(), u64::MAX, &mut buffer, &mut |n| {
+            hashed_bytes += n;
+            if hashed_bytes >= next_report {
+                progress(hashed_bytes, total_bytes);
+                next_report = hashed_bytes + self.progress_interval_bytes;
+            }
+        })?;
+        progress(hashed_bytes, total_bytes);
+
+        Ok(hasher.finish())
+    }
+
+    fn fingerprint_ranges(&self, path: &str, ranges: &[(u64, u64)]) -> Result<Fingerprint, FingerprintError> {
+        debug!("Fingerprinting {} ranges of {}", ranges.len(), path);
+        let mut file = File::open(path)?;
+        let mut hasher = new_fingerprinter(self.algorithm);
+        let mut buffer = vec![0u8; self.buffer_bytes];
+
+        for &(offset, len) in ranges {
+            file.seek(SeekFrom::Start(offset))?;
+            self.stream(&mut file, hasher.as_mut(), len, &mut buffer, &mut |_| {})?;
+        }
+        Ok(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+    use xxhash_rust::xxh3::xxh3_64;
+
+    fn xxh3(bytes: &[u8]) -> Fingerprint {
+        Fingerprint::new(FingerprintAlgorithm::Xxh3_64, xxh3_64(bytes).to_be_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_fingerprint_streams_whole_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("f");
+        let content: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        fs::write(&path, &content).unwrap();
+
+        let service = StreamingFingerprintService::new(FingerprintAlgorithm::Xxh3_64, 64, 300, CancellationToken::new());
+        let mut reports = Vec::new();
+        let fingerprint = service.fingerprint(&path.to_string_lossy(), &mut |hashed, total| reports.push((hashed, total))).unwrap();
+
+        assert_eq!(fingerprint, xxh3(&content));
+        assert_eq!(reports, vec![(320, 1000), (640, 1000), (960, 1000), (1000, 1000)]);
+    }
+
+    #[test]
+    fn test_fingerprint_ranges() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("f");
+        fs::write(&path, b"0123456789").unwrap();
+
+        let service = StreamingFingerprintService::new(FingerprintAlgorithm::Xxh3_64, 3, 1, CancellationToken::new());
+        let fingerprint = service.fingerprint_ranges(&path.to_string_lossy(), &[(0, 4), (6, 4)]).unwrap();
+
+        assert_eq!(fingerprint, xxh3(b"01236789"));
+    }
+
+    #[test]
+    fn test_algorithm() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("f");
+        fs::write(&path, b"abc").unwrap();
+
+        let service = StreamingFingerprintService::new(FingerprintAlgorithm::Sha256, 2, 1, CancellationToken::new());
+        let fingerprint = service.fingerprint(&path.to_string_lossy(), &mut |_, _| {}).unwrap();
+
+        assert_eq!(fingerprint.to_string(), "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn test_missing_file_is_an_error() {
+        let result = StreamingFingerprintService::default().fingerprint("/does/not/exist", &mut |_, _| {});
+        assert!(matches!(result, Err(FingerprintError::Io(_))));
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("f");
+        fs::write(&path, b"content").unwrap();
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+
+        let result = StreamingFingerprintService::new(FingerprintAlgorithm::Xxh3_64, 4, 4, cancellation).fingerprint(&path.to_string_lossy(), &mut |_, _| {});
+        assert!(matches!(result, Err(FingerprintError::Cancelled)));
+    }
+}
@@ -2,3 +2,5 @@ pub mod scanner_api;
 pub mod scanner_impl;
 pub mod file_api;
 pub mod file_impl;
+pub mod registry_api;
+pub mod registry_impl;
@@ -0,0 +1,2 @@
+pub(crate) mod snapshot_store;
+pub(crate) mod registry_codec;
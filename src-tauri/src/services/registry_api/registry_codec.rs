@@ -0,0 +1,11 @@
+use std::io;
+use crate::state::registry::Registry;
+
+/// Converts registry entries to and from an external dump format.
+pub trait RegistryCodec {
+    /// Writes every entry under `root` to `writer`.
+    fn export(&self, registry: &Registry, root: &str, writer: &mut dyn io::Write) -> io::Result<()>;
+
+    /// Reads a dump, returning its root path and the entries it describes.
+    fn import(&self, reader: &mut dyn io::Read) -> io::Result<(String, Registry)>;
+}
@@ -0,0 +1,12 @@
+use std::io;
+use crate::state::registry_snapshot::RegistrySnapshot;
+
+pub trait SnapshotStore {
+    /// Persists a snapshot, pruning the oldest snapshots beyond the store's retention limit.
+    fn save(&self, snapshot: &RegistrySnapshot) -> io::Result<()>;
+
+    /// Snapshot ids, oldest first.
+    fn list(&self) -> io::Result<Vec<String>>;
+
+    fn load(&self, id: &str) -> io::Result<RegistrySnapshot>;
+}
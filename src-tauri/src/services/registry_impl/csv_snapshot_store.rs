@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind};
+use std::path::PathBuf;
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use log::{info, warn};
+use crate::services::registry_api::snapshot_store::SnapshotStore;
+use crate::state::registry_snapshot::{DirectoryRollup, RegistrySnapshot, SnapshotEntry};
+
+const SNAPSHOT_EXTENSION: &str = "csv";
+
+/// Stores each snapshot as a headerless CSV file named after the snapshot id. Rows are tagged
+/// with their kind: a single `meta` row, then `rollup` and `entry` rows.
+pub struct CsvSnapshotStore {
+    dir: PathBuf,
+    max_snapshots: usize,
+}
+
+impl CsvSnapshotStore {
+    pub fn new(dir: PathBuf, max_snapshots: usize) -> Self {
+        CsvSnapshotStore { dir, max_snapshots }
+    }
+
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, SNAPSHOT_EXTENSION))
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let ids = self.list()?;
+        if ids.len() > self.max_snapshots {
+            for id in &ids[..ids.len() - self.max_snapshots] {
+                info!("Pruning snapshot {}", id);
+                fs::remove_file(self.snapshot_path(id))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn field<'a>(record: &'a StringRecord, i: usize, name: &str) -> io::Result<&'a str> {
+    record.get(i).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("Missing {} in snapshot record", name)))
+}
+
+fn parse<T: std::str::FromStr>(record: &StringRecord, i: usize, name: &str) -> io::Result<T> {
+    field(record, i, name)?
+        .parse::<T>()
+        .map_err(|_| io::Error::new(ErrorKind::InvalidData, format!("Invalid {} in snapshot record", name)))
+}
+
+impl SnapshotStore for CsvSnapshotStore {
+    fn save(&self, snapshot: &RegistrySnapshot) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let mut writer = WriterBuilder::new().flexible(true).from_path(self.snapshot_path(snapshot.id()))?;
+        writer.write_record(["meta", snapshot.id(), &snapshot.timestamp().to_string(), snapshot.root()])?;
+
+        for (path, r) in snapshot.rollups() {
+            writer.write_record(["rollup", path, &r.size_bytes.to_string(), &r.file_count.to_string(), &r.dir_count.to_string()])?;
+        }
+
+        for (path, e) in snapshot.entries() {
+            writer.write_record(["entry", path, &e.is_dir.to_string(), &e.modified.to_string(), &e.size_bytes.to_string()])?;
+        }
+
+        writer.flush()?;
+        info!("Persisted snapshot {} rollups={} entries={}", snapshot.id(), snapshot.rollups().len(), snapshot.entries().len());
+
+        self.prune()
+    }
+
+    fn list(&self) -> io::Result<Vec<String>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut ids: Vec<String> = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == SNAPSHOT_EXTENSION))
+            .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+            .collect();
+
+        // Ids are timestamp based so lexical order is chronological order
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn load(&self, id: &str) -> io::Result<RegistrySnapshot> {
+        let file = File::open(self.snapshot_path(id))?;
+        let mut reader = ReaderBuilder::new().has_headers(false).flexible(true).from_reader(BufReader::new(file));
+
+        let mut meta: Option<(String, i64, String)> = None;
+        let mut rollups = HashMap::new();
+        let mut entries = HashMap::new();
+
+        for record in reader.records() {
+            let record = record?;
+            match field(&record, 0, "kind")? {
+                "meta" => {
+                    meta = Some((field(&record, 1, "id")?.to_string(), parse(&record, 2, "timestamp")?, field(&record, 3, "root")?.to_string()));
+                }
+                "rollup" => {
+                    rollups.insert(field(&record, 1, "path")?.to_string(), DirectoryRollup {
+                        size_bytes: parse(&record, 2, "size_bytes")?,
+                        file_count: parse(&record, 3, "file_count")?,
+                        dir_count: parse(&record, 4, "dir_count")?,
+                    });
+                }
+                "entry" => {
+                    entries.insert(field(&record, 1, "path")?.to_string(), SnapshotEntry {
+                        is_dir: parse(&record, 2, "is_dir")?,
+                        modified: parse(&record, 3, "modified")?,
+                        size_bytes: parse(&record, 4, "size_bytes")?,
+                    });
+                }
+                kind => warn!("Ignoring unknown snapshot record kind {}", kind),
+            }
+        }
+
+        let (id, timestamp, root) = meta.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Snapshot is missing its meta record"))?;
+        Ok(RegistrySnapshot::new(&id, timestamp, &root, rollups, entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use crate::state::registry::Registry;
+    use crate::state::resource_metadata::ResourceMetadata;
+
+    fn snapshot(id: &str, timestamp: i64) -> RegistrySnapshot {
+        let mut registry = Registry::new();
+        for (p, is_dir, size) in [("/a", true, 0), ("/a/f", false, 42)] {
+            registry.insert(&ResourceMetadata::new(&p.to_string(), is_dir, false, 7, size, false));
+        }
+        RegistrySnapshot::capture(id, timestamp, "/a", &registry, Some(0))
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = tempdir().unwrap();
+        let store = CsvSnapshotStore::new(dir.path().join("snapshots"), 10);
+        let original = snapshot("20240101T000000", 1);
+
+        store.save(&original).unwrap();
+        let loaded = store.load("20240101T000000").unwrap();
+
+        assert_eq!(loaded, original);
+        assert_eq!(store.list().unwrap(), vec!["20240101T000000".to_string()]);
+    }
+
+    #[test]
+    fn test_retention_prunes_oldest() {
+        let dir = tempdir().unwrap();
+        let store = CsvSnapshotStore::new(dir.path().to_path_buf(), 2);
+
+        store.save(&snapshot("20240101T000000", 1)).unwrap();
+        store.save(&snapshot("20240102T000000", 2)).unwrap();
+        store.save(&snapshot("20240103T000000", 3)).unwrap();
+
+        assert_eq!(store.list().unwrap(), vec!["20240102T000000".to_string(), "20240103T000000".to_string()]);
+    }
+
+    #[test]
+    fn test_load_missing_snapshot() {
+        let dir = tempdir().unwrap();
+        let store = CsvSnapshotStore::new(dir.path().to_path_buf(), 2);
+
+        assert!(store.load("nope").is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::io;
+use log::warn;
+use crate::services::file_api::fingerprint_service::{FingerprintError, FingerprintService};
+use crate::state::registry::Registry;
+
+/// Writes a `<hex digest>  <path>` line per file under `root`, the format `sha256sum`, `b3sum`
+/// and friends read with `-c`. Fingerprints already in the registry for the service's algorithm
+/// are reused, new ones are recorded in the registry. Files that can't be read are skipped.
+/// Returns the number of files written.
+pub(crate) fn write_manifest(registry: &mut Registry, root: &str, fingerprint_service: &dyn FingerprintService, writer: &mut dyn io::Write) -> io::Result<usize> {
+    let mut written = 0;
+
+    for id in registry.walk(root) {
+        let metadata = match registry.get_by_id(id) {
+            Some(m) if !m.is_dir() && !m.is_symlink() => m,
+            _ => continue,
+        };
+
+        let fingerprint = match metadata.fingerprint().filter(|f| f.algorithm() == fingerprint_service.algorithm()) {
+            Some(f) => f.clone(),
+            None => match fingerprint_service.fingerprint(metadata.get_path(), &mut |_, _| {}) {
+                Ok(f) => {
+                    registry.set_fingerprint(metadata.get_path(), f.clone());
+                    f
+                }
+                Err(FingerprintError::Cancelled) => return Err(io::Error::new(io::ErrorKind::Interrupted, FingerprintError::Cancelled.to_string())),
+                Err(e) => {
+                    warn!("Leaving {} out of the manifest: {}", metadata.get_path(), e);
+                    continue;
+                }
+            },
+        };
+
+        writeln!(writer, "{}  {}", fingerprint.hex(), metadata.get_path())?;
+        written += 1;
+    }
+
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+    use crate::services::file_impl::streaming_fingerprint_service::StreamingFingerprintService;
+    use crate::state::fingerprint::{Fingerprint, FingerprintAlgorithm};
+    use crate::state::resource_metadata::ResourceMetadata;
+    use crate::util::cancellation_token::CancellationToken;
+
+    #[test]
+    fn test_write_manifest() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let hashed = format!("{}/hashed", root);
+        let known = format!("{}/known", root);
+        fs::write(&hashed, b"abc").unwrap();
+        fs::write(&known, b"whatever").unwrap();
+
+        let known_fingerprint: Fingerprint = "sha256:00ff".parse().unwrap();
+        let mut registry = Registry::new();
+        registry.insert(&ResourceMetadata::new(&root, true, false, 0, 0, false));
+        registry.insert(&ResourceMetadata::new(&hashed, false, false, 0, 3, false));
+        registry.insert(&ResourceMetadata::new(&known, false, false, 0, 8, false).with_fingerprint(Some(known_fingerprint)));
+        registry.insert(&ResourceMetadata::new(&format!("{}/gone", root), false, false, 0, 1, false));
+
+        let service = StreamingFingerprintService::new(FingerprintAlgorithm::Sha256, 1024, 1024, CancellationToken::new());
+        let mut out = Vec::new();
+        let written = write_manifest(&mut registry, &root, &service, &mut out).unwrap();
+
+        assert_eq!(written, 2);
+        assert_eq!(String::from_utf8(out).unwrap(), format!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  {}\n00ff  {}\n", hashed, known));
+        assert_eq!(registry.get(&hashed).unwrap().fingerprint().unwrap().algorithm(), FingerprintAlgorithm::Sha256);
+    }
+}
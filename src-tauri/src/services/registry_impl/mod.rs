@@ -0,0 +1,5 @@
+pub(crate) mod csv_snapshot_store;
+pub(crate) mod fingerprint_manifest;
+pub(crate) mod ncdu_registry_codec;
+pub(crate) mod registry_lock;
+pub(crate) mod registry_verifier;
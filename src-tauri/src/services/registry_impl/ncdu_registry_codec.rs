@@ -0,0 +1,211 @@
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use chrono::Utc;
+use serde_json::{json, Map, Value};
+use crate::services::registry_api::registry_codec::RegistryCodec;
+use crate::state::registry::Registry;
+use crate::state::resource_metadata::{Ownership, ResourceMetadata};
+
+const NCDU_MAJOR_VERSION: u64 = 1;
+const NCDU_MINOR_VERSION: u64 = 2;
+
+/// Reads and writes the ncdu JSON dump format (`ncdu -o` / `ncdu -f`). A directory is an array
+/// whose first element describes the directory and whose remaining elements are its children;
+/// files are plain objects. The registry doesn't track disk usage so `dsize` mirrors `asize`,
+/// and symlinks round trip through ncdu's `notreg` flag. Known owners and modes go into the
+/// `uid`, `gid` and `mode` fields of ncdu's extended mode.
+pub struct NcduRegistryCodec;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+impl NcduRegistryCodec {
+    fn info(name: &str, metadata: &ResourceMetadata) -> Value {
+        let mut info = Map::new();
+        info.insert("name".to_string(), json!(name));
+        info.insert("asize".to_string(), json!(metadata.size_bytes()));
+        info.insert("dsize".to_string(), json!(metadata.size_bytes()));
+        info.insert("mtime".to_string(), json!(metadata.modified()));
+        if metadata.is_symlink() {
+            info.insert("notreg".to_string(), json!(true));
+        }
+        if let Some(ownership) = metadata.ownership() {
+            info.insert("uid".to_string(), json!(ownership.uid));
+            info.insert("gid".to_string(), json!(ownership.gid));
+            info.insert("mode".to_string(), json!(ownership.mode));
+        }
+        Value::Object(info)
+    }
+
+    fn export_node(registry: &Registry, path: &str, name: &str, writer: &mut dyn io::Write) -> io::Result<()> {
+        let metadata = match registry.get(path) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+
+        let info = serde_json::to_string(&Self::info(name, &metadata))?;
+        if !metadata.is_dir() || metadata.is_symlink() {
+            return write!(writer, "{}", info);
+        }
+
+        write!(writer, "[{}", info)?;
+        for child in registry.children(path) {
+            let child_name = Path::new(&child).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            write!(writer, ",")?;
+            Self::export_node(registry, &child, &child_name, writer)?;
+        }
+        write!(writer, "]")
+    }
+
+    fn import_node(value: &Value, parent: Option<&str>, registry: &mut Registry) -> io::Result<()> {
+        let (info, children, is_dir) = match value {
+            Value::Array(items) => {
+                let info = items.first().ok_or_else(|| invalid("Empty ncdu directory entry"))?;
+                (info, &items[1..], true)
+            }
+            Value::Object(_) => (value, &[][..], false),
+            _ => return Err(invalid("Unexpected ncdu entry")),
+        };
+
+        let name = info.get("name").and_then(Value::as_str).ok_or_else(|| invalid("ncdu entry is missing a name"))?;
+        let path = match parent {
+            // Anything but a plain file name would put the entry outside its parent
+            Some(_) if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\0') => {
+                return Err(invalid(&format!("Invalid ncdu entry name {:?}", name)));
+            }
+            Some(p) => Path::new(p).join(name).to_string_lossy().to_string(),
+            None => name.to_string(),
+        };
+
+        let size_bytes = info.get("asize").and_then(Value::as_u64).unwrap_or(0);
+        let modified = info.get("mtime").and_then(Value::as_i64).unwrap_or(0);
+        let is_symlink = info.get("notreg").and_then(Value::as_bool).unwrap_or(false);
+
+        let id = |key: &str| info.get(key).and_then(Value::as_u64).and_then(|v| u32::try_from(v).ok());
+        let ownership = match (id("uid"), id("gid"), id("mode")) {
+            (Some(uid), Some(gid), Some(mode)) => Some(Ownership { uid, gid, mode }),
+            _ => None,
+        };
+
+        registry.insert(&ResourceMetadata::new(&path, is_dir, is_symlink, modified, size_bytes, false).with_ownership(ownership));
+
+        for child in children {
+            Self::import_node(child, Some(&path), registry)?;
+        }
+        Ok(())
+    }
+}
+
+impl RegistryCodec for NcduRegistryCodec {
+    fn export(&self, registry: &Registry, root: &str, writer: &mut dyn io::Write) -> io::Result<()> {
+        if !registry.contains_key(root) {
+            return Err(io::Error::new(ErrorKind::NotFound, format!("{} is not in the registry", root)));
+        }
+
+        let header = json!({"progname": "tt", "progver": env!("CARGO_PKG_VERSION"), "timestamp": Utc::now().timestamp()});
+        write!(writer, "[{},{},{},", NCDU_MAJOR_VERSION, NCDU_MINOR_VERSION, header)?;
+
+        Self::export_node(registry, root, root, writer)?;
+
+        write!(writer, "]")?;
+        writer.flush()
+    }
+
+    fn import(&self, reader: &mut dyn io::Read) -> io::Result<(String, Registry)> {
+        let dump: Value = serde_json::from_reader(reader)?;
+        let items = dump.as_array().ok_or_else(|| invalid("ncdu dump must be a JSON array"))?;
+
+        match items.first().and_then(Value::as_u64) {
+            Some(NCDU_MAJOR_VERSION) => {}
+            _ => return Err(invalid("Unsupported ncdu dump version")),
+        }
+
+        let root = items.get(3).ok_or_else(|| invalid("ncdu dump has no root directory"))?;
+        let mut registry = Registry::new();
+        Self::import_node(root, None, &mut registry)?;
+
+        let root_path = match root {
+            Value::Array(r) => r.first().and_then(|i| i.get("name")).and_then(Value::as_str),
+            _ => root.get("name").and_then(Value::as_str),
+        }.ok_or_else(|| invalid("ncdu root is missing a name"))?;
+
+        Ok((root_path.to_string(), registry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = r#"[1,2,{"progname":"ncdu","progver":"1.19","timestamp":1700000000},
+        [{"name":"/data","asize":4096,"dsize":4096,"mtime":10},
+            {"name":"a.txt","asize":100,"dsize":4096,"mtime":11},
+            [{"name":"sub","asize":4096,"mtime":12},
+                {"name":"b.bin","asize":2000,"mtime":13,"uid":1000,"gid":100,"mode":33188},
+                {"name":"link","asize":10,"notreg":true}
+            ]
+        ]]"#;
+
+    fn metadata(path: &str, is_dir: bool, is_symlink: bool, modified: i64, size: u64) -> ResourceMetadata {
+        ResourceMetadata::new(&path.to_string(), is_dir, is_symlink, modified, size, false)
+    }
+
+    #[test]
+    fn test_import() {
+        let (root, registry) = NcduRegistryCodec.import(&mut DUMP.as_bytes()).unwrap();
+
+        assert_eq!(root, "/data");
+        assert_eq!(registry.len(), 5);
+        assert_eq!(registry.get("/data/a.txt"), Some(metadata("/data/a.txt", false, false, 11, 100)));
+        assert_eq!(registry.get("/data/sub"), Some(metadata("/data/sub", true, false, 12, 4096)));
+        assert!(registry.get("/data/sub/link").unwrap().is_symlink());
+        assert_eq!(registry.get("/data/sub/b.bin").unwrap().ownership(), Some(Ownership { uid: 1000, gid: 100, mode: 0o100644 }));
+        assert_eq!(registry.get("/data/a.txt").unwrap().ownership(), None);
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let (_, registry) = NcduRegistryCodec.import(&mut DUMP.as_bytes()).unwrap();
+
+        let mut out = Vec::new();
+        NcduRegistryCodec.export(&registry, "/data", &mut out).unwrap();
+        let (root, reimported) = NcduRegistryCodec.import(&mut out.as_slice()).unwrap();
+
+        assert_eq!(root, "/data");
+        let mut expected: Vec<ResourceMetadata> = registry.iter().collect();
+        let mut actual: Vec<ResourceMetadata> = reimported.iter().collect();
+        expected.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+        actual.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_export_only_includes_root_subtree() {
+        let mut registry = Registry::new();
+        for m in [metadata("/a", true, false, 0, 0), metadata("/a/f", false, false, 0, 1), metadata("/b", true, false, 0, 0)] {
+            registry.insert(&m);
+        }
+
+        let mut out = Vec::new();
+        NcduRegistryCodec.export(&registry, "/a", &mut out).unwrap();
+        let (_, reimported) = NcduRegistryCodec.import(&mut out.as_slice()).unwrap();
+
+        assert_eq!(reimported.len(), 2);
+        assert!(!reimported.contains_key("/b"));
+    }
+
+    #[test]
+    fn test_import_rejects_names_leaving_their_parent() {
+        for name in ["/etc/passwd", "..", ".", "a/../../b", ""] {
+            let dump = format!(r#"[1,2,{{}},[{{"name":"/data"}},{{"name":{:?}}}]]"#, name);
+            assert!(NcduRegistryCodec.import(&mut dump.as_bytes()).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_import_rejects_unknown_version() {
+        assert!(NcduRegistryCodec.import(&mut r#"[2,0,{},[{"name":"/"}]]"#.as_bytes()).is_err());
+        assert!(NcduRegistryCodec.import(&mut r#"{"name":"/"}"#.as_bytes()).is_err());
+    }
+}
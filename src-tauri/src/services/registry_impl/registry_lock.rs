@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    // Lock files held by this process, mapped to the owner that holds them
+    static ref HELD_LOCKS: Mutex<HashMap<PathBuf, String>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LockInfo {
+    pid: u32,
+    owner: String,
+    created: i64,
+}
+
+impl LockInfo {
+    fn parse(content: &str) -> Option<LockInfo> {
+        let mut pid = None;
+        let mut owner = None;
+        let mut created = None;
+        for line in content.lines() {
+            match line.split_once('=') {
+                Some(("pid", v)) => pid = v.parse().ok(),
+                Some(("owner", v)) => owner = Some(v.to_string()),
+                Some(("created", v)) => created = v.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(LockInfo { pid: pid?, owner: owner?, created: created? })
+    }
+}
+
+/// Exclusive `flock` without waiting, false when another open file holds it.
+fn try_flock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(e),
+    }
+}
+
+/// Advisory lock guarding a registry file for a whole load, scan, save cycle.
+///
+/// Across processes the lock is an exclusive `flock` on a `<registry>.lock` file, which records
+/// the holder's pid, owner and creation time for the error of whoever waits on it. The kernel
+/// drops the lock with the process holding it, so a lock is never broken while its holder runs
+/// and a lock file left behind by a crash is simply locked again. Within the process a second
+/// owner is rejected immediately rather than waiting on its own lock. The lock is released on
+/// drop.
+#[derive(Debug)]
+pub struct RegistryLock {
+    path: PathBuf,
+    // Holds the flock, closing it releases the lock too
+    file: File,
+}
+
+impl RegistryLock {
+    pub fn lock_path(registry_path: &Path) -> PathBuf {
+        let mut name = registry_path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+        name.push(".lock");
+        registry_path.with_file_name(name)
+    }
+
+    /// Acquires the lock for `registry_path`, waiting up to `timeout` for another process to
+    /// release it. Blocks while it waits.
+    pub fn acquire(registry_path: &Path, owner: &str, timeout: Duration) -> io::Result<RegistryLock> {
+        let path = Self::lock_path(registry_path);
+
+        {
+            let mut held = HELD_LOCKS.lock().unwrap();
+            if let Some(holder) = held.get(&path) {
+                return Err(io::Error::new(ErrorKind::WouldBlock, format!("Registry is in use by {}", holder)));
+            }
+            held.insert(path.clone(), owner.to_string());
+        }
+
+        match Self::acquire_file(&path, owner, timeout) {
+            Ok(file) => {
+                info!("[{}] acquired registry lock {}", owner, path.to_string_lossy());
+                Ok(RegistryLock { path, file })
+            }
+            Err(e) => {
+                HELD_LOCKS.lock().unwrap().remove(&path);
+                Err(e)
+            }
+        }
+    }
+
+    fn acquire_file(path: &Path, owner: &str, timeout: Duration) -> io::Result<File> {
+        let start = Instant::now();
+        loop {
+            let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+            if try_flock(&file)? {
+                // A holder releasing the lock removes the file, the one locked may be gone already
+                if !Self::is_current(path, &file)? {
+                    continue;
+                }
+                file.set_len(0)?;
+                write!(file, "pid={}\nowner={}\ncreated={}\n", std::process::id(), owner, Utc::now().timestamp())?;
+                file.sync_all()?;
+                return Ok(file);
+            }
+
+            if start.elapsed() >= timeout {
+                let mut content = String::new();
+                file.seek(SeekFrom::Start(0))?;
+                file.read_to_string(&mut content).unwrap_or_default();
+                let holder = LockInfo::parse(&content)
+                    .map(|h| format!("{} (pid {}) since {}", h.owner, h.pid, h.created))
+                    .unwrap_or_else(|| "unknown".to_string());
+                return Err(io::Error::new(ErrorKind::WouldBlock, format!("Registry is locked by {}", holder)));
+            }
+
+            debug!("Waiting for registry lock {}", path.to_string_lossy());
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Whether `file` is still the lock file at `path`.
+    fn is_current(path: &Path, file: &File) -> io::Result<bool> {
+        let locked = file.metadata()?;
+        match fs::metadata(path) {
+            Ok(current) => Ok(current.dev() == locked.dev() && current.ino() == locked.ino()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for RegistryLock {
+    fn drop(&mut self) {
+        // Removed while still locked so waiters notice they locked a file that is gone
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to release registry lock {}: {}", self.path.to_string_lossy(), e);
+        }
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+        HELD_LOCKS.lock().unwrap().remove(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const TIMEOUT: Duration = Duration::from_millis(300);
+
+    // Another process holding the lock, an flock through a separate open file conflicts even
+    // within the process
+    fn hold_lock(registry: &Path, content: &str) -> File {
+        let lock_path = RegistryLock::lock_path(registry);
+        fs::write(&lock_path, content).unwrap();
+        let file = File::open(&lock_path).unwrap();
+        assert!(try_flock(&file).unwrap());
+        file
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = tempdir().unwrap();
+        let registry = dir.path().join("output.csv");
+
+        let lock = RegistryLock::acquire(&registry, "scan-1", TIMEOUT).unwrap();
+        assert!(RegistryLock::lock_path(&registry).exists());
+
+        drop(lock);
+        assert!(!RegistryLock::lock_path(&registry).exists());
+        assert!(RegistryLock::acquire(&registry, "scan-2", TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn test_second_owner_in_process_is_rejected() {
+        let dir = tempdir().unwrap();
+        let registry = dir.path().join("output.csv");
+
+        let _lock = RegistryLock::acquire(&registry, "scan-1", TIMEOUT).unwrap();
+        let err = RegistryLock::acquire(&registry, "scan-2", TIMEOUT).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(err.to_string().contains("scan-1"));
+    }
+
+    #[test]
+    fn test_held_lock_times_out_however_old() {
+        let dir = tempdir().unwrap();
+        let registry = dir.path().join("output.csv");
+        let _held = hold_lock(&registry, "pid=1\nowner=cli\ncreated=0\n");
+
+        let err = RegistryLock::acquire(&registry, "gui", TIMEOUT).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(err.to_string().contains("cli (pid 1)"));
+    }
+
+    #[test]
+    fn test_waits_for_release() {
+        let dir = tempdir().unwrap();
+        let registry = dir.path().join("output.csv");
+        let held = hold_lock(&registry, "pid=1\nowner=cli\ncreated=0\n");
+
+        let releasing = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            // Released the way a holder does it, the file goes before the lock
+            fs::remove_file(RegistryLock::lock_path(&dir.path().join("output.csv"))).unwrap();
+            drop(held);
+            dir
+        });
+        let lock = RegistryLock::acquire(&registry, "gui", Duration::from_secs(5)).unwrap();
+        let _dir = releasing.join().unwrap();
+
+        let content = fs::read_to_string(RegistryLock::lock_path(&registry)).unwrap();
+        assert_eq!(LockInfo::parse(&content).unwrap().owner, "gui");
+        drop(lock);
+    }
+
+    #[test]
+    fn test_leftover_lock_file_is_reused() {
+        let dir = tempdir().unwrap();
+        let registry = dir.path().join("output.csv");
+        // Left behind by a process that died, nobody holds the flock
+        fs::write(RegistryLock::lock_path(&registry), "pid=1\nowner=cli\ncreated=0\n").unwrap();
+
+        assert!(RegistryLock::acquire(&registry, "gui", TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn test_parse_lock_info() {
+        assert_eq!(LockInfo::parse("pid=42\nowner=a=b\ncreated=7\n"), Some(LockInfo { pid: 42, owner: "a=b".to_string(), created: 7 }));
+        assert_eq!(LockInfo::parse("garbage"), None);
+    }
+}
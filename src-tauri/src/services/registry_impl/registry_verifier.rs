@@ -0,0 +1,312 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use csv::ReaderBuilder;
+use log::{info, warn};
+use serde::Serialize;
+use xxhash_rust::xxh3::{xxh3_64_with_seed, Xxh3};
+use crate::parse_registry_record;
+use crate::state::registry::Registry;
+
+/// Sidecar file holding the xxh3 checksum of the registry written by `save_registry`.
+pub(crate) fn checksum_path(registry_path: &Path) -> PathBuf {
+    let mut name = registry_path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".xxh3");
+    registry_path.with_file_name(name)
+}
+
+pub(crate) fn write_checksum(registry_path: &Path, checksum: u64) -> io::Result<()> {
+    fs::write(checksum_path(registry_path), format!("{:016x}\n", checksum))
+}
+
+fn read_checksum(registry_path: &Path) -> Option<u64> {
+    let content = fs::read_to_string(checksum_path(registry_path)).ok()?;
+    u64::from_str_radix(content.trim(), 16).ok()
+}
+
+/// Writer that checksums everything passing through it.
+pub(crate) struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Xxh3,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        ChecksumWriter { inner, hasher: Xxh3::new() }
+    }
+
+    pub(crate) fn finish(self) -> u64 {
+        self.hasher.digest()
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that checksums everything read through it.
+struct ChecksumReader<R: Read> {
+    inner: R,
+    hasher: Xxh3,
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub enum ChecksumStatus {
+    Valid,
+    Mismatch,
+    Missing,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct MalformedRecord {
+    pub line: u64,
+    pub error: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct StaleEntry {
+    pub path: String,
+    pub reason: String,
+    pub exists: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerificationReport {
+    pub checksum: ChecksumStatus,
+    pub records: usize,
+    pub malformed: Vec<MalformedRecord>,
+    pub orphans: Vec<String>,
+    pub sampled: usize,
+    pub stale: Vec<StaleEntry>,
+}
+
+impl VerificationReport {
+    pub fn is_clean(&self) -> bool {
+        self.checksum == ChecksumStatus::Valid && self.malformed.is_empty() && self.orphans.is_empty() && self.stale.is_empty()
+    }
+}
+
+/// Checks a persisted registry for corruption and drift from the filesystem.
+///
+/// Verification compares the registry against its checksum, collects malformed rows with
+/// their line numbers, finds orphaned entries whose parent directory is missing, and lstats
+/// a `sample_fraction` of the entries to catch stale metadata. Which entries are sampled is
+/// driven by `seed` so successive runs cover different parts of the registry.
+pub struct RegistryVerifier {
+    sample_fraction: f64,
+    seed: u64,
+}
+
+impl RegistryVerifier {
+    pub fn new(sample_fraction: f64, seed: u64) -> Self {
+        RegistryVerifier { sample_fraction: sample_fraction.clamp(0.0, 1.0), seed }
+    }
+
+    fn sampled(&self, path: &str) -> bool {
+        if self.sample_fraction >= 1.0 {
+            return true;
+        }
+        (xxh3_64_with_seed(path.as_bytes(), self.seed) as f64 / u64::MAX as f64) < self.sample_fraction
+    }
+
+    /// Loads the registry at `registry_path`, keeping only well formed records, and reports what's
+    /// wrong with it.
+    pub fn verify(&self, registry_path: &Path) -> io::Result<(Registry, VerificationReport)> {
+        let mut reader = ChecksumReader { inner: BufReader::new(File::open(registry_path)?), hasher: Xxh3::new() };
+        let mut registry = Registry::new();
+        let mut records = 0;
+        let mut malformed = Vec::new();
+
+        {
+            let mut csv_reader = ReaderBuilder::new().has_headers(false).flexible(true).from_reader(&mut reader);
+            for record in csv_reader.records() {
+                records += 1;
+                let parsed = record
+                    .map_err(|e| (e.position().map_or(0, |p| p.line()), e.to_string()))
+                    .and_then(|r| parse_registry_record(&r).map_err(|e| (r.position().map_or(0, |p| p.line()), e)));
+                match parsed {
+                    Ok(m) => {
+                        registry.insert(&m);
+                    }
+                    Err((line, error)) => malformed.push(MalformedRecord { line, error }),
+                }
+            }
+        }
+
+        let checksum = match read_checksum(registry_path) {
+            Some(expected) if expected == reader.hasher.digest() => ChecksumStatus::Valid,
+            Some(_) => ChecksumStatus::Mismatch,
+            None => ChecksumStatus::Missing,
+        };
+
+        let orphans = registry.orphans();
+
+        let mut sampled = 0;
+        let mut stale = Vec::new();
+        for m in registry.iter().filter(|m| self.sampled(m.get_path())) {
+            sampled += 1;
+            let reason = match fs::symlink_metadata(m.get_path()) {
+                Err(_) => Some("missing"),
+                Ok(fs_m) if fs_m.is_dir() != m.is_dir() => Some("type changed"),
+                Ok(fs_m) if fs_m.mtime() != m.modified() => Some("modified"),
+                Ok(fs_m) if !m.is_dir() && fs_m.len() != m.size_bytes() => Some("size changed"),
+                Ok(_) => None,
+            };
+            if let Some(reason) = reason {
+                stale.push(StaleEntry { path: m.get_path().clone(), reason: reason.to_string(), exists: reason != "missing" });
+            }
+        }
+        stale.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let report = VerificationReport { checksum, records, malformed, orphans, sampled, stale };
+        info!("Verified registry records={} malformed={} orphans={} sampled={} stale={} checksum={:?}",
+              report.records, report.malformed.len(), report.orphans.len(), report.sampled, report.stale.len(), report.checksum);
+
+        Ok((registry, report))
+    }
+
+    /// Fixes the problems in `report`. Missing entries are dropped, stale entries and the nearest
+    /// present ancestor of orphans are invalidated so the next incremental scan resyncs those
+    /// subtrees. Malformed records were never loaded, so saving the registry rewrites it clean.
+    pub fn repair(&self, registry: &mut Registry, report: &VerificationReport) {
+        for orphan in &report.orphans {
+            let ancestor = Path::new(orphan)
+                .ancestors()
+                .skip(1)
+                .map(|a| a.to_string_lossy().to_string())
+                .find(|a| registry.contains_key(a));
+            match ancestor {
+                Some(a) => {
+                    warn!("Rescanning {} to recover orphan {}", a, orphan);
+                    registry.invalidate(&a);
+                }
+                None => {
+                    warn!("Dropping orphan {} with no known ancestor", orphan);
+                    registry.remove_subtree(orphan);
+                }
+            }
+        }
+
+        for entry in &report.stale {
+            if entry.exists {
+                info!("Rescanning stale entry {} ({})", entry.path, entry.reason);
+                registry.invalidate(&entry.path);
+            } else {
+                info!("Dropping missing entry {}", entry.path);
+                registry.remove_subtree(&entry.path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use tempfile::{tempdir, TempDir};
+    use crate::save_registry;
+    use crate::state::resource_metadata::ResourceMetadata;
+
+    // Registers a real directory with a single file and persists the registry next to it
+    fn setup() -> (TempDir, String, PathBuf) {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("f.txt"), "hello").unwrap();
+
+        let mut registry = Registry::new();
+        for p in [root.clone(), root.join("f.txt")] {
+            let m = fs::symlink_metadata(&p).unwrap();
+            registry.insert(&ResourceMetadata::new(&p.to_string_lossy().to_string(), m.is_dir(), false, m.mtime(), m.len(), false));
+        }
+
+        let registry_path = dir.path().join("output.csv");
+        save_registry(&mut registry, &registry_path).unwrap();
+        (dir, root.to_string_lossy().to_string(), registry_path)
+    }
+
+    #[test]
+    fn test_clean_registry() {
+        let (_dir, _, registry_path) = setup();
+
+        let (registry, report) = RegistryVerifier::new(1.0, 0).verify(&registry_path).unwrap();
+
+        assert!(report.is_clean(), "{:?}", report);
+        assert_eq!(report.records, 2);
+        assert_eq!(report.sampled, 2);
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let (_dir, root, registry_path) = setup();
+        let mut file = OpenOptions::new().append(true).open(&registry_path).unwrap();
+        writeln!(file, "{}/bad,maybe,false,0,1", root).unwrap();
+        writeln!(file, "{}/gone/child,false,false,0,1", root).unwrap();
+
+        let (registry, report) = RegistryVerifier::new(0.0, 0).verify(&registry_path).unwrap();
+
+        assert_eq!(report.checksum, ChecksumStatus::Mismatch);
+        assert_eq!(report.records, 4);
+        assert_eq!(report.malformed.len(), 1);
+        assert_eq!(report.malformed[0].line, 3);
+        assert_eq!(report.orphans, vec![format!("{}/gone/child", root)]);
+        assert_eq!(report.sampled, 0);
+        assert!(!registry.contains_key(&format!("{}/bad", root)));
+    }
+
+    #[test]
+    fn test_missing_checksum() {
+        let (_dir, _, registry_path) = setup();
+        fs::remove_file(checksum_path(&registry_path)).unwrap();
+
+        let (_, report) = RegistryVerifier::new(0.0, 0).verify(&registry_path).unwrap();
+
+        assert_eq!(report.checksum, ChecksumStatus::Missing);
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn test_repair() {
+        let (_dir, root, registry_path) = setup();
+        let file = format!("{}/f.txt", root);
+        // Mtimes are compared first at one second resolution, keep it so only the size differs
+        let modified = fs::metadata(&file).unwrap().modified().unwrap();
+        fs::write(&file, "hello world").unwrap();
+        File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+        let mut csv = OpenOptions::new().append(true).open(&registry_path).unwrap();
+        writeln!(csv, "{}/gone,false,false,0,1", file).unwrap();
+        writeln!(csv, "{}/missing.txt,false,false,0,1", root).unwrap();
+
+        let verifier = RegistryVerifier::new(1.0, 0);
+        let (mut registry, report) = verifier.verify(&registry_path).unwrap();
+        assert_eq!(report.stale.iter().map(|s| s.reason.as_str()).collect::<Vec<_>>(), vec!["size changed", "missing", "missing"]);
+
+        verifier.repair(&mut registry, &report);
+        save_registry(&mut registry, &registry_path).unwrap();
+
+        assert!(!registry.contains_key(&format!("{}/missing.txt", root)));
+        assert!(!registry.contains_key(&format!("{}/gone", file)));
+        assert_eq!(registry.get(&file).unwrap().modified(), crate::state::registry::INVALIDATED_MTIME);
+
+        let (_, report) = RegistryVerifier::new(0.0, 0).verify(&registry_path).unwrap();
+        assert!(report.is_clean(), "{:?}", report);
+    }
+}
@@ -1,11 +1,36 @@
 use std::io;
+use std::sync::Arc;
 use crate::state::resource_metadata::ResourceMetadata;
 use crate::services::scanner_api::event_handler::EventHandler;
 
+/// Receives the resources of a scan. `visit` is called for every resource present once the scan
+/// saw it, the `on_` hooks tell visitors more about how the scan got there and default to doing
+/// nothing. For a directory the scanner calls, in order, its change hook if any, `visit`,
+/// `on_dir_enter`, the same for everything below it, then `on_dir_exit`.
 pub trait Visitable {
-    fn visit(&mut self, metadata: &ResourceMetadata, writer: &mut dyn io::Write, logger: &dyn EventHandler);
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, writer: &mut dyn io::Write, logger: &dyn EventHandler);
 
     fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler);
 
     fn name(&self) -> &'static str;
-}
\ No newline at end of file
+
+    /// Before anything below `root` is visited.
+    fn on_scan_start(&mut self, _root: &str, _logger: &dyn EventHandler) {}
+
+    fn on_dir_enter(&mut self, _metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {}
+
+    /// After everything below the directory was visited.
+    fn on_dir_exit(&mut self, _metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {}
+
+    /// A resource the registry didn't know yet, visited right after.
+    fn on_added(&mut self, _metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {}
+
+    /// A resource modified since the registry saw it as `previous`, visited right after.
+    fn on_modified(&mut self, _previous: &ResourceMetadata, _current: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {}
+
+    /// A resource of the registry that's gone, as it was last seen. It isn't visited.
+    fn on_deleted(&mut self, _metadata: &ResourceMetadata, _logger: &dyn EventHandler) {}
+
+    /// `path` couldn't be read, what's below it is skipped for this scan.
+    fn on_error(&mut self, _path: &str, _error: &io::Error, _logger: &dyn EventHandler) {}
+}
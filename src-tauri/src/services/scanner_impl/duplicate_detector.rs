@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::Hash;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use log::{info, warn};
+use crate::services::file_api::content_comparator::ContentComparator;
+use crate::services::file_api::fingerprint_service::{FingerprintError, FingerprintService};
+use crate::services::scanner_impl::hashing_scheduler::{HashJob, HashingScheduler};
+use crate::state::duplicate_set::{DuplicateSet, VerificationLevel};
+use crate::state::fingerprint::Fingerprint;
+use crate::state::hashing_progress::HashingProgress;
+use crate::state::resource_metadata::ResourceMetadata;
+
+/// Finds files with identical content in four stages, each only looking at the groups the
+/// previous one left with more than one member:
+///
+/// 1. size, taken from the scan metadata without touching the disk
+/// 2. fingerprint of the first and last `block_bytes` of each file
+/// 3. fingerprint of the whole file, reusing the one from the registry when it was computed
+///    with the same algorithm
+/// 4. optionally, a byte for byte comparison that splits groups whose hashes collided and
+///    marks the survivors `Verified`, otherwise sets are only `Hashed`
+///
+/// The reads of all stages but the first go through the `HashingScheduler`, so they're spread
+/// over its workers per device and share its rate.
+///
+/// Files no larger than two blocks are fully read by the second stage, so its hash is already
+/// the full content hash and they skip the third. Hardlinks to the same inode are not duplicates
+/// and are collapsed to the first path seen. Files that can't be read are skipped.
+pub struct DuplicateDetector {
+    min_size_bytes: u64,
+    block_bytes: u64,
+    fingerprint_service: Box<dyn FingerprintService + Sync>,
+    scheduler: HashingScheduler,
+    content_comparator: Option<Box<dyn ContentComparator + Sync>>,
+    by_size: HashMap<u64, Vec<String>>,
+    known_fingerprints: HashMap<String, Fingerprint>,
+}
+
+impl DuplicateDetector {
+    pub fn new(min_size_bytes: u64, block_bytes: u64, fingerprint_service: Box<dyn FingerprintService + Sync>) -> Self {
+        DuplicateDetector {
+            min_size_bytes: min_size_bytes.max(1),
+            block_bytes: block_bytes.max(1),
+            fingerprint_service,
+            scheduler: HashingScheduler::default(),
+            content_comparator: None,
+            by_size: HashMap::new(),
+            known_fingerprints: HashMap::new(),
+        }
+    }
+
+    /// Replaces the default scheduler, one worker per core without a rate cap.
+    pub fn with_scheduler(mut self, scheduler: HashingScheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Enables the byte for byte verification stage.
+    pub fn with_verification(mut self, content_comparator: Box<dyn ContentComparator + Sync>) -> Self {
+        self.content_comparator = Some(content_comparator);
+        self
+    }
+
+    /// Registers a scanned resource as a candidate. Directories, symlinks and files below the
+    /// minimum size are ignored.
+    pub fn add(&mut self, metadata: &ResourceMetadata) {
+        if metadata.is_dir() || metadata.is_symlink() || metadata.size_bytes() < self.min_size_bytes {
+            return;
+        }
+        self.by_size.entry(metadata.size_bytes()).or_default().push(metadata.get_path().clone());
+        if let Some(fingerprint) = metadata.fingerprint().filter(|f| f.algorithm() == self.fingerprint_service.algorithm()) {
+            self.known_fingerprints.insert(metadata.get_path().clone(), fingerprint.clone());
+        }
+    }
+
+    /// Runs the hashing stages over the candidates. `progress` is called while large files are
+    /// fully hashed. Stops at the first file once the fingerprint service or content comparator
+    /// is cancelled.
+    pub fn detect(&self, progress: &mut dyn FnMut(&HashingProgress)) -> Result<Vec<DuplicateSet>, FingerprintError> {
+        let candidates: Vec<(u64, Vec<String>)> = self.by_size
+            .iter()
+            .filter(|(_, p)| p.len() > 1)
+            .map(|(&size_bytes, paths)| (size_bytes, Self::distinct_inodes(paths)))
+            .filter(|(_, p)| p.len() > 1)
+            .collect();
+        let jobs = candidates
+            .iter()
+            .flat_map(|(size_bytes, paths)| paths.iter().map(move |p| HashJob { path: p.clone(), size_bytes: *size_bytes }))
+            .collect();
+        let partial_hashes = self.scheduler.run(jobs, &|job, on_read| {
+            let ranges = self.partial_ranges(job.size_bytes);
+            let fingerprint = self.fingerprint_service.fingerprint_ranges(&job.path, &ranges)?;
+            on_read(ranges.iter().map(|(_, len)| len).sum());
+            Ok(fingerprint)
+        }, &mut |_| {})?;
+
+        let mut hashed = Vec::new();
+        let mut partially_hashed = Vec::new();
+        for (size_bytes, paths) in candidates {
+            // Files that couldn't be hashed were logged by the scheduler
+            for (fingerprint, paths) in Self::group_by(paths, |p| partial_hashes.get(p).cloned()) {
+                if size_bytes <= 2 * self.block_bytes {
+                    hashed.push(DuplicateSet { size_bytes, fingerprint, paths, verification: VerificationLevel::Hashed });
+                } else {
+                    partially_hashed.push((size_bytes, paths));
+                }
+            }
+        }
+
+        let jobs = partially_hashed
+            .iter()
+            .flat_map(|(size_bytes, paths)| paths.iter().map(move |p| HashJob { path: p.clone(), size_bytes: *size_bytes }))
+            .filter(|job| !self.known_fingerprints.contains_key(&job.path))
+            .collect();
+        let fingerprints = self.scheduler.hash(self.fingerprint_service.as_ref(), jobs, progress)?;
+        let full_hash = |p: &str| self.known_fingerprints.get(p).or_else(|| fingerprints.get(p)).cloned();
+        for (size_bytes, paths) in partially_hashed {
+            for (fingerprint, paths) in Self::group_by(paths, full_hash) {
+                hashed.push(DuplicateSet { size_bytes, fingerprint, paths, verification: VerificationLevel::Hashed });
+            }
+        }
+
+        let mut sets = match &self.content_comparator {
+            Some(comparator) => self.verify(comparator.as_ref(), hashed)?,
+            None => hashed,
+        };
+
+        for set in &mut sets {
+            set.paths.sort();
+        }
+        sets.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.paths.cmp(&b.paths)));
+
+        info!("Duplicate detection found {} sets wasting {} bytes", sets.len(), sets.iter().map(DuplicateSet::wasted_bytes).sum::<u64>());
+        Ok(sets)
+    }
+
+    /// Splits every set on the scheduler, queued on the device of its first file which the
+    /// others are compared with.
+    fn verify(&self, comparator: &(dyn ContentComparator + Sync), hashed: Vec<DuplicateSet>) -> Result<Vec<DuplicateSet>, FingerprintError> {
+        let by_first: HashMap<&str, &DuplicateSet> = hashed.iter().map(|set| (set.paths[0].as_str(), set)).collect();
+        let jobs = hashed
+            .iter()
+            .map(|set| HashJob { path: set.paths[0].clone(), size_bytes: set.size_bytes * set.paths.len() as u64 })
+            .collect();
+        let mut split = self.scheduler.run(jobs, &|job, on_read| Self::split_identical(comparator, &by_first[job.path.as_str()].paths, on_read), &mut |_| {})?;
+
+        let mut verified = Vec::new();
+        for set in hashed {
+            let Some(classes) = split.remove(&set.paths[0]) else { continue };
+            if classes.len() > 1 {
+                warn!("Files with fingerprint {} differ, split into {} sets", set.fingerprint, classes.len());
+            }
+            for paths in classes {
+                verified.push(DuplicateSet { size_bytes: set.size_bytes, fingerprint: set.fingerprint.clone(), paths, verification: VerificationLevel::Verified });
+            }
+        }
+        Ok(verified)
+    }
+
+    /// Partitions `paths` into classes of byte identical files by comparing each file with the
+    /// first member of every class so far, keeping classes with more than one member. `on_read`
+    /// is called with the bytes compared so far.
+    fn split_identical(comparator: &dyn ContentComparator, paths: &[String], on_read: &mut dyn FnMut(u64)) -> Result<Vec<Vec<String>>, FingerprintError> {
+        let mut classes: Vec<Vec<String>> = Vec::new();
+        let mut read = 0;
+        'paths: for path in paths {
+            for class in &mut classes {
+                let mut compared = 0;
+                let result = comparator.identical(&class[0], path, &mut |r| {
+                    compared = r;
+                    on_read(read + r);
+                });
+                read += compared;
+                match result {
+                    Ok(true) => {
+                        class.push(path.clone());
+                        continue 'paths;
+                    }
+                    Ok(false) => {}
+                    Err(e) if e.kind() == ErrorKind::Interrupted => return Err(FingerprintError::Cancelled),
+                    Err(e) => {
+                        warn!("Skipping duplicate candidate {}: {}", path, e);
+                        continue 'paths;
+                    }
+                }
+            }
+            classes.push(vec![path.clone()]);
+        }
+        Ok(classes.into_iter().filter(|c| c.len() > 1).collect())
+    }
+
+    /// Head and tail blocks. Files no larger than two blocks are covered by a single range so the
+    /// partial hash equals the full content hash.
+    fn partial_ranges(&self, size_bytes: u64) -> Vec<(u64, u64)> {
+        if size_bytes <= 2 * self.block_bytes {
+            vec![(0, size_bytes)]
+        } else {
+            vec![(0, self.block_bytes), (size_bytes - self.block_bytes, self.block_bytes)]
+        }
+    }
+
+    fn distinct_inodes(paths: &[String]) -> Vec<String> {
+        let mut seen = HashSet::new();
+        paths
+            .iter()
+            .filter(|p| match fs::symlink_metadata(p) {
+                Ok(m) => seen.insert((m.dev(), m.ino())),
+                Err(e) => {
+                    warn!("Skipping duplicate candidate {}: {}", p, e);
+                    false
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Splits `paths` by `key`, keeping groups with more than one member. Files without a key
+    /// are dropped.
+    fn group_by<K: Hash + Eq, F: Fn(&str) -> Option<K>>(paths: Vec<String>, key: F) -> Vec<(K, Vec<String>)> {
+        let mut groups: HashMap<K, Vec<String>> = HashMap::new();
+        for path in paths {
+            if let Some(k) = key(&path) {
+                groups.entry(k).or_default().push(path);
+            }
+        }
+        groups.into_iter().filter(|(_, g)| g.len() > 1).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::tempdir;
+    use xxhash_rust::xxh3::xxh3_64;
+    use crate::services::file_impl::streaming_content_comparator::StreamingContentComparator;
+    use crate::services::file_impl::streaming_fingerprint_service::StreamingFingerprintService;
+    use crate::state::fingerprint::FingerprintAlgorithm;
+    use crate::util::cancellation_token::CancellationToken;
+
+    fn new_detector(min_size_bytes: u64, block_bytes: u64) -> DuplicateDetector {
+        DuplicateDetector::new(min_size_bytes, block_bytes, Box::new(StreamingFingerprintService::default()))
+    }
+
+    fn xxh3(bytes: &[u8]) -> Fingerprint {
+        Fingerprint::new(FingerprintAlgorithm::Xxh3_64, xxh3_64(bytes).to_be_bytes().to_vec())
+    }
+
+    fn add_file(detector: &mut DuplicateDetector, path: &Path, content: &[u8]) -> String {
+        fs::write(path, content).unwrap();
+        let p = path.to_string_lossy().to_string();
+        detector.add(&ResourceMetadata::new(&p, false, false, 0, content.len() as u64, false));
+        p
+    }
+
+    #[test]
+    fn test_detects_duplicates() {
+        let dir = tempdir().unwrap();
+        let mut detector = new_detector(1, 4);
+
+        let a = add_file(&mut detector, &dir.path().join("a"), b"0123456789");
+        let b = add_file(&mut detector, &dir.path().join("b"), b"0123456789");
+        // Same size, head and tail as a and b but a different middle
+        add_file(&mut detector, &dir.path().join("c"), b"0123xx6789");
+        // Small files are fully hashed by the partial stage
+        let d = add_file(&mut detector, &dir.path().join("d"), b"xy");
+        let e = add_file(&mut detector, &dir.path().join("e"), b"xy");
+        add_file(&mut detector, &dir.path().join("f"), b"xz");
+
+        let sets = detector.detect(&mut |_| {}).unwrap();
+
+        assert_eq!(sets, vec![
+            DuplicateSet { size_bytes: 10, fingerprint: xxh3(b"0123456789"), paths: vec![a, b], verification: VerificationLevel::Hashed },
+            DuplicateSet { size_bytes: 2, fingerprint: xxh3(b"xy"), paths: vec![d, e], verification: VerificationLevel::Hashed },
+        ]);
+    }
+
+    #[test]
+    fn test_ignores_dirs_empty_files_and_hardlinks() {
+        let dir = tempdir().unwrap();
+        let mut detector = new_detector(0, 4);
+
+        add_file(&mut detector, &dir.path().join("empty1"), b"");
+        add_file(&mut detector, &dir.path().join("empty2"), b"");
+        let original = add_file(&mut detector, &dir.path().join("original"), b"content");
+        let link = dir.path().join("link").to_string_lossy().to_string();
+        fs::hard_link(&original, &link).unwrap();
+        detector.add(&ResourceMetadata::new(&link, false, false, 0, 7, false));
+        detector.add(&ResourceMetadata::new(&dir.path().to_string_lossy().to_string(), true, false, 0, 7, false));
+
+        assert!(detector.detect(&mut |_| {}).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_skips_unreadable_candidates() {
+        let dir = tempdir().unwrap();
+        let mut detector = new_detector(1, 4);
+
+        add_file(&mut detector, &dir.path().join("a"), b"same");
+        let b = add_file(&mut detector, &dir.path().join("b"), b"same");
+        fs::remove_file(&b).unwrap();
+
+        assert!(detector.detect(&mut |_| {}).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let dir = tempdir().unwrap();
+        let cancellation = CancellationToken::new();
+        let mut detector = DuplicateDetector::new(1, 4, Box::new(StreamingFingerprintService::new(FingerprintAlgorithm::Xxh3_64, 4, 4, cancellation.clone())));
+
+        add_file(&mut detector, &dir.path().join("a"), b"same");
+        add_file(&mut detector, &dir.path().join("b"), b"same");
+        cancellation.cancel();
+
+        assert!(matches!(detector.detect(&mut |_| {}), Err(FingerprintError::Cancelled)));
+    }
+
+    #[test]
+    fn test_reuses_known_fingerprints() {
+        let dir = tempdir().unwrap();
+        let mut detector = new_detector(1, 4);
+        // The registry says both files hash the same although their middles differ, proving the
+        // full hash wasn't recomputed
+        let known = xxh3(b"known");
+        let mut paths = Vec::new();
+        for (name, content) in [("a", b"0123456789"), ("b", b"0123xx6789")] {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            let p = path.to_string_lossy().to_string();
+            detector.add(&ResourceMetadata::new(&p, false, false, 0, 10, false).with_fingerprint(Some(known.clone())));
+            paths.push(p);
+        }
+
+        let sets = detector.detect(&mut |_| {}).unwrap();
+
+        assert_eq!(sets, vec![DuplicateSet { size_bytes: 10, fingerprint: known, paths, verification: VerificationLevel::Hashed }]);
+    }
+
+    #[test]
+    fn test_verification_splits_hash_collisions() {
+        let dir = tempdir().unwrap();
+        let mut detector = new_detector(1, 4).with_verification(Box::new(StreamingContentComparator::default()));
+        // Same (pretend) fingerprint for all, only a and c are really identical
+        let known = xxh3(b"collision");
+        let mut paths = Vec::new();
+        for (name, content) in [("a", b"0123456789"), ("b", b"0123xx6789"), ("c", b"0123456789")] {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            let p = path.to_string_lossy().to_string();
+            detector.add(&ResourceMetadata::new(&p, false, false, 0, 10, false).with_fingerprint(Some(known.clone())));
+            paths.push(p);
+        }
+
+        let sets = detector.detect(&mut |_| {}).unwrap();
+
+        assert_eq!(sets, vec![DuplicateSet { size_bytes: 10, fingerprint: known, paths: vec![paths[0].clone(), paths[2].clone()], verification: VerificationLevel::Verified }]);
+    }
+
+    #[test]
+    fn test_rate_limits_partial_and_verification_reads() {
+        let dir = tempdir().unwrap();
+        let mut detector = new_detector(1, 4)
+            .with_scheduler(HashingScheduler::new(2, Some(40)))
+            .with_verification(Box::new(StreamingContentComparator::default()));
+        // Known fingerprints leave only the 16 head and tail bytes and the 20 compared ones to read
+        let known = xxh3(b"0123456789");
+        for name in ["a", "b"] {
+            let path = dir.path().join(name);
+            fs::write(&path, b"0123456789").unwrap();
+            detector.add(&ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, 0, 10, false).with_fingerprint(Some(known.clone())));
+        }
+
+        let start = std::time::Instant::now();
+        let sets = detector.detect(&mut |_| {}).unwrap();
+
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].verification, VerificationLevel::Verified);
+        assert!(start.elapsed() >= std::time::Duration::from_millis(700), "{:?}", start.elapsed());
+    }
+}
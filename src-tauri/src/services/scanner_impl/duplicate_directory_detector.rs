@@ -0,0 +1,277 @@
+use std::collections::{HashMap, HashSet};
+use crate::services::file_impl::hash_fingerprinters::new_fingerprinter;
+use crate::services::scanner_impl::visitor::directory_analyzer_visitor::DirectoryNode;
+use crate::state::duplicate_directory::{DuplicateDirectorySet, SubsetDirectory};
+use crate::state::duplicate_set::DuplicateSet;
+use crate::state::fingerprint::{Fingerprint, FingerprintAlgorithm};
+
+const MERKLE_ALGORITHM: FingerprintAlgorithm = FingerprintAlgorithm::Xxh3_128;
+
+/// Directory of the flattened tree. `hash` is `None` when a file somewhere below has content
+/// no other file shares, such a tree can't have a copy.
+struct Directory {
+    name: String,
+    path: String,
+    parent: Option<usize>,
+    size_bytes: u64,
+    hash: Option<Fingerprint>,
+    files: HashMap<String, Option<Fingerprint>>,
+    dirs: HashMap<String, usize>,
+}
+
+/// Finds copied directory trees on top of file level duplicates. Every directory gets a Merkle
+/// hash over the names and content fingerprints of its files and the hashes of its
+/// subdirectories, trees with equal hashes are identical. Only files in a `DuplicateSet` can be
+/// part of a copy, so a directory holding any other file isn't hashed at all. Empty files all
+/// share the same content.
+///
+/// Copies nested in reported copies are left out, `/a/x` and `/b/x` aren't listed when `/a` and
+/// `/b` already are.
+pub(crate) struct DuplicateDirectoryDetector {
+    min_size_bytes: u64,
+    include_subsets: bool,
+}
+
+impl DuplicateDirectoryDetector {
+    /// With `include_subsets` set, directories whose files are all found in a larger directory
+    /// are reported too. Candidates are looked up through a direct child, so a directory
+    /// without identical children is never reported as a subset.
+    pub(crate) fn new(min_size_bytes: u64, include_subsets: bool) -> Self {
+        DuplicateDirectoryDetector { min_size_bytes: min_size_bytes.max(1), include_subsets }
+    }
+
+    /// Returns identical trees sorted by wasted bytes and subsets sorted by size, both largest first.
+    pub(crate) fn detect(&self, root: &DirectoryNode, duplicates: &[DuplicateSet]) -> (Vec<DuplicateDirectorySet>, Vec<SubsetDirectory>) {
+        let known: HashMap<&str, &Fingerprint> = duplicates
+            .iter()
+            .flat_map(|s| s.paths.iter().map(move |p| (p.as_str(), &s.fingerprint)))
+            .collect();
+        let mut dirs = Vec::new();
+        Self::flatten(root, "/".to_string(), None, &known, &mut dirs);
+
+        let mut groups: HashMap<&Fingerprint, Vec<usize>> = HashMap::new();
+        for (i, d) in dirs.iter().enumerate() {
+            if let Some(hash) = d.hash.as_ref().filter(|_| d.size_bytes >= self.min_size_bytes) {
+                groups.entry(hash).or_default().push(i);
+            }
+        }
+        groups.retain(|_, members| members.len() > 1);
+
+        let subsets = if self.include_subsets { self.subsets(&dirs) } else { HashSet::new() };
+        let related = |a: usize, b: usize| {
+            (dirs[a].hash.is_some() && dirs[a].hash == dirs[b].hash) || subsets.contains(&(a, b)) || subsets.contains(&(b, a))
+        };
+        // A copy is implied when its parent is part of a reported copy holding the same name
+        let implied = |a: usize, b: usize| {
+            dirs[a].name == dirs[b].name && matches!((dirs[a].parent, dirs[b].parent), (Some(p), Some(q)) if p != q && related(p, q))
+        };
+
+        let mut sets: Vec<DuplicateDirectorySet> = groups
+            .values()
+            .filter(|members| !members.iter().all(|&m| members.iter().any(|&o| implied(m, o))))
+            .map(|members| {
+                let mut paths: Vec<String> = members.iter().map(|&m| dirs[m].path.clone()).collect();
+                paths.sort();
+                DuplicateDirectorySet { size_bytes: dirs[members[0]].size_bytes, paths }
+            })
+            .collect();
+        sets.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.paths.cmp(&b.paths)));
+
+        // Keep the smallest superset of each directory, it's the closest match
+        let mut closest: HashMap<usize, usize> = HashMap::new();
+        for &(a, b) in subsets.iter().filter(|&&(a, b)| !implied(a, b)) {
+            let current = closest.entry(a).or_insert(b);
+            if (dirs[b].size_bytes, &dirs[b].path) < (dirs[*current].size_bytes, &dirs[*current].path) {
+                *current = b;
+            }
+        }
+        let mut subset_dirs: Vec<SubsetDirectory> = closest
+            .into_iter()
+            .map(|(a, b)| SubsetDirectory { path: dirs[a].path.clone(), superset: dirs[b].path.clone(), size_bytes: dirs[a].size_bytes })
+            .collect();
+        subset_dirs.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.path.cmp(&b.path)));
+
+        (sets, subset_dirs)
+    }
+
+    /// Appends `node` and everything below it to `dirs` children first, returning its index.
+    fn flatten(node: &DirectoryNode, path: String, parent: Option<usize>, known: &HashMap<&str, &Fingerprint>, dirs: &mut Vec<Directory>) -> usize {
+        let index = dirs.len();
+        dirs.push(Directory {
+            name: node.name.clone(),
+            path: path.clone(),
+            parent,
+            size_bytes: 0,
+            hash: None,
+            files: HashMap::new(),
+            dirs: HashMap::new(),
+        });
+
+        let child_path = |name: &str| if path == "/" { format!("/{}", name) } else { format!("{}/{}", path, name) };
+        let mut size_bytes = 0;
+        let mut files = HashMap::new();
+        for file in &node.files {
+            let content = if file.is_symlink {
+                None
+            } else if file.size_bytes == 0 {
+                Some(Fingerprint::new(MERKLE_ALGORITHM, Vec::new()))
+            } else {
+                known.get(child_path(&file.name).as_str()).map(|f| (*f).clone())
+            };
+            size_bytes += file.size_bytes;
+            files.insert(file.name.clone(), content);
+        }
+        let mut children = HashMap::new();
+        for child in node.children.values() {
+            let child_index = Self::flatten(child, child_path(&child.name), Some(index), known, dirs);
+            size_bytes += dirs[child_index].size_bytes;
+            children.insert(child.name.clone(), child_index);
+        }
+
+        let hash = Self::merkle_hash(&files, &children, dirs);
+        let directory = &mut dirs[index];
+        directory.size_bytes = size_bytes;
+        directory.hash = hash;
+        directory.files = files;
+        directory.dirs = children;
+        index
+    }
+
+    fn merkle_hash(files: &HashMap<String, Option<Fingerprint>>, children: &HashMap<String, usize>, dirs: &[Directory]) -> Option<Fingerprint> {
+        let mut entries = Vec::with_capacity(files.len() + children.len());
+        for (name, content) in files {
+            entries.push((name, 'f', content.as_ref()?.to_string()));
+        }
+        for (name, &child) in children {
+            entries.push((name, 'd', dirs[child].hash.as_ref()?.to_string()));
+        }
+        entries.sort();
+
+        let mut fingerprinter = new_fingerprinter(MERKLE_ALGORITHM);
+        for (name, kind, content) in entries {
+            fingerprinter.update(format!("{}\0{}\0{}\n", kind, name, content).as_bytes());
+        }
+        Some(fingerprinter.finish())
+    }
+
+    /// Every pair `(a, b)` where all files of hashed directory `a` are found in `b`, which
+    /// isn't identical to `a` and isn't an ancestor or descendant of it.
+    fn subsets(&self, dirs: &[Directory]) -> HashSet<(usize, usize)> {
+        let mut index: HashMap<(char, &str, &Fingerprint), Vec<usize>> = HashMap::new();
+        for (i, d) in dirs.iter().enumerate() {
+            for (name, content) in &d.files {
+                if let Some(content) = content {
+                    index.entry(('f', name.as_str(), content)).or_default().push(i);
+                }
+            }
+            for (name, &child) in &d.dirs {
+                if let Some(hash) = &dirs[child].hash {
+                    index.entry(('d', name.as_str(), hash)).or_default().push(i);
+                }
+            }
+        }
+
+        let nested = |a: &str, b: &str| b.starts_with(&format!("{}/", a.trim_end_matches('/')));
+        let mut pairs = HashSet::new();
+        for (a, dir) in dirs.iter().enumerate() {
+            if dir.hash.is_none() || dir.size_bytes < self.min_size_bytes {
+                continue;
+            }
+            let key = match dir.files.iter().min_by_key(|(name, _)| *name) {
+                Some((name, content)) => ('f', name.as_str(), content.as_ref().unwrap()),
+                None => match dir.dirs.iter().min_by_key(|(name, _)| *name) {
+                    Some((name, &child)) => ('d', name.as_str(), dirs[child].hash.as_ref().unwrap()),
+                    None => continue,
+                },
+            };
+            let candidates: HashSet<usize> = index.get(&key).into_iter().flatten().copied().collect();
+            for b in candidates {
+                if b != a && dirs[b].hash != dir.hash && !nested(&dir.path, &dirs[b].path) && !nested(&dirs[b].path, &dir.path) && Self::contains(dirs, a, b) {
+                    pairs.insert((a, b));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Whether every file below `a` exists with the same content at the same place below `b`.
+    fn contains(dirs: &[Directory], a: usize, b: usize) -> bool {
+        dirs[a].files.iter().all(|(name, content)| content.is_some() && dirs[b].files.get(name) == Some(content))
+            && dirs[a].dirs.iter().all(|(name, &child)| match dirs[b].dirs.get(name) {
+                Some(&other) => (dirs[child].hash.is_some() && dirs[child].hash == dirs[other].hash) || Self::contains(dirs, child, other),
+                None => false,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::Arc;
+    use super::*;
+    use crate::services::scanner_api::visitable::Visitable;
+    use crate::services::scanner_impl::noop_event_handler::NoopEventHandler;
+    use crate::services::scanner_impl::visitor::directory_analyzer_visitor::DirectoryAnalyzerVisitor;
+    use crate::state::duplicate_set::VerificationLevel;
+    use crate::state::resource_metadata::ResourceMetadata;
+
+    /// Builds the tree from `(path, size, content)`, paths ending in `/` are directories. Files
+    /// sharing a content label are duplicates, an empty label marks unique content.
+    fn tree(entries: &[(&str, u64, &str)]) -> (DirectoryAnalyzerVisitor, Vec<DuplicateSet>) {
+        let mut visitor = DirectoryAnalyzerVisitor::with_files();
+        let mut by_content: HashMap<&str, Vec<String>> = HashMap::new();
+        for &(path, size, content) in entries {
+            let is_dir = path.ends_with('/');
+            let path = path.trim_end_matches('/').to_string();
+            visitor.visit(&Arc::new(ResourceMetadata::new(&path, is_dir, false, 0, size, false)), &mut io::sink(), &NoopEventHandler{});
+            if !content.is_empty() {
+                by_content.entry(content).or_default().push(path);
+            }
+        }
+        let sets = by_content
+            .into_iter()
+            .map(|(content, paths)| DuplicateSet {
+                size_bytes: 0,
+                fingerprint: Fingerprint::new(FingerprintAlgorithm::Xxh3_64, content.as_bytes().to_vec()),
+                paths,
+                verification: VerificationLevel::Hashed,
+            })
+            .collect();
+        (visitor, sets)
+    }
+
+    #[test]
+    fn test_identical_trees() {
+        let (visitor, duplicates) = tree(&[
+            ("/photos/", 0, ""), ("/photos/a.jpg", 10, "a"), ("/photos/raw/", 0, ""), ("/photos/raw/b.raw", 20, "b"), ("/photos/empty", 0, ""),
+            ("/backup/", 0, ""), ("/backup/a.jpg", 10, "a"), ("/backup/raw/", 0, ""), ("/backup/raw/b.raw", 20, "b"), ("/backup/empty", 0, ""),
+            ("/renamed/", 0, ""), ("/renamed/x.jpg", 10, "a"),
+            ("/other/", 0, ""), ("/other/raw/", 0, ""), ("/other/raw/b.raw", 20, "b"), ("/other/c", 5, ""),
+        ]);
+
+        let (sets, subsets) = DuplicateDirectoryDetector::new(1, false).detect(visitor.root(), &duplicates);
+
+        assert_eq!(sets, vec![
+            DuplicateDirectorySet { size_bytes: 20, paths: vec!["/backup/raw".to_string(), "/other/raw".to_string(), "/photos/raw".to_string()] },
+            DuplicateDirectorySet { size_bytes: 30, paths: vec!["/backup".to_string(), "/photos".to_string()] },
+        ]);
+        assert!(subsets.is_empty());
+    }
+
+    #[test]
+    fn test_subsets() {
+        let (visitor, duplicates) = tree(&[
+            ("/project/", 0, ""), ("/project/src/", 0, ""), ("/project/src/main.rs", 10, "main"), ("/project/src/lib.rs", 5, "lib"), ("/project/README", 3, "readme"),
+            ("/project-final2/", 0, ""), ("/project-final2/src/", 0, ""), ("/project-final2/src/main.rs", 10, "main"), ("/project-final2/README", 3, "readme"),
+            ("/lonely/", 0, ""), ("/lonely/README", 3, "readme"),
+        ]);
+
+        let (sets, subsets) = DuplicateDirectoryDetector::new(1, true).detect(visitor.root(), &duplicates);
+
+        assert!(sets.is_empty());
+        assert_eq!(subsets, vec![
+            SubsetDirectory { path: "/project-final2".to_string(), superset: "/project".to_string(), size_bytes: 13 },
+            SubsetDirectory { path: "/lonely".to_string(), superset: "/project-final2".to_string(), size_bytes: 3 },
+        ]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::num::NonZeroUsize;
+use std::os::unix::fs::MetadataExt;
+use std::sync::mpsc;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, info, warn};
+use crate::services::file_api::fingerprint_service::{FingerprintError, FingerprintService};
+use crate::state::fingerprint::Fingerprint;
+use crate::state::hashing_progress::HashingProgress;
+use crate::util::rate_limiter::RateLimiter;
+
+const MAX_DEFAULT_WORKERS: usize = 8;
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A file to read, `size_bytes` as scanned is used for progress before the file is opened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HashJob {
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+/// Files of one device, in inode order as a stand-in for their place on disk.
+struct DeviceQueue {
+    jobs: VecDeque<HashJob>,
+    max_workers: usize,
+    active: usize,
+}
+
+struct Queues {
+    devices: Vec<DeviceQueue>,
+    stopped: bool,
+}
+
+/// Reads a job's file, reporting the bytes read so far through its callback.
+pub type ReadTask<'a, T> = dyn Fn(&HashJob, &mut dyn FnMut(u64)) -> Result<T, FingerprintError> + Sync + 'a;
+
+enum Message<T> {
+    Hashed(u64),
+    Done(String, Result<T, FingerprintError>),
+}
+
+/// Reads files on a bounded pool of worker threads, to fingerprint them or for any other task
+/// that reads one file per job. Files are queued per device and a spinning disk is read by one
+/// worker at a time so it isn't made to seek between files, while solid state and unknown
+/// devices are read by as many workers as are free. With a rate the bytes read by all workers
+/// together are capped to it.
+///
+/// Progress is reported on the calling thread at most every `REPORT_INTERVAL` and once all
+/// files are done.
+pub struct HashingScheduler {
+    workers: usize,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl Default for HashingScheduler {
+    fn default() -> Self {
+        HashingScheduler::new(Self::default_workers(), None)
+    }
+}
+
+impl HashingScheduler {
+    pub fn new(workers: usize, bytes_per_second: Option<u64>) -> Self {
+        HashingScheduler { workers: workers.max(1), rate_limiter: bytes_per_second.map(RateLimiter::new) }
+    }
+
+    /// One worker per core, up to `MAX_DEFAULT_WORKERS`.
+    pub fn default_workers() -> usize {
+        thread::available_parallelism().map_or(1, NonZeroUsize::get).min(MAX_DEFAULT_WORKERS)
+    }
+
+    /// Fingerprints of the `jobs`, files that can't be read are logged and left out. Stops
+    /// handing out files at the first cancellation and returns it.
+    pub fn hash(&self, service: &(dyn FingerprintService + Sync), jobs: Vec<HashJob>, progress: &mut dyn FnMut(&HashingProgress)) -> Result<HashMap<String, Fingerprint>, FingerprintError> {
+        self.run(jobs, &|job, on_read| service.fingerprint(&job.path, &mut |hashed, _| on_read(hashed)), progress)
+    }
+
+    /// Results of `task` for each of the `jobs` by path, like `hash`. The task reports the bytes
+    /// it read from the job's file so far through its callback, which waits for the rate.
+    pub fn run<T: Send>(&self, jobs: Vec<HashJob>, task: &ReadTask<'_, T>, progress: &mut dyn FnMut(&HashingProgress)) -> Result<HashMap<String, T>, FingerprintError> {
+        let mut results = HashMap::new();
+        if jobs.is_empty() {
+            return Ok(results);
+        }
+
+        let mut status = HashingProgress::new(jobs.len(), jobs.iter().map(|j| j.size_bytes).sum());
+        let devices = self.device_queues(jobs);
+        let workers = self.workers.min(devices.iter().map(|d| d.max_workers.min(d.jobs.len())).sum());
+        info!("Reading {} files, {} bytes on {} devices with {} workers", status.total_files, status.total_bytes, devices.len(), workers);
+
+        let queues = Mutex::new(Queues { devices, stopped: false });
+        let available = Condvar::new();
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut cancelled = false;
+
+        thread::scope(|s| {
+            for _ in 0..workers {
+                let sender = sender.clone();
+                let (queues, available) = (&queues, &available);
+                s.spawn(move || self.work(task, queues, available, sender));
+            }
+            drop(sender);
+
+            for message in receiver {
+                match message {
+                    Message::Hashed(bytes) => status.hashed_bytes += bytes,
+                    Message::Done(path, result) => {
+                        status.hashed_files += 1;
+                        match result {
+                            Ok(result) => {
+                                results.insert(path.clone(), result);
+                            }
+                            Err(FingerprintError::Cancelled) => cancelled = true,
+                            Err(e) => warn!("Skipping duplicate candidate {}: {}", path, e),
+                        }
+                        status.path = path;
+                    }
+                }
+                if last_report.elapsed() >= REPORT_INTERVAL {
+                    status.update_rate(start.elapsed());
+                    progress(&status);
+                    last_report = Instant::now();
+                }
+            }
+        });
+
+        if cancelled {
+            return Err(FingerprintError::Cancelled);
+        }
+        status.update_rate(start.elapsed());
+        progress(&status);
+        info!("Read {} bytes in {:?}", status.hashed_bytes, start.elapsed());
+        Ok(results)
+    }
+
+    fn work<T>(&self, task: &ReadTask<'_, T>, queues: &Mutex<Queues>, available: &Condvar, sender: mpsc::Sender<Message<T>>) {
+        while let Some((device, job)) = Self::next_job(queues, available) {
+            let mut reported = 0;
+            let result = task(&job, &mut |total| {
+                let read = total.saturating_sub(reported);
+                reported = total;
+                if let Some(limiter) = &self.rate_limiter {
+                    limiter.acquire(read);
+                }
+                // The receiver only goes away once all workers are done
+                let _ = sender.send(Message::Hashed(read));
+            });
+
+            let mut guard = queues.lock().unwrap();
+            guard.devices[device].active -= 1;
+            guard.stopped |= matches!(result, Err(FingerprintError::Cancelled));
+            drop(guard);
+            available.notify_all();
+            let _ = sender.send(Message::Done(job.path, result));
+        }
+    }
+
+    /// Takes a file from the device with the fewest workers that can take another one, waiting
+    /// while all devices with files left are busy. `None` once everything is handed out.
+    fn next_job(queues: &Mutex<Queues>, available: &Condvar) -> Option<(usize, HashJob)> {
+        let mut guard = queues.lock().unwrap();
+        loop {
+            if guard.stopped || guard.devices.iter().all(|d| d.jobs.is_empty()) {
+                return None;
+            }
+            let free = guard.devices
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| !d.jobs.is_empty() && d.active < d.max_workers)
+                .min_by_key(|(_, d)| d.active)
+                .map(|(i, _)| i);
+            if let Some(i) = free {
+                let device = &mut guard.devices[i];
+                device.active += 1;
+                return device.jobs.pop_front().map(|job| (i, job));
+            }
+            guard = available.wait(guard).unwrap();
+        }
+    }
+
+    fn device_queues(&self, jobs: Vec<HashJob>) -> Vec<DeviceQueue> {
+        let mut by_device: HashMap<u64, Vec<(u64, HashJob)>> = HashMap::new();
+        for job in jobs {
+            // Files gone since the scan still get a job so their error is reported like any other
+            let (device, inode) = fs::metadata(&job.path).map_or((0, 0), |m| (m.dev(), m.ino()));
+            by_device.entry(device).or_default().push((inode, job));
+        }
+
+        by_device
+            .into_iter()
+            .map(|(device, mut jobs)| {
+                jobs.sort_by_key(|(inode, _)| *inode);
+                let max_workers = if is_rotational(device) { 1 } else { self.workers };
+                debug!("Device {} queued {} files for up to {} workers", device, jobs.len(), max_workers);
+                DeviceQueue { jobs: jobs.into_iter().map(|(_, job)| job).collect(), max_workers, active: 0 }
+            })
+            .collect()
+    }
+}
+
+/// Major and minor number of a Linux device id.
+fn device_numbers(device: u64) -> (u64, u64) {
+    let major = ((device >> 32) & 0xffff_f000) | ((device >> 8) & 0x0fff);
+    let minor = ((device >> 12) & 0xffff_ff00) | (device & 0x00ff);
+    (major, minor)
+}
+
+/// Whether sysfs says the block device, or the disk a partition is on, is a spinning disk.
+/// Devices without sysfs entries, like network and virtual file systems, aren't.
+fn is_rotational(device: u64) -> bool {
+    let (major, minor) = device_numbers(device);
+    let base = format!("/sys/dev/block/{}:{}", major, minor);
+    [format!("{}/queue/rotational", base), format!("{}/../queue/rotational", base)]
+        .iter()
+        .find_map(|p| fs::read_to_string(p).ok())
+        .is_some_and(|flag| flag.trim() == "1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use crate::services::file_impl::streaming_fingerprint_service::StreamingFingerprintService;
+    use crate::state::fingerprint::FingerprintAlgorithm;
+    use crate::util::cancellation_token::CancellationToken;
+
+    fn jobs(dir: &std::path::Path, count: usize) -> Vec<HashJob> {
+        (0..count)
+            .map(|i| {
+                let path = dir.join(format!("file-{}", i));
+                fs::write(&path, vec![i as u8; 1000 + i]).unwrap();
+                HashJob { path: path.to_string_lossy().to_string(), size_bytes: 1000 + i as u64 }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hashes_in_parallel() {
+        let dir = tempdir().unwrap();
+        let mut jobs = jobs(dir.path(), 20);
+        jobs.push(HashJob { path: dir.path().join("missing").to_string_lossy().to_string(), size_bytes: 10 });
+        let service = StreamingFingerprintService::default();
+
+        let mut reports = Vec::new();
+        let fingerprints = HashingScheduler::new(4, None).hash(&service, jobs.clone(), &mut |p| reports.push(p.clone())).unwrap();
+
+        assert_eq!(fingerprints.len(), 20);
+        for job in &jobs[..20] {
+            assert_eq!(fingerprints[&job.path], service.fingerprint(&job.path, &mut |_, _| {}).unwrap());
+        }
+        let last = reports.last().unwrap();
+        assert_eq!((last.hashed_files, last.total_files, last.hashed_bytes, last.total_bytes), (21, 21, 20190, 20200));
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let dir = tempdir().unwrap();
+        let cancellation = CancellationToken::new();
+        let service = StreamingFingerprintService::new(FingerprintAlgorithm::Xxh3_64, 64, 64, cancellation.clone());
+        cancellation.cancel();
+
+        let result = HashingScheduler::new(2, None).hash(&service, jobs(dir.path(), 5), &mut |_| {});
+        assert!(matches!(result, Err(FingerprintError::Cancelled)));
+    }
+
+    #[test]
+    fn test_device_numbers() {
+        assert_eq!(device_numbers(0x0801), (8, 1));
+        assert_eq!(device_numbers(0x0000_1000_5672_3489), (0x1234, 0x56789));
+    }
+}
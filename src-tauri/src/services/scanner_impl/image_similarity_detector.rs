@@ -0,0 +1,143 @@
+use log::{debug, warn};
+use crate::services::file_api::file_type_detector::FileTypeDetector;
+use crate::services::file_api::perceptual_hasher::PerceptualHasher;
+use crate::state::image_cluster::{ImageCluster, PerceptualHashAlgorithm, SimilarImage};
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::util::bk_tree::BkTree;
+use crate::util::cancellation_token::CancellationToken;
+use crate::util::disjoint_set::DisjointSet;
+
+const IMAGE_MIME_TYPES: [&str; 3] = ["image/jpeg", "image/png", "image/webp"];
+
+/// Finds JPEG, PNG and WebP images that look alike. Candidates are picked by MIME type while
+/// the scan runs, `detect` hashes them and clusters images whose hashes are at most
+/// `max_distance` bits apart, directly or through other images of the cluster. Images that
+/// can't be decoded are logged and skipped.
+pub(crate) struct ImageSimilarityDetector {
+    hasher: Box<dyn PerceptualHasher>,
+    file_type_detector: Box<dyn FileTypeDetector>,
+    max_distance: u32,
+    cancellation: CancellationToken,
+    candidates: Vec<(String, u64)>,
+}
+
+impl ImageSimilarityDetector {
+    pub(crate) fn new(hasher: Box<dyn PerceptualHasher>, file_type_detector: Box<dyn FileTypeDetector>, max_distance: u32, cancellation: CancellationToken) -> Self {
+        ImageSimilarityDetector { hasher, file_type_detector, max_distance, cancellation, candidates: Vec::new() }
+    }
+
+    pub(crate) fn algorithm(&self) -> PerceptualHashAlgorithm {
+        self.hasher.algorithm()
+    }
+
+    pub(crate) fn add(&mut self, metadata: &ResourceMetadata) {
+        if !metadata.is_file() || metadata.is_symlink() || metadata.size_bytes() == 0 {
+            return;
+        }
+        match self.file_type_detector.get_file_type(metadata.get_path()) {
+            Ok(mime) if IMAGE_MIME_TYPES.contains(&mime.as_str()) => self.candidates.push((metadata.get_path().clone(), metadata.size_bytes())),
+            Ok(_) => {}
+            Err(e) => debug!("No file type for {}: {}", metadata.get_path(), e),
+        }
+    }
+
+    /// Clusters of two or more images, the cluster wasting the most bytes first. Stops early,
+    /// returning `None`, when cancelled. `progress` gets the number of images hashed so far.
+    pub(crate) fn detect(&self, progress: &mut dyn FnMut(usize, usize)) -> Option<Vec<ImageCluster>> {
+        let mut hashes = Vec::with_capacity(self.candidates.len());
+        for (i, (path, size_bytes)) in self.candidates.iter().enumerate() {
+            if self.cancellation.is_cancelled() {
+                return None;
+            }
+            match self.hasher.hash(path) {
+                Ok(hash) => hashes.push((path, *size_bytes, hash)),
+                Err(e) => warn!("Skipping image {}: {}", path, e),
+            }
+            progress(i + 1, self.candidates.len());
+        }
+
+        let mut tree = BkTree::default();
+        for (i, &(_, _, hash)) in hashes.iter().enumerate() {
+            tree.insert(hash, i);
+        }
+
+        let mut similar = DisjointSet::new(hashes.len());
+        for (i, &(_, _, hash)) in hashes.iter().enumerate() {
+            for j in tree.within(hash, self.max_distance) {
+                similar.union(i, j);
+            }
+        }
+
+        let mut clusters: Vec<ImageCluster> = similar
+            .groups()
+            .into_iter()
+            .map(|mut members| {
+                members.sort_by(|&a, &b| hashes[b].1.cmp(&hashes[a].1).then_with(|| hashes[a].0.cmp(hashes[b].0)));
+                let first = hashes[members[0]].2;
+                let images = members
+                    .into_iter()
+                    .map(|m| SimilarImage { path: hashes[m].0.clone(), size_bytes: hashes[m].1, distance: (hashes[m].2 ^ first).count_ones() })
+                    .collect();
+                ImageCluster { images }
+            })
+            .collect();
+        clusters.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.images[0].path.cmp(&b.images[0].path)));
+        Some(clusters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use crate::services::file_impl::mime_guess_file_type_detector::MimeGuessFileTypeDetector;
+
+    /// Hashes are read from the file name, `/x/<hex>.jpg`
+    struct NameHasher;
+
+    impl PerceptualHasher for NameHasher {
+        fn algorithm(&self) -> PerceptualHashAlgorithm {
+            PerceptualHashAlgorithm::DHash
+        }
+
+        fn hash(&self, path: &str) -> io::Result<u64> {
+            let name = path.rsplit('/').next().unwrap().split('.').next().unwrap();
+            u64::from_str_radix(name, 16).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+    }
+
+    fn detector(max_distance: u32, cancellation: CancellationToken, files: &[(&str, u64)]) -> ImageSimilarityDetector {
+        let mut detector = ImageSimilarityDetector::new(Box::new(NameHasher), Box::new(MimeGuessFileTypeDetector), max_distance, cancellation);
+        for &(path, size) in files {
+            detector.add(&ResourceMetadata::new(&path.to_string(), false, false, 0, size, false));
+        }
+        detector
+    }
+
+    #[test]
+    fn test_clusters() {
+        let detector = detector(2, CancellationToken::new(), &[
+            ("/p/ff00.jpg", 100), ("/p/ff01.png", 300), ("/p/ff03.webp", 50),
+            ("/p/00ff.jpg", 10), ("/p/f0f0.jpg", 10),
+            ("/p/ff00.txt", 10), ("/p/zz.jpg", 10), ("/p/ff02.jpg", 0),
+        ]);
+
+        let mut reported = Vec::new();
+        let clusters = detector.detect(&mut |done, total| reported.push((done, total))).unwrap();
+
+        assert_eq!(clusters.len(), 1);
+        let images: Vec<(&str, u32)> = clusters[0].images.iter().map(|i| (i.path.as_str(), i.distance)).collect();
+        assert_eq!(images, vec![("/p/ff01.png", 0), ("/p/ff00.jpg", 1), ("/p/ff03.webp", 1)]);
+        assert_eq!(clusters[0].wasted_bytes(), 150);
+        assert_eq!(reported.last(), Some(&(6, 6)));
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let cancellation = CancellationToken::new();
+        let detector = detector(2, cancellation.clone(), &[("/p/ff00.jpg", 100), ("/p/ff01.jpg", 100)]);
+        cancellation.cancel();
+
+        assert!(detector.detect(&mut |_, _| {}).is_none());
+    }
+}
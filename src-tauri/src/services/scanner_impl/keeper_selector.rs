@@ -0,0 +1,123 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use log::warn;
+use crate::state::duplicate_set::DuplicateSet;
+use crate::state::keeper_policy::{KeeperDecision, KeeperPolicy, StagedAction};
+
+/// Picks the copy of each duplicate set that survives and stages `action` for the others.
+/// Policies are applied in order, ties left after the last one go to the first path. Copies
+/// that can't be stat'ed are neither kept nor acted on.
+pub(crate) struct KeeperSelector {
+    policies: Vec<KeeperPolicy>,
+    action: String,
+}
+
+impl KeeperSelector {
+    /// `action` is `delete` or `hardlink`, as taken by `commit`.
+    pub(crate) fn new(policies: Vec<KeeperPolicy>, action: &str) -> Result<Self, String> {
+        match action {
+            "delete" | "hardlink" => Ok(KeeperSelector { policies, action: action.to_string() }),
+            _ => Err(format!("Unknown keeper action {}", action)),
+        }
+    }
+
+    pub(crate) fn select(&self, set: &DuplicateSet) -> Option<KeeperDecision> {
+        let mut candidates: Vec<(&String, i64, i64)> = set.paths
+            .iter()
+            .filter_map(|p| match fs::metadata(p) {
+                Ok(m) => Some((p, m.mtime(), m.atime())),
+                Err(e) => {
+                    warn!("Leaving {} out of keeper selection: {}", p, e);
+                    None
+                }
+            })
+            .collect();
+        if candidates.len() < 2 {
+            return None;
+        }
+        candidates.sort();
+
+        let mut reasons = Vec::new();
+        for policy in &self.policies {
+            if candidates.len() == 1 {
+                break;
+            }
+            let best = candidates.iter().map(|&(p, m, a)| policy.rank(p, m, a)).min()?;
+            let before = candidates.len();
+            candidates.retain(|&(p, m, a)| policy.rank(p, m, a) == best);
+            if candidates.len() < before {
+                reasons.push(policy.reason());
+            }
+        }
+        if candidates.len() > 1 {
+            reasons.push("first path in order".to_string());
+        }
+
+        let keeper = candidates[0].0.clone();
+        let actions = set.paths
+            .iter()
+            .filter(|&p| *p != keeper && fs::metadata(p).is_ok())
+            .map(|p| StagedAction { action: self.action.clone(), path: p.clone(), bytes: set.size_bytes, duplicate_of: keeper.clone() })
+            .collect();
+        Some(KeeperDecision { keeper, reasons, actions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+    use super::*;
+    use crate::state::duplicate_set::VerificationLevel;
+    use crate::state::fingerprint::{Fingerprint, FingerprintAlgorithm};
+
+    fn duplicates(paths: &[String]) -> DuplicateSet {
+        DuplicateSet { size_bytes: 4, fingerprint: Fingerprint::new(FingerprintAlgorithm::Xxh3_64, vec![1]), paths: paths.to_vec(), verification: VerificationLevel::Verified }
+    }
+
+    #[test]
+    fn test_select() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        fs::create_dir(dir.path().join("Downloads")).unwrap();
+        let paths: Vec<String> = ["Downloads/a", "b-long-name", "c"].iter().map(|n| format!("{}/{}", root, n)).collect();
+        for (i, p) in paths.iter().enumerate() {
+            fs::write(p, "same").unwrap();
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 + if i == 0 { 0 } else { 10 });
+            File::options().write(true).open(p).unwrap().set_modified(modified).unwrap();
+        }
+        let set = duplicates(&paths);
+
+        let oldest = KeeperSelector::new(vec![KeeperPolicy::OldestModified], "delete").unwrap().select(&set).unwrap();
+        assert_eq!(oldest.keeper, paths[0]);
+        assert_eq!(oldest.reasons, vec!["oldest modification time"]);
+        assert_eq!(oldest.actions.len(), 2);
+        assert!(oldest.actions.iter().all(|a| a.action == "delete" && a.duplicate_of == paths[0] && a.bytes == 4));
+
+        let selector = KeeperSelector::new(vec![KeeperPolicy::AvoidTransient, KeeperPolicy::OldestModified, KeeperPolicy::ShortestPath], "hardlink").unwrap();
+        let decision = selector.select(&set).unwrap();
+        assert_eq!(decision.keeper, paths[2]);
+        assert_eq!(decision.reasons, vec!["fewest Downloads and temporary directories in its path", "shortest path"]);
+        assert_eq!(decision.actions.iter().map(|a| a.path.as_str()).collect::<Vec<_>>(), vec![paths[0].as_str(), paths[1].as_str()]);
+    }
+
+    #[test]
+    fn test_ties_and_missing_copies() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a").to_string_lossy().to_string();
+        let b = dir.path().join("b").to_string_lossy().to_string();
+        fs::write(&a, "same").unwrap();
+        fs::write(&b, "same").unwrap();
+        let gone = dir.path().join("gone").to_string_lossy().to_string();
+
+        let decision = KeeperSelector::new(vec![], "delete").unwrap().select(&duplicates(&[b.clone(), gone.clone(), a.clone()])).unwrap();
+        assert_eq!(decision.keeper, a);
+        assert_eq!(decision.reasons, vec!["first path in order"]);
+        assert_eq!(decision.actions.len(), 1);
+        assert_eq!(decision.actions[0].path, b);
+
+        assert!(KeeperSelector::new(vec![], "delete").unwrap().select(&duplicates(&[a, gone])).is_none());
+        assert!(KeeperSelector::new(vec![], "compress").is_err());
+    }
+}
@@ -1,3 +1,12 @@
 pub mod resource_scanner;
 pub mod visitor;
+pub mod duplicate_detector;
+pub mod duplicate_directory_detector;
+pub mod keeper_selector;
+pub mod image_similarity_detector;
+pub mod text_similarity_detector;
+pub mod hashing_scheduler;
+pub mod visitor_pipeline;
 pub(crate) mod noop_event_handler;
+#[cfg(test)]
+pub(crate) mod recording_event_handler;
@@ -0,0 +1,21 @@
+use std::cell::RefCell;
+use crate::services::scanner_api::event_handler::EventHandler;
+
+/// Keeps every published event so tests can assert on what a component emitted.
+#[derive(Default)]
+pub struct RecordingEventHandler {
+    events: RefCell<Vec<(String, String)>>,
+}
+
+impl RecordingEventHandler {
+    /// Messages published under `event`, oldest first.
+    pub fn messages(&self, event: &str) -> Vec<String> {
+        self.events.borrow().iter().filter(|(e, _)| e == event).map(|(_, m)| m.clone()).collect()
+    }
+}
+
+impl EventHandler for RecordingEventHandler {
+    fn publish(&self, event: &str, message: String) {
+        self.events.borrow_mut().push((event.to_string(), message));
+    }
+}
@@ -1,8 +1,10 @@
-use std::collections::HashMap;
 use std::{fs, io};
+use std::io::ErrorKind;
+use std::sync::Arc;
 use std::os::unix::fs::MetadataExt;
-use log::{debug, info};
-use crate::state::resource_metadata::ResourceMetadata;
+use log::{debug, info, warn};
+use crate::state::registry::{EntryId, Registry};
+use crate::state::resource_metadata::{Ownership, ResourceMetadata};
 use crate::services::scanner_api::event_handler::EventHandler;
 use crate::services::scanner_api::visitable::Visitable;
 
@@ -13,6 +15,8 @@ pub struct ResourceScanner {
     deleted_dirs: u64,
     updated_files: u64,
     updated_dirs: u64,
+    // Directories entered by a walk of the registry that weren't exited yet, innermost last
+    open_dirs: Vec<Arc<ResourceMetadata>>,
 }
 
 impl ResourceScanner {
@@ -23,55 +27,91 @@ impl ResourceScanner {
             deleted_dirs: 0,
             updated_files: 0,
             updated_dirs: 0,
+            open_dirs: Vec::new(),
         }
     }
 
+    pub fn full_scan(&mut self, registry: &mut Registry, path: &String, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        Self::notify(visitors, |v| v.on_scan_start(path, logger));
+        self.scan_tree(registry, path, visitors, writer, logger);
+    }
+
     #[warn(clippy::only_used_in_recursion)]
-    pub fn full_scan(&mut self, registry: &mut HashMap<String, ResourceMetadata>, path: &String, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
-        let metadata = registry.entry(path.clone()).or_insert_with(|| {
-            let m = fs::symlink_metadata(path).unwrap();
-            ResourceMetadata::new(path, m.is_dir(), m.is_symlink(), m.mtime(), m.len(), false)
-        });
+    fn scan_tree(&mut self, registry: &mut Registry, path: &String, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let metadata = match registry.get(path) {
+            Some(cached) => Arc::new(cached),
+            None => match fs::symlink_metadata(path) {
+                Ok(m) => {
+                    let new = Arc::new(Self::metadata(path, &m));
+                    registry.insert(&new);
+                    Self::notify(visitors, |v| v.on_added(&new, logger));
+                    new
+                }
+                Err(e) => {
+                    Self::notify(visitors, |v| v.on_error(path, &e, logger));
+                    return;
+                }
+            },
+        };
 
-        for visitor in &mut *visitors {
-            visitor.visit(metadata, writer, logger);
-        }
+        Self::visit(&metadata, visitors, writer, logger);
 
         if metadata.is_dir() && !metadata.is_symlink() {
-            if let Ok(entries) = fs::read_dir(path) {
-                for entry in entries {
-                    if let Ok(e) = entry {
-                        self.full_scan(registry, &e.path().to_string_lossy().to_string(), visitors, writer, logger);
+            Self::notify(visitors, |v| v.on_dir_enter(&metadata, logger));
+            match fs::read_dir(path) {
+                Ok(entries) => {
+                    for entry in entries {
+                        match entry {
+                            Ok(e) => self.scan_tree(registry, &e.path().to_string_lossy().to_string(), visitors, writer, logger),
+                            Err(e) => Self::notify(visitors, |v| v.on_error(path, &e, logger)),
+                        }
                     }
                 }
+                Err(e) => Self::notify(visitors, |v| v.on_error(path, &e, logger)),
             }
+            Self::notify(visitors, |v| v.on_dir_exit(&metadata, logger));
         }
     }
 
-    pub fn incremental_scan(&mut self, root: &String, registry: &mut HashMap<String, ResourceMetadata>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
-        let mut keys: Vec<String> = registry
-            .keys()
-            .filter(|key| key.starts_with(root))
-            .cloned()
-            .collect();
+    pub fn incremental_scan(&mut self, root: &String, registry: &mut Registry, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        // Depth-first order so lstat lookups have locality
+        let ids = registry.walk(root);
+        info!("Scanning resources={}", ids.len());
 
-        // Sort the keys so lstat lookups have locality
-        keys.sort();
-        info!("Scanning resources={}", keys.len());
+        Self::notify(visitors, |v| v.on_scan_start(root, logger));
+        self.inspect_resources_for_change(registry, ids, visitors, writer, logger);
+        self.exit_dirs(None, visitors, logger);
+    }
 
-        self.inspect_resources_for_change(registry, keys, visitors, writer, logger);
+    /// Drives the visitors over registry entries under `root` in depth-first order without touching
+    /// the filesystem, e.g. for a registry imported from a dump.
+    pub fn replay(&mut self, root: &str, registry: &Registry, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let ids = registry.walk(root);
+        info!("Replaying resources={}", ids.len());
+
+        Self::notify(visitors, |v| v.on_scan_start(root, logger));
+        for id in ids {
+            if let Some(metadata) = registry.get_by_id(id) {
+                let metadata = Arc::new(metadata);
+                self.exit_dirs(Some(metadata.get_path()), visitors, logger);
+                Self::visit(&metadata, visitors, writer, logger);
+                self.enter_dir(&metadata, visitors, logger);
+            }
+        }
+        self.exit_dirs(None, visitors, logger);
     }
 
-    fn inspect_resources_for_change(&mut self, registry: &mut HashMap<String, ResourceMetadata>, keys: Vec<String>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
-        for key in keys {
-            self.inspect_resource_for_change(registry, &key, visitors, writer, logger);
+    fn inspect_resources_for_change(&mut self, registry: &mut Registry, ids: Vec<EntryId>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        for id in ids {
+            self.inspect_resource_for_change(registry, id, visitors, writer, logger);
         }
     }
 
-    fn inspect_resource_for_change(&mut self, registry: &mut HashMap<String, ResourceMetadata>, key: &String, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
-        let resource = registry.get_mut(key);
-        match resource {
-            Some(ref cached_metadata) => {
+    fn inspect_resource_for_change(&mut self, registry: &mut Registry, id: EntryId, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        match registry.get_by_id(id) {
+            Some(cached_metadata) => {
+                let key = cached_metadata.get_path();
+                self.exit_dirs(Some(key), visitors, logger);
                 match fs::symlink_metadata(key) {
                     Ok(value) => {
                         let mtime = value.mtime();
@@ -80,93 +120,162 @@ impl ResourceScanner {
                             // Cached resource is invalid
                             debug!("Resource changed : is_dir={} {} new modified time {:?}", value.is_dir(), key, mtime);
 
-                            let current = ResourceMetadata::new(key, value.is_dir(), value.is_symlink(), mtime, value.len(), false);
+                            let current = Arc::new(Self::metadata(key, &value));
                             if !cached_metadata.is_dir() {
-                                self.sync_file(registry, &current, visitors, writer, logger);
+                                self.sync_file(registry, &cached_metadata, &current, visitors, writer, logger);
                             } else {
-                                self.sync_dir(registry, &current, visitors, writer, logger);
+                                self.sync_dir(registry, &cached_metadata, &current, visitors, writer, logger);
                             }
                         } else {
                             // Cached resource is fresh
-                            Self::visit(cached_metadata, visitors, writer, logger);
+                            let ownership = Some(Self::ownership(&value));
+                            if cached_metadata.ownership() != ownership {
+                                // chown and chmod leave the mtime alone
+                                registry.insert(&cached_metadata.clone().with_ownership(ownership));
+                            }
+                            let cached_metadata = Arc::new(cached_metadata.with_times(value.atime(), value.ctime()).with_ownership(ownership));
+                            Self::visit(&cached_metadata, visitors, writer, logger);
+                            self.enter_dir(&cached_metadata, visitors, logger);
                         }
                     }
-                    Err(_value) => {
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
                         debug!("change detected : {} deleted", key);
-                        if resource.unwrap().is_dir() {
+                        if cached_metadata.is_dir() {
                             self.deleted_dirs += 1;
                         } else {
                             self.deleted_files += 1;
                         }
                         registry.remove(key);
+                        Self::notify(visitors, |v| v.on_deleted(&cached_metadata, logger));
+                    }
+                    Err(e) => {
+                        warn!("Failed to inspect {}: {}", key, e);
+                        Self::notify(visitors, |v| v.on_error(key, &e, logger));
                     }
                 }
             }
             _ => {
-                // Shouldn't get here, all lookups are from keys that exist in cache
+                // Entry was removed earlier in this scan, e.g. the child of a deleted directory
             }
         }
     }
 
-    fn sync_file(&mut self, registry: &mut HashMap<String, ResourceMetadata>, current: &ResourceMetadata, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
-        Self::update(registry, current.get_path(), current);
+    fn sync_file(&mut self, registry: &mut Registry, previous: &ResourceMetadata, current: &Arc<ResourceMetadata>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        registry.insert(current);
         self.updated_files += 1;
+        Self::notify(visitors, |v| v.on_modified(previous, current, logger));
         Self::visit(current, visitors, writer, logger);
     }
 
-    fn sync_dir(&mut self, registry: &mut HashMap<String, ResourceMetadata>, current: &ResourceMetadata, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+    /// A known directory that changed. Its known children follow in the walk, so it's left open
+    /// for them once the new ones were added.
+    fn sync_dir(&mut self, registry: &mut Registry, previous: &ResourceMetadata, current: &Arc<ResourceMetadata>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
         debug!("Resource changed : {}", current.get_path());
 
-        Self::update(registry, current.get_path(), current);
+        registry.insert(current);
         self.updated_dirs += 1;
+        Self::notify(visitors, |v| v.on_modified(previous, current, logger));
         Self::visit(current, visitors, writer, logger);
+        self.enter_dir(current, visitors, logger);
+        self.add_children(registry, current, visitors, writer, logger);
+    }
 
-        match fs::read_dir(current.get_path()) {
-            Ok(children) => {
-                for child in children {
-                    match child {
-                        Ok(e) => {
-                            let resource = &e.path().to_string_lossy().into_owned();
-
-                            match registry.get(resource) {
-                                Some(_v) => {
-                                    // Resource is known so ignore. If it changed it was picked as it's scanned
-                                }
-                                None => {
-                                    // Resource not cached, validate existence & acquire metadata
-                                    if let Ok(c) = fs::symlink_metadata(resource) {
-                                        let new = ResourceMetadata::new(&resource.to_string(), c.is_dir(), c.is_symlink(), c.mtime(), c.len(), false);
-                                        Self::update(registry, &new.get_path().to_string(), &new);
-
-                                        if !c.is_dir() {
-                                            self.added_files += 1;
-                                            Self::visit(&new, visitors, writer, logger);
-                                        } else {
-                                            self.sync_dir(registry, &new, visitors, writer, logger);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => {}
+    /// A directory the registry didn't know, everything below it is new as well.
+    fn add_dir(&mut self, registry: &mut Registry, current: &Arc<ResourceMetadata>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        debug!("Resource added : {}", current.get_path());
+
+        registry.insert(current);
+        Self::notify(visitors, |v| v.on_added(current, logger));
+        Self::visit(current, visitors, writer, logger);
+        Self::notify(visitors, |v| v.on_dir_enter(current, logger));
+        self.add_children(registry, current, visitors, writer, logger);
+        Self::notify(visitors, |v| v.on_dir_exit(current, logger));
+    }
+
+    fn add_children(&mut self, registry: &mut Registry, dir: &Arc<ResourceMetadata>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let children = match fs::read_dir(dir.get_path()) {
+            Ok(children) => children,
+            Err(e) => {
+                Self::notify(visitors, |v| v.on_error(dir.get_path(), &e, logger));
+                return;
+            }
+        };
+
+        for child in children {
+            let resource = match child {
+                Ok(e) => e.path().to_string_lossy().into_owned(),
+                Err(e) => {
+                    Self::notify(visitors, |v| v.on_error(dir.get_path(), &e, logger));
+                    continue;
+                }
+            };
+
+            // Known resources are ignored, if they changed they're picked up as they're scanned
+            if registry.contains_key(&resource) {
+                continue;
+            }
+            // Resource not cached, validate existence & acquire metadata
+            match fs::symlink_metadata(&resource) {
+                Ok(c) => {
+                    let new = Arc::new(Self::metadata(&resource, &c));
+                    if !c.is_dir() {
+                        registry.insert(&new);
+                        self.added_files += 1;
+                        Self::notify(visitors, |v| v.on_added(&new, logger));
+                        Self::visit(&new, visitors, writer, logger);
+                    } else {
+                        self.add_dir(registry, &new, visitors, writer, logger);
                     }
                 }
+                Err(e) => Self::notify(visitors, |v| v.on_error(&resource, &e, logger)),
+            }
+        }
+    }
+
+    /// Opens `metadata` if it's a directory, until the walk leaves it.
+    fn enter_dir(&mut self, metadata: &Arc<ResourceMetadata>, visitors: &mut [&mut dyn Visitable], logger: &dyn EventHandler) {
+        if metadata.is_dir() && !metadata.is_symlink() {
+            Self::notify(visitors, |v| v.on_dir_enter(metadata, logger));
+            self.open_dirs.push(metadata.clone());
+        }
+    }
+
+    /// Exits the open directories `path` isn't below, innermost first, or all of them without a path.
+    fn exit_dirs(&mut self, path: Option<&str>, visitors: &mut [&mut dyn Visitable], logger: &dyn EventHandler) {
+        while let Some(dir) = self.open_dirs.last() {
+            if path.is_some_and(|p| Self::is_below(p, dir.get_path())) {
+                break;
             }
-            _ => {}
+            let dir = self.open_dirs.pop().unwrap();
+            Self::notify(visitors, |v| v.on_dir_exit(&dir, logger));
         }
     }
 
-    fn update(registry: &mut HashMap<String, ResourceMetadata>, k: &str, v: &ResourceMetadata) {
-        registry.entry(k.to_string()).and_modify(|existing| {
-            *existing = v.clone();
-        }).or_insert(v.clone());
+    fn metadata(path: &str, m: &fs::Metadata) -> ResourceMetadata {
+        ResourceMetadata::new(&path.to_string(), m.is_dir(), m.is_symlink(), m.mtime(), m.len(), false)
+            .with_times(m.atime(), m.ctime())
+            .with_ownership(Some(Self::ownership(m)))
+    }
+
+    fn ownership(m: &fs::Metadata) -> Ownership {
+        Ownership { uid: m.uid(), gid: m.gid(), mode: m.mode() }
     }
 
-    fn visit(cached: &ResourceMetadata, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+    fn is_below(path: &str, dir: &str) -> bool {
+        path.strip_prefix(dir).is_some_and(|rest| dir.ends_with('/') || rest.starts_with('/'))
+    }
+
+    fn visit(cached: &Arc<ResourceMetadata>, visitors: &mut [&mut dyn Visitable], writer: &mut dyn io::Write, logger: &dyn EventHandler) {
         for visitor in &mut *visitors {
             visitor.visit(cached, writer, logger);
         }
     }
+
+    fn notify(visitors: &mut [&mut dyn Visitable], mut hook: impl FnMut(&mut dyn Visitable)) {
+        for visitor in &mut *visitors {
+            hook(&mut **visitor);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -180,7 +289,7 @@ mod tests {
     }
 
     impl Visitable for MockVisitor {
-        fn visit(&mut self, resource: &ResourceMetadata, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        fn visit(&mut self, resource: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
             // Mock implementation
             println!("test={} resource={}", self.test, resource.get_path());
         }
@@ -203,7 +312,7 @@ mod tests {
     #[test]
     fn test_full_scan() {
         let mut scanner = ResourceScanner::new();
-        let mut registry = HashMap::new();
+        let mut registry = Registry::new();
         let mut buffer: Vec<u8> = Vec::new();
         let mut writer = io::BufWriter::new(&mut buffer);
 
@@ -226,7 +335,7 @@ mod tests {
     #[test]
     fn test_incremental_scan() {
         let mut scanner = ResourceScanner::new();
-        let mut registry = HashMap::new();
+        let mut registry = Registry::new();
         let mut v = MockVisitor::new(&String::from("test_incremental_scan"));
 
         let mut visitors: Vec<&mut dyn Visitable> = Vec::new();
@@ -242,7 +351,7 @@ mod tests {
         // Register root
         let p = Path::new(&td);
         let m = ResourceMetadata::new(&td, p.is_dir(), p.is_symlink(), 0, 1024, false);
-        registry.insert(td.clone(), m);
+        registry.insert(&m);
 
         let mut buffer: Vec<u8> = Vec::new();
         let mut writer = io::BufWriter::new(&mut buffer);
@@ -254,5 +363,142 @@ mod tests {
         assert_eq!(registry.len(), 2);
     }
 
+    struct RecordingVisitor {
+        visited: Vec<String>,
+    }
+
+    impl Visitable for RecordingVisitor {
+        fn visit(&mut self, resource: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+            self.visited.push(resource.get_path().clone());
+        }
+
+        fn recap(&mut self, _w: &mut dyn io::Write, _logger: &dyn EventHandler) {}
+
+        fn name(&self) -> &'static str {
+            "recording visitor"
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut scanner = ResourceScanner::new();
+        let mut registry = Registry::new();
+        for (p, is_dir) in [("/r", true), ("/r/b", true), ("/r/b/f", false), ("/r/a", false), ("/other", true)] {
+            registry.insert(&ResourceMetadata::new(&p.to_string(), is_dir, false, 0, 0, false));
+        }
+
+        let mut v = RecordingVisitor { visited: Vec::new() };
+        let mut visitors: Vec<&mut dyn Visitable> = vec![&mut v];
+        let mut buffer: Vec<u8> = Vec::new();
+        let logger = NoopEventHandler{};
+
+        scanner.replay("/r", &registry, &mut visitors, &mut buffer, &logger);
+
+        assert_eq!(v.visited, vec!["/r", "/r/a", "/r/b", "/r/b/f"]);
+    }
+
+    /// Records the hooks called, with paths relative to `root`.
+    struct LifecycleVisitor {
+        root: String,
+        events: Vec<String>,
+    }
+
+    impl LifecycleVisitor {
+        fn record(&mut self, event: &str, path: &str) {
+            let path = path.strip_prefix(&self.root).unwrap_or(path);
+            self.events.push(format!("{} {}", event, if path.is_empty() { "/" } else { path }));
+        }
+    }
+
+    impl Visitable for LifecycleVisitor {
+        fn visit(&mut self, resource: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+            self.record("visit", resource.get_path());
+        }
+
+        fn recap(&mut self, _w: &mut dyn io::Write, _logger: &dyn EventHandler) {}
+
+        fn name(&self) -> &'static str {
+            "lifecycle visitor"
+        }
+
+        fn on_scan_start(&mut self, root: &str, _logger: &dyn EventHandler) {
+            self.record("start", root);
+        }
+
+        fn on_dir_enter(&mut self, metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+            self.record("enter", metadata.get_path());
+        }
+
+        fn on_dir_exit(&mut self, metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+            self.record("exit", metadata.get_path());
+        }
+
+        fn on_added(&mut self, metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+            self.record("added", metadata.get_path());
+        }
+
+        fn on_modified(&mut self, _previous: &ResourceMetadata, current: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+            self.record("modified", current.get_path());
+        }
+
+        fn on_deleted(&mut self, metadata: &ResourceMetadata, _logger: &dyn EventHandler) {
+            self.record("deleted", metadata.get_path());
+        }
+
+        fn on_error(&mut self, path: &str, _error: &io::Error, _logger: &dyn EventHandler) {
+            self.record("error", path);
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().to_string_lossy().to_string();
+        let sub = temp_dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("b"), "b").unwrap();
+        let mut registry = Registry::new();
+        let logger = NoopEventHandler{};
+
+        let mut v = LifecycleVisitor { root: root.clone(), events: Vec::new() };
+        ResourceScanner::new().full_scan(&mut registry, &root, &mut [&mut v], &mut io::sink(), &logger);
+
+        assert_eq!(v.events, vec![
+            "start /", "added /", "visit /", "enter /",
+            "added /sub", "visit /sub", "enter /sub",
+            "added /sub/b", "visit /sub/b",
+            "exit /sub", "exit /",
+        ]);
+
+        // The directory changes under a stale registry entry, one file replaced by another
+        let sub_path = sub.to_string_lossy().to_string();
+        registry.insert(&ResourceMetadata::new(&sub_path, true, false, 0, 0, false));
+        fs::remove_file(sub.join("b")).unwrap();
+        fs::write(sub.join("c"), "c").unwrap();
+
+        let mut v = LifecycleVisitor { root: root.clone(), events: Vec::new() };
+        ResourceScanner::new().incremental_scan(&root, &mut registry, &mut [&mut v], &mut io::sink(), &logger);
+
+        assert_eq!(v.events, vec![
+            "start /", "visit /", "enter /",
+            "modified /sub", "visit /sub", "enter /sub",
+            "added /sub/c", "visit /sub/c",
+            "deleted /sub/b",
+            "exit /sub", "exit /",
+        ]);
+        assert!(!registry.contains_key(&sub.join("b").to_string_lossy()));
+    }
+
+    #[test]
+    fn test_full_scan_reports_errors() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("missing").to_string_lossy().to_string();
+        let mut v = LifecycleVisitor { root: String::new(), events: Vec::new() };
+
+        ResourceScanner::new().full_scan(&mut Registry::new(), &missing, &mut [&mut v], &mut io::sink(), &NoopEventHandler{});
+
+        assert_eq!(v.events, vec![format!("start {}", missing), format!("error {}", missing)]);
+    }
+
     // Add more test cases for inspect_resource_for_change, sync_file, sync_dir, and other functions as needed.
 }
\ No newline at end of file
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use log::{debug, warn};
+use xxhash_rust::xxh3::xxh3_64;
+use crate::services::file_api::file_type_detector::FileTypeDetector;
+use crate::services::file_api::text_signature_service::TextSignatureService;
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::state::text_cluster::{SimilarText, TextCluster, TextSignature, TextSignatureAlgorithm};
+use crate::util::bk_tree::BkTree;
+use crate::util::cancellation_token::CancellationToken;
+use crate::util::disjoint_set::DisjointSet;
+
+const TEXT_MIME_TYPES: [&str; 5] = ["application/json", "application/xml", "application/javascript", "application/x-sh", "application/toml"];
+const MINHASH_BAND_ROWS: usize = 4;
+
+struct Candidate {
+    path: String,
+    size_bytes: u64,
+    modified: i64,
+}
+
+/// Finds text files that are mostly the same, like rotated logs, CSV exports and snapshots of
+/// a source tree. Candidates are picked by MIME type while the scan runs, `detect` signs them
+/// and clusters files at least `threshold` similar, directly or through other files of the
+/// cluster. Files larger than `max_size_bytes` are left out as reading them would dominate
+/// the scan, files that can't be read or are binary are logged and skipped.
+///
+/// MinHash signatures are paired up through locality sensitive hashing of bands of the
+/// signature, SimHash signatures through a `BkTree`, and every pair is checked against the
+/// threshold before it's clustered. A band bucket keeps one file per cluster, so thousands of
+/// near copies sharing a band aren't all paired with each other.
+pub(crate) struct TextSimilarityDetector {
+    signature_service: Box<dyn TextSignatureService>,
+    file_type_detector: Box<dyn FileTypeDetector>,
+    threshold: f64,
+    max_size_bytes: u64,
+    cancellation: CancellationToken,
+    candidates: Vec<Candidate>,
+}
+
+impl TextSimilarityDetector {
+    pub(crate) fn new(signature_service: Box<dyn TextSignatureService>, file_type_detector: Box<dyn FileTypeDetector>, threshold: f64, max_size_bytes: u64, cancellation: CancellationToken) -> Self {
+        TextSimilarityDetector { signature_service, file_type_detector, threshold: threshold.clamp(0.0, 1.0), max_size_bytes, cancellation, candidates: Vec::new() }
+    }
+
+    pub(crate) fn algorithm(&self) -> TextSignatureAlgorithm {
+        self.signature_service.algorithm()
+    }
+
+    pub(crate) fn add(&mut self, metadata: &ResourceMetadata) {
+        if !metadata.is_file() || metadata.is_symlink() || metadata.size_bytes() == 0 || metadata.size_bytes() > self.max_size_bytes {
+            return;
+        }
+        match self.file_type_detector.get_file_type(metadata.get_path()) {
+            Ok(mime) if mime.starts_with("text/") || TEXT_MIME_TYPES.contains(&mime.as_str()) => self.candidates.push(Candidate {
+                path: metadata.get_path().clone(),
+                size_bytes: metadata.size_bytes(),
+                modified: metadata.modified(),
+            }),
+            Ok(_) => {}
+            Err(e) => debug!("No file type for {}: {}", metadata.get_path(), e),
+        }
+    }
+
+    /// Clusters of two or more files, the cluster wasting the most bytes first. Stops early,
+    /// returning `None`, when cancelled. `progress` gets the number of files signed so far.
+    pub(crate) fn detect(&self, progress: &mut dyn FnMut(usize, usize)) -> Option<Vec<TextCluster>> {
+        let mut signed: Vec<(&Candidate, TextSignature)> = Vec::with_capacity(self.candidates.len());
+        for (i, candidate) in self.candidates.iter().enumerate() {
+            if self.cancellation.is_cancelled() {
+                return None;
+            }
+            match self.signature_service.signature(&candidate.path) {
+                Ok(signature) => signed.push((candidate, signature)),
+                Err(e) => warn!("Skipping text {}: {}", candidate.path, e),
+            }
+            progress(i + 1, self.candidates.len());
+        }
+
+        let mut similar = self.cluster(&signed);
+        let mut clusters: Vec<TextCluster> = similar
+            .groups()
+            .into_iter()
+            .map(|mut members| {
+                members.sort_by(|&a, &b| signed[b].0.modified.cmp(&signed[a].0.modified).then_with(|| signed[a].0.path.cmp(&signed[b].0.path)));
+                let first = &signed[members[0]].1;
+                let files = members
+                    .into_iter()
+                    .map(|m| SimilarText { path: signed[m].0.path.clone(), size_bytes: signed[m].0.size_bytes, similarity: first.similarity(&signed[m].1) })
+                    .collect();
+                TextCluster { files }
+            })
+            .collect();
+        clusters.sort_by(|a, b| b.wasted_bytes().cmp(&a.wasted_bytes()).then_with(|| a.files[0].path.cmp(&b.files[0].path)));
+        Some(clusters)
+    }
+
+    /// Joins files sharing a band of their MinHash signature or with SimHash signatures within
+    /// the threshold. A file only goes into a band bucket when no file of its cluster is in there
+    /// yet, it's reached through that one.
+    fn cluster(&self, signed: &[(&Candidate, TextSignature)]) -> DisjointSet {
+        let mut similar = DisjointSet::new(signed.len());
+        let mut tree = BkTree::default();
+        let mut bands: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+        let max_distance = ((1.0 - self.threshold) * 64.0).floor() as u32;
+
+        for (i, (_, signature)) in signed.iter().enumerate() {
+            match signature {
+                TextSignature::MinHash(minimums) => {
+                    for (band, rows) in minimums.chunks(MINHASH_BAND_ROWS).enumerate() {
+                        let key = xxh3_64(&rows.iter().flat_map(|r| r.to_le_bytes()).collect::<Vec<u8>>());
+                        let bucket = bands.entry((band, key)).or_default();
+                        let mut represented = false;
+                        for &j in bucket.iter() {
+                            if similar.find(j) == similar.find(i) || signature.similarity(&signed[j].1) >= self.threshold {
+                                similar.union(i, j);
+                                represented = true;
+                            }
+                        }
+                        if !represented {
+                            bucket.push(i);
+                        }
+                    }
+                }
+                TextSignature::SimHash(hash) => {
+                    for j in tree.within(*hash, max_distance) {
+                        similar.union(i, j);
+                    }
+                    tree.insert(*hash, i);
+                }
+            }
+        }
+        similar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::tempdir;
+    use super::*;
+    use crate::services::file_impl::mime_guess_file_type_detector::MimeGuessFileTypeDetector;
+    use crate::services::file_impl::shingling_text_signature_service::ShinglingTextSignatureService;
+
+    fn report(count: usize, edited: usize) -> String {
+        (0..count).map(|i| if i == edited { "totally different row\n".to_string() } else { format!("{},name {},{}\n", i, i, i * 31) }).collect()
+    }
+
+    #[test]
+    fn test_clusters() {
+        for algorithm in [TextSignatureAlgorithm::MinHash, TextSignatureAlgorithm::SimHash] {
+            let dir = tempdir().unwrap();
+            let mut detector = TextSimilarityDetector::new(Box::new(ShinglingTextSignatureService::new(algorithm)), Box::new(MimeGuessFileTypeDetector), 0.8, 1024 * 1024, CancellationToken::new());
+            let files = [
+                ("export-1.csv", report(400, 5), 1),
+                ("export-2.csv", report(400, 300), 2),
+                ("notes.txt", "something else entirely, not a report at all".repeat(20), 3),
+                ("export.bin", report(400, 5), 4),
+            ];
+            for (name, content, modified) in files {
+                let path = dir.path().join(name);
+                fs::write(&path, &content).unwrap();
+                detector.add(&ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, modified, content.len() as u64, false));
+            }
+
+            let mut reported = Vec::new();
+            let clusters = detector.detect(&mut |done, total| reported.push((done, total))).unwrap();
+
+            assert_eq!(clusters.len(), 1, "{}", algorithm);
+            let names: Vec<&str> = clusters[0].files.iter().map(|f| f.path.rsplit('/').next().unwrap()).collect();
+            assert_eq!(names, vec!["export-2.csv", "export-1.csv"], "{}", algorithm);
+            assert_eq!(clusters[0].files[0].similarity, 1.0);
+            assert!(clusters[0].files[1].similarity >= 0.8);
+            assert_eq!(reported.last(), Some(&(3, 3)));
+        }
+    }
+
+    #[test]
+    fn test_clusters_many_copies() {
+        let dir = tempdir().unwrap();
+        let mut detector = TextSimilarityDetector::new(Box::new(ShinglingTextSignatureService::new(TextSignatureAlgorithm::MinHash)), Box::new(MimeGuessFileTypeDetector), 0.8, 1024 * 1024, CancellationToken::new());
+        for i in 0..50 {
+            let path = dir.path().join(format!("rotated-{}.log", i));
+            let content = report(200, i);
+            fs::write(&path, &content).unwrap();
+            detector.add(&ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, i as i64, content.len() as u64, false));
+        }
+
+        let clusters = detector.detect(&mut |_, _| {}).unwrap();
+
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].files.len(), 50);
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "text").unwrap();
+        let cancellation = CancellationToken::new();
+        let mut detector = TextSimilarityDetector::new(Box::new(ShinglingTextSignatureService::new(TextSignatureAlgorithm::MinHash)), Box::new(MimeGuessFileTypeDetector), 0.8, 1024, cancellation.clone());
+        detector.add(&ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, 0, 4, false));
+        cancellation.cancel();
+
+        assert!(detector.detect(&mut |_, _| {}).is_none());
+    }
+}
@@ -0,0 +1,380 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::io;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{error, warn};
+use serde_json::json;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::state::age_histogram::{SubtreeAges, DAY_SECONDS};
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::util::mount_info::{find_mount, mounts, Mount};
+use crate::util::util::{add_groupings_u64, subtrees, trim_dir};
+
+struct OpenDirectory {
+    path: String,
+    size_bytes: u64,
+    last_touched: i64,
+    atime_reliable: bool,
+    /// Stale directories right below, ranked once this one turns out not to be stale itself
+    stale_children: Vec<(u64, String, i64)>,
+}
+
+/// Ages the files of the scan by modification, access and status change time, histograms are
+/// kept for the scan root and every directory up to `depth` levels below it. Files and
+/// directories untouched, neither modified nor read, for `stale_days` are ranked by size, a
+/// directory counting as touched when anything below it is and only the outermost stale
+/// directories ranked. On a filesystem mounted `noatime`, at the scan root or below it, access
+/// times say nothing, so only modification times are used there and the recap warns about it.
+/// Everything is published as an `age-analysis-event`.
+pub(crate) struct AgeAnalysisVisitor {
+    stale_days: u64,
+    depth: usize,
+    k: usize,
+    now: i64,
+    scan_root: String,
+    mount: Option<Mount>,
+    atime_reliable: bool,
+    /// Mount table by mount point, to spot mounts below the scan root
+    mounts: HashMap<String, Mount>,
+    noatime_mounts: Vec<String>,
+    ages: BTreeMap<String, SubtreeAges>,
+    stale_files: BinaryHeap<Reverse<(u64, String, i64)>>,
+    stale_directories: BinaryHeap<Reverse<(u64, String, i64)>>,
+    open_directories: Vec<OpenDirectory>,
+}
+
+impl AgeAnalysisVisitor {
+    pub(crate) fn new(stale_days: u64, depth: usize, k: usize) -> Self {
+        AgeAnalysisVisitor {
+            stale_days,
+            depth,
+            k: k.max(1),
+            now: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64),
+            scan_root: "/".to_string(),
+            mount: None,
+            atime_reliable: true,
+            mounts: HashMap::new(),
+            noatime_mounts: Vec::new(),
+            ages: BTreeMap::new(),
+            stale_files: BinaryHeap::new(),
+            stale_directories: BinaryHeap::new(),
+            open_directories: Vec::new(),
+        }
+    }
+
+    fn set_mount(&mut self, mount: Option<Mount>, logger: &dyn EventHandler) {
+        self.atime_reliable = !mount.as_ref().is_some_and(Mount::is_noatime);
+        if !self.atime_reliable {
+            let warning = format!("{} is mounted noatime, access times are unreliable and only modification times are used", self.scan_root);
+            warn!("{}", warning);
+            logger.publish("age-analysis-warning-event", warning);
+        }
+        self.mount = mount;
+    }
+
+    /// Whether access times can be trusted in the current directory.
+    fn atime_reliable_here(&self) -> bool {
+        self.open_directories.last().map_or(self.atime_reliable, |d| d.atime_reliable)
+    }
+
+    /// Last time the resource was modified or, when access times can be trusted, read.
+    fn last_touched(&self, metadata: &ResourceMetadata) -> i64 {
+        match metadata.accessed() {
+            Some(accessed) if self.atime_reliable_here() => metadata.modified().max(accessed),
+            _ => metadata.modified(),
+        }
+    }
+
+    fn is_stale(&self, last_touched: i64) -> bool {
+        self.now - last_touched >= self.stale_days as i64 * DAY_SECONDS
+    }
+
+    fn push(heap: &mut BinaryHeap<Reverse<(u64, String, i64)>>, k: usize, entry: (u64, String, i64)) {
+        heap.push(Reverse(entry));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    fn exit_directory(&mut self) {
+        let directory = match self.open_directories.pop() {
+            Some(directory) => directory,
+            None => return,
+        };
+        let stale = directory.size_bytes > 0 && self.is_stale(directory.last_touched);
+        // A stale directory says it all for the stale ones below it
+        if !stale {
+            for child in directory.stale_children {
+                Self::push(&mut self.stale_directories, self.k, child);
+            }
+        }
+        let entry = (directory.size_bytes, directory.path, directory.last_touched);
+        match self.open_directories.last_mut() {
+            Some(parent) => {
+                parent.size_bytes += entry.0;
+                parent.last_touched = parent.last_touched.max(entry.2);
+                if stale {
+                    parent.stale_children.push(entry);
+                }
+            }
+            None if stale => Self::push(&mut self.stale_directories, self.k, entry),
+            None => {}
+        }
+    }
+
+    /// Largest first.
+    fn ranked(heap: &BinaryHeap<Reverse<(u64, String, i64)>>) -> Vec<(u64, String, i64)> {
+        let mut ranked: Vec<(u64, String, i64)> = heap.iter().map(|Reverse(e)| e.clone()).collect();
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        ranked
+    }
+
+    fn write_stale(&self, stale_files: &[(u64, String, i64)], stale_directories: &[(u64, String, i64)], w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "\nUntouched for {} days below {}:", self.stale_days, self.scan_root)?;
+        if !self.atime_reliable {
+            writeln!(w, "    mounted noatime, by modification time only")?;
+        }
+        for mount_point in &self.noatime_mounts {
+            writeln!(w, "    {} mounted noatime, by modification time only there", mount_point)?;
+        }
+        for (size_bytes, path, last_touched) in stale_directories.iter().chain(stale_files.iter()) {
+            writeln!(w, "{:>16} bytes, {:>6} days {}", add_groupings_u64(*size_bytes), (self.now - last_touched) / DAY_SECONDS, path)?;
+        }
+        Ok(())
+    }
+
+    fn rows(&self, ranked: &[(u64, String, i64)]) -> Vec<serde_json::Value> {
+        ranked
+            .iter()
+            .map(|(size_bytes, path, last_touched)| json!({
+                "path": path,
+                "size_bytes": size_bytes,
+                "last_touched": last_touched,
+                "days": (self.now - last_touched).max(0) / DAY_SECONDS,
+            }))
+            .collect()
+    }
+}
+
+impl Visitable for AgeAnalysisVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        if metadata.is_symlink() {
+            return;
+        }
+        let last_touched = self.last_touched(metadata);
+        if metadata.is_dir() {
+            // A directory is modified when entries are added or removed
+            if let Some(parent) = self.open_directories.last_mut() {
+                parent.last_touched = parent.last_touched.max(metadata.modified());
+            }
+            return;
+        }
+
+        let size_bytes = metadata.size_bytes();
+        let accessed = metadata.accessed().filter(|_| self.atime_reliable_here());
+        for subtree in subtrees(&self.scan_root, metadata.get_path(), self.depth) {
+            let ages = self.ages.entry(subtree).or_default();
+            ages.modified.add(self.now, metadata.modified(), size_bytes);
+            if let Some(accessed) = accessed {
+                ages.accessed.add(self.now, accessed, size_bytes);
+            }
+            if let Some(changed) = metadata.changed() {
+                ages.changed.add(self.now, changed, size_bytes);
+            }
+        }
+
+        if self.is_stale(last_touched) {
+            Self::push(&mut self.stale_files, self.k, (size_bytes, metadata.get_path().clone(), last_touched));
+        }
+        if let Some(directory) = self.open_directories.last_mut() {
+            directory.size_bytes += size_bytes;
+            directory.last_touched = directory.last_touched.max(last_touched);
+        }
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        while !self.open_directories.is_empty() {
+            self.exit_directory();
+        }
+        let stale_files = Self::ranked(&self.stale_files);
+        let stale_directories = Self::ranked(&self.stale_directories);
+
+        if let Err(e) = self.write_stale(&stale_files, &stale_directories, w) {
+            error!("Failed to write the age analysis recap: {}", e);
+        }
+
+        let subtrees: Vec<_> = self.ages
+            .iter()
+            .map(|(path, ages)| json!({"path": path, "modified": ages.modified, "accessed": ages.accessed, "changed": ages.changed}))
+            .collect();
+        let payload = json!({
+            "root": self.scan_root,
+            "now": self.now,
+            "stale_days": self.stale_days,
+            "atime_reliable": self.atime_reliable,
+            "mount_point": self.mount.as_ref().map(|m| &m.mount_point),
+            "mount_options": self.mount.as_ref().map(|m| &m.options),
+            "noatime_mounts": self.noatime_mounts,
+            "subtrees": subtrees,
+            "stale_files": self.rows(&stale_files),
+            "stale_directories": self.rows(&stale_directories),
+        });
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("age-analysis-event", payload),
+            Err(e) => error!("Failed to serialize age analysis: {}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "AgeAnalysisVisitor"
+    }
+
+    fn on_scan_start(&mut self, root: &str, logger: &dyn EventHandler) {
+        self.scan_root = trim_dir(root);
+        let mounts = mounts();
+        self.set_mount(find_mount(&mounts, &self.scan_root).cloned(), logger);
+        // Later mounts over the same point hide earlier ones
+        self.mounts = mounts.into_iter().map(|m| (m.mount_point.clone(), m)).collect();
+    }
+
+    fn on_dir_enter(&mut self, metadata: &Arc<ResourceMetadata>, logger: &dyn EventHandler) {
+        let path = metadata.get_path();
+        let mut atime_reliable = self.atime_reliable_here();
+        if let Some(mount) = self.mounts.get(path).filter(|_| *path != self.scan_root) {
+            atime_reliable = !mount.is_noatime();
+            if !atime_reliable {
+                let warning = format!("{} is mounted noatime, access times below it are unreliable and only modification times are used", path);
+                warn!("{}", warning);
+                logger.publish("age-analysis-warning-event", warning);
+                self.noatime_mounts.push(path.clone());
+            }
+        }
+        self.open_directories.push(OpenDirectory { path: path.clone(), size_bytes: 0, last_touched: metadata.modified(), atime_reliable, stale_children: Vec::new() });
+    }
+
+    fn on_dir_exit(&mut self, _metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+        self.exit_directory();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+
+    const NOW: i64 = 1_000 * DAY_SECONDS;
+
+    fn metadata(path: &str, is_dir: bool, size: u64, modified_days: i64, accessed_days: i64) -> Arc<ResourceMetadata> {
+        Arc::new(ResourceMetadata::new(&path.to_string(), is_dir, false, NOW - modified_days * DAY_SECONDS, size, false)
+            .with_times(NOW - accessed_days * DAY_SECONDS, NOW - modified_days * DAY_SECONDS))
+    }
+
+    fn scan(visitor: &mut AgeAnalysisVisitor, logger: &RecordingEventHandler) -> serde_json::Value {
+        let mut writer = Vec::new();
+        let (r, old, new) = (metadata("/r", true, 0, 400, 0), metadata("/r/old", true, 0, 400, 0), metadata("/r/new", true, 0, 400, 0));
+        visitor.on_dir_enter(&r, logger);
+        visitor.visit(&old, &mut writer, logger);
+        visitor.on_dir_enter(&old, logger);
+        visitor.visit(&metadata("/r/old/a.iso", false, 500, 800, 800), &mut writer, logger);
+        visitor.visit(&metadata("/r/old/b.iso", false, 300, 500, 500), &mut writer, logger);
+        visitor.on_dir_exit(&old, logger);
+        visitor.visit(&new, &mut writer, logger);
+        visitor.on_dir_enter(&new, logger);
+        visitor.visit(&metadata("/r/new/read.pdf", false, 200, 600, 2), &mut writer, logger);
+        visitor.visit(&metadata("/r/new/log", false, 10, 1, 1), &mut writer, logger);
+        visitor.on_dir_exit(&new, logger);
+        visitor.on_dir_exit(&r, logger);
+        visitor.recap(&mut writer, logger);
+        serde_json::from_str(&logger.messages("age-analysis-event")[0]).unwrap()
+    }
+
+    fn paths(rows: &serde_json::Value) -> Vec<&str> {
+        rows.as_array().unwrap().iter().map(|r| r["path"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_stale_files_and_directories() {
+        let logger = RecordingEventHandler::default();
+        let mut visitor = AgeAnalysisVisitor::new(365, 1, 10);
+        visitor.now = NOW;
+        visitor.scan_root = "/r".to_string();
+        let event = scan(&mut visitor, &logger);
+
+        assert_eq!(event["atime_reliable"], true);
+        assert_eq!(paths(&event["stale_files"]), vec!["/r/old/a.iso", "/r/old/b.iso"]);
+        assert_eq!(event["stale_files"][0]["days"], 800);
+        assert_eq!(paths(&event["stale_directories"]), vec!["/r/old"]);
+        assert_eq!(event["stale_directories"][0]["size_bytes"], 800);
+
+        assert_eq!(paths(&event["subtrees"]), vec!["/r", "/r/new", "/r/old"]);
+        let root = &event["subtrees"][0];
+        assert_eq!(root["modified"][6], json!({"label": "over 2 years", "files": 1, "size_bytes": 500}));
+        assert_eq!(root["accessed"][0], json!({"label": "under a week", "files": 2, "size_bytes": 210}));
+    }
+
+    #[test]
+    fn test_noatime_falls_back_to_modified() {
+        let logger = RecordingEventHandler::default();
+        let mut visitor = AgeAnalysisVisitor::new(365, 0, 10);
+        visitor.now = NOW;
+        visitor.scan_root = "/r".to_string();
+        let mount = Mount { mount_point: "/".to_string(), fs_type: "xfs".to_string(), options: vec!["rw".to_string(), "noatime".to_string()] };
+        visitor.set_mount(Some(mount), &logger);
+        let event = scan(&mut visitor, &logger);
+
+        assert_eq!(logger.messages("age-analysis-warning-event").len(), 1);
+        assert_eq!(event["atime_reliable"], false);
+        assert_eq!(paths(&event["stale_files"]), vec!["/r/old/a.iso", "/r/old/b.iso", "/r/new/read.pdf"]);
+        assert_eq!(paths(&event["stale_directories"]), vec!["/r/old"]);
+        assert_eq!(event["subtrees"][0]["accessed"][0]["files"], 0);
+    }
+
+    #[test]
+    fn test_noatime_mount_below_root() {
+        let logger = RecordingEventHandler::default();
+        let mut visitor = AgeAnalysisVisitor::new(365, 0, 10);
+        visitor.now = NOW;
+        visitor.scan_root = "/r".to_string();
+        let mount = Mount { mount_point: "/r/new".to_string(), fs_type: "xfs".to_string(), options: vec!["rw".to_string(), "noatime".to_string()] };
+        visitor.mounts.insert(mount.mount_point.clone(), mount);
+        let event = scan(&mut visitor, &logger);
+
+        assert_eq!(logger.messages("age-analysis-warning-event").len(), 1);
+        assert_eq!(event["atime_reliable"], true);
+        assert_eq!(event["noatime_mounts"], json!(["/r/new"]));
+        assert_eq!(paths(&event["stale_files"]), vec!["/r/old/a.iso", "/r/old/b.iso", "/r/new/read.pdf"]);
+        // Only the files below /r/old were read on a mount keeping access times
+        assert_eq!(event["subtrees"][0]["accessed"][0]["files"], 0);
+        assert_eq!(event["subtrees"][0]["accessed"][6]["files"], 1);
+    }
+
+    #[test]
+    fn test_nested_stale_directories_dont_take_ranks() {
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = AgeAnalysisVisitor::new(365, 0, 2);
+        visitor.now = NOW;
+        visitor.scan_root = "/r".to_string();
+        let dir = |path: &str| metadata(path, true, 0, 400, 400);
+        visitor.on_dir_enter(&dir("/r"), &logger);
+        for (path, size) in [("/r/a", 0), ("/r/a/x", 90), ("/r/a/y", 5), ("/r/b", 50)] {
+            visitor.on_dir_enter(&dir(path), &logger);
+            if size > 0 {
+                visitor.visit(&metadata(&format!("{}/file", path), false, size, 400, 400), &mut writer, &logger);
+                visitor.on_dir_exit(&dir(path), &logger);
+            }
+            if path == "/r/a/y" {
+                visitor.on_dir_exit(&dir("/r/a"), &logger);
+            }
+        }
+        visitor.visit(&metadata("/r/log", false, 1, 1, 1), &mut writer, &logger);
+        visitor.on_dir_exit(&dir("/r"), &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let event: serde_json::Value = serde_json::from_str(&logger.messages("age-analysis-event")[0]).unwrap();
+        assert_eq!(paths(&event["stale_directories"]), vec!["/r/a", "/r/b"]);
+        assert_eq!(event["stale_directories"][0]["size_bytes"], 95);
+    }
+}
@@ -1,21 +1,52 @@
 use std::collections::hash_map::Entry::{Occupied, Vacant};
 use std::collections::HashMap;
 use std::io;
+use std::sync::Arc;
+use log::error;
+use serde_json::{json, Value};
 use crate::state::resource_metadata::ResourceMetadata;
+use crate::state::visitor_config::{DEFAULT_TREE_CHILDREN, DEFAULT_TREE_DEPTH};
 use crate::services::scanner_api::event_handler::EventHandler;
 use crate::services::scanner_api::visitable::Visitable;
 
+/// A directory with the files and directories directly in it and their bytes, and once the
+/// visitor rolled the tree up the same for its whole subtree, the directory itself included.
 #[derive(Debug, Default)]
-struct DirectoryNode {
-    name: String,
+pub(crate) struct DirectoryNode {
+    pub(crate) name: String,
     child_files: usize,
     child_dirs: usize,
     total_size: u64,
-    children: HashMap<String, DirectoryNode>,
+    // Size of the directory entry itself and of the files directly in it
+    own_size: u64,
+    file_size: u64,
+    pub(crate) cumulative_files: usize,
+    pub(crate) cumulative_dirs: usize,
+    pub(crate) cumulative_size: u64,
+    pub(crate) children: HashMap<String, DirectoryNode>,
+    /// Only recorded by a visitor created `with_files`
+    pub(crate) files: Vec<FileEntry>,
 }
 
+#[derive(Debug)]
+pub(crate) struct FileEntry {
+    pub(crate) name: String,
+    pub(crate) size_bytes: u64,
+    pub(crate) is_symlink: bool,
+}
+
+/// Builds the directory tree of the scan. The recap rolls the counts of every directory up into
+/// its ancestors, post-order so it doesn't depend on the order resources were visited in, writes
+/// the tree below the scan root and publishes it as a `directory-tree-event` for a treemap.
+/// The published tree stops `tree_depth` levels below the root and keeps the `tree_children`
+/// largest children of each directory, nodes with more say so, the UI can ask for the subtree
+/// of a node to drill into it.
 pub struct DirectoryAnalyzerVisitor {
     root: DirectoryNode,
+    track_files: bool,
+    scan_root: Option<String>,
+    tree_depth: usize,
+    tree_children: usize,
 }
 
 impl DirectoryAnalyzerVisitor {
@@ -23,31 +54,116 @@ impl DirectoryAnalyzerVisitor {
     pub fn new() -> Self {
         DirectoryAnalyzerVisitor {
             root: DirectoryNode::default(),
+            track_files: false,
+            scan_root: None,
+            tree_depth: DEFAULT_TREE_DEPTH,
+            tree_children: DEFAULT_TREE_CHILDREN,
+        }
+    }
+
+    /// Also keeps the name and size of every file in its directory node, for analyses that need
+    /// the full tree.
+    pub(crate) fn with_files() -> Self {
+        DirectoryAnalyzerVisitor {
+            track_files: true,
+            ..DirectoryAnalyzerVisitor::new()
         }
     }
 
-    // Recursive function to enumerate and display statistics
+    pub(crate) fn with_tree(mut self, depth: usize, max_children: usize) -> Self {
+        self.tree_depth = depth;
+        self.tree_children = max_children.max(1);
+        self
+    }
+
+    /// Node of the filesystem root `/`, its children are named after path components.
+    pub(crate) fn root(&self) -> &DirectoryNode {
+        &self.root
+    }
+
+    /// Sums the counts of each subtree into its cumulative ones.
+    fn rollup(node: &mut DirectoryNode) {
+        node.cumulative_files = node.child_files;
+        node.cumulative_dirs = node.child_dirs;
+        node.cumulative_size = node.own_size + node.file_size;
+        for child in node.children.values_mut() {
+            Self::rollup(child);
+            node.cumulative_files += child.cumulative_files;
+            node.cumulative_dirs += child.cumulative_dirs;
+            node.cumulative_size += child.cumulative_size;
+        }
+    }
+
+    /// Node and path of the scan root, the filesystem root when the scan didn't say.
+    fn scan_root(&self) -> Option<(&DirectoryNode, String)> {
+        match &self.scan_root {
+            Some(root) => root
+                .trim_start_matches('/')
+                .split('/')
+                .filter(|c| !c.is_empty())
+                .try_fold(&self.root, |node, component| node.children.get(component))
+                .map(|node| (node, root.clone())),
+            None => Some((&self.root, "/".to_string())),
+        }
+    }
+
+    /// Children largest first.
+    fn sorted_children(node: &DirectoryNode) -> Vec<&DirectoryNode> {
+        let mut children: Vec<&DirectoryNode> = node.children.values().collect();
+        children.sort_by(|a, b| b.cumulative_size.cmp(&a.cumulative_size).then_with(|| a.name.cmp(&b.name)));
+        children
+    }
+
+    fn tree_json(&self, node: &DirectoryNode, path: &str, depth: usize) -> Value {
+        let children = Self::sorted_children(node);
+        let mut tree = json!({
+            "name": node.name,
+            "path": path,
+            "size_bytes": node.cumulative_size,
+            "files": node.cumulative_files,
+            "dirs": node.cumulative_dirs,
+            "file_bytes": node.file_size,
+            "has_children": !children.is_empty(),
+        });
+        if depth == 0 {
+            return tree;
+        }
+
+        let (shown, omitted) = children.split_at(children.len().min(self.tree_children));
+        tree["children"] = shown
+            .iter()
+            .map(|child| {
+                let child_path = if path.ends_with('/') { format!("{}{}", path, child.name) } else { format!("{}/{}", path, child.name) };
+                self.tree_json(child, &child_path, depth - 1)
+            })
+            .collect();
+        if !omitted.is_empty() {
+            tree["omitted"] = json!({"count": omitted.len(), "size_bytes": omitted.iter().map(|c| c.cumulative_size).sum::<u64>()});
+        }
+        tree
+    }
+
     fn recap_recursive(&self, w: &mut dyn io::Write, node: &DirectoryNode, depth: usize) {
-        // Print information about the current node
-        write!(w,
-               "{:indent$}{}: {} files, {} directories, {} bytes\n",
+        writeln!(w,
+               "{:indent$}{}: {} files, {} directories, {} bytes",
                "",
                node.name,
-               node.child_files,
-               node.child_dirs,
-               node.total_size,
+               node.cumulative_files,
+               node.cumulative_dirs,
+               node.cumulative_size,
                indent = depth * 2
         ).expect("TODO: panic message");
 
-        // Recursively call the function for child nodes
-        for child_node in node.children.values() {
-            self.recap_recursive(w, child_node, depth + 1);
+        if depth < self.tree_depth {
+            for child_node in Self::sorted_children(node).into_iter().take(self.tree_children) {
+                self.recap_recursive(w, child_node, depth + 1);
+            }
         }
     }
 }
 
 impl Visitable for DirectoryAnalyzerVisitor {
-    fn visit(&mut self, metadata: &ResourceMetadata, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
         let path = metadata.get_path();
 
         let components: Vec<&str> = path.trim_start_matches('/').split('/').collect();
@@ -68,6 +184,14 @@ impl Visitable for DirectoryAnalyzerVisitor {
                         // at filename so update stats of parent dir
                         if metadata.is_file() {
                             current_node.child_files += 1;
+                            current_node.file_size += metadata.size_bytes();
+                            if self.track_files {
+                                current_node.files.push(FileEntry {
+                                    name: component.to_string(),
+                                    size_bytes: metadata.size_bytes(),
+                                    is_symlink: metadata.is_symlink(),
+                                });
+                            }
                         } else if metadata.is_dir() {
                             current_node.child_dirs += 1;
                         }
@@ -79,6 +203,7 @@ impl Visitable for DirectoryAnalyzerVisitor {
                     if !(i == components.len() - 1 && metadata.is_file()) {
                         let new_node = DirectoryNode {
                             name: component.to_string(),
+                            own_size: if i == components.len() - 1 { metadata.size_bytes() } else { 0 },
                             ..Default::default()
                         };
                         entry.insert(new_node)
@@ -90,29 +215,46 @@ impl Visitable for DirectoryAnalyzerVisitor {
         }
     }
 
-    fn recap(&mut self, w: &mut dyn io::Write, _logger: &dyn EventHandler) {
-        // Start the recursive enumeration from the root
-        self.recap_recursive(w, &self.root, 0);
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        Self::rollup(&mut self.root);
+        let Some((root, path)) = self.scan_root() else {
+            if let Err(e) = writeln!(w, "Directory Tree: {} was not scanned", self.scan_root.as_deref().unwrap_or_default()) {
+                error!("Failed to write the directory tree recap: {}", e);
+            }
+            return;
+        };
+        self.recap_recursive(w, root, 0);
+
+        let payload = json!({"root": path, "depth": self.tree_depth, "tree": self.tree_json(root, &path, self.tree_depth)});
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("directory-tree-event", payload),
+            Err(e) => error!("Failed to serialize directory tree: {}", e),
+        }
     }
 
     fn name(&self) -> &'static str {
         "DirectoryAnalyzerVisitor"
     }
+
+    fn on_scan_start(&mut self, root: &str, _logger: &dyn EventHandler) {
+        self.scan_root = Some(root.to_string());
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::services::scanner_impl::noop_event_handler::NoopEventHandler;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
     use super::*;
 
     #[test]
     fn test_directory_analyzer_visitor() {
         // Test data
-        let metadata1 = ResourceMetadata::new(&"/a".to_string(), true, false, 0, 96, false);
-        let metadata2 = ResourceMetadata::new(&"/a/foo.txt".to_string(), false, false, 0, 100, false);
-        let metadata3 = ResourceMetadata::new(&"/a/bar.txt".to_string(), false, false, 0, 150, false);
-        let metadata4 = ResourceMetadata::new(&"/a/b".to_string(), true, false, 0, 96, false);
-        let metadata5 = ResourceMetadata::new(&"/a/b/bif.txt".to_string(), false, false, 0, 75, false);
+        let metadata1 = Arc::new(ResourceMetadata::new(&"/a".to_string(), true, false, 0, 96, false));
+        let metadata2 = Arc::new(ResourceMetadata::new(&"/a/foo.txt".to_string(), false, false, 0, 100, false));
+        let metadata3 = Arc::new(ResourceMetadata::new(&"/a/bar.txt".to_string(), false, false, 0, 150, false));
+        let metadata4 = Arc::new(ResourceMetadata::new(&"/a/b".to_string(), true, false, 0, 96, false));
+        let metadata5 = Arc::new(ResourceMetadata::new(&"/a/b/bif.txt".to_string(), false, false, 0, 75, false));
 
         let mut visitor = DirectoryAnalyzerVisitor::new();
 
@@ -158,9 +300,9 @@ mod tests {
     #[test]
     fn test_recap_recursive() {
         // Test data
-        let metadata1 = ResourceMetadata::new(&"/a".to_string(), true, false, 0, 96, false);
-        let metadata2 = ResourceMetadata::new(&"/a/b".to_string(), true, false, 0, 96, false);
-        let metadata3 = ResourceMetadata::new(&"/a/b/c".to_string(), true, false, 0, 96, false);
+        let metadata1 = Arc::new(ResourceMetadata::new(&"/a".to_string(), true, false, 0, 96, false));
+        let metadata2 = Arc::new(ResourceMetadata::new(&"/a/b".to_string(), true, false, 0, 96, false));
+        let metadata3 = Arc::new(ResourceMetadata::new(&"/a/b/c".to_string(), true, false, 0, 96, false));
 
         let mut visitor = DirectoryAnalyzerVisitor::new();
 
@@ -179,9 +321,53 @@ mod tests {
 
         // Assert output
         let output_str = String::from_utf8(output).unwrap();
-        assert!(output_str.contains("a: 0 files, 1 directories, 96 bytes"));
-        assert!(output_str.contains("  b: 0 files, 1 directories, 96 bytes"));
-        assert!(output_str.contains("    c: 0 files, 0 directories, 0 bytes"));
+        assert!(output_str.contains("  a: 0 files, 2 directories, 288 bytes"));
+        assert!(output_str.contains("    b: 0 files, 1 directories, 192 bytes"));
+        assert!(output_str.contains("      c: 0 files, 0 directories, 96 bytes"));
+    }
+
+    #[test]
+    fn test_rollup_and_tree_event() {
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = DirectoryAnalyzerVisitor::new().with_tree(1, 1);
+
+        visitor.on_scan_start("/r", &logger);
+        for (path, is_dir, size) in [("/r", true, 0), ("/r/a", true, 10), ("/r/a/x", true, 10), ("/r/a/x/f", false, 100), ("/r/b", true, 10), ("/r/b/g", false, 5), ("/r/h", false, 1)] {
+            visitor.visit(&Arc::new(ResourceMetadata::new(&path.to_string(), is_dir, false, 0, size, false)), &mut writer, &logger);
+        }
+        visitor.recap(&mut writer, &logger);
+
+        let a = &visitor.root().children["r"].children["a"];
+        assert_eq!((a.cumulative_files, a.cumulative_dirs, a.cumulative_size), (1, 1, 120));
+
+        let messages = logger.messages("directory-tree-event");
+        let payload: serde_json::Value = serde_json::from_str(&messages[0]).unwrap();
+        let tree = &payload["tree"];
+        assert_eq!((tree["path"].as_str(), tree["size_bytes"].as_u64(), tree["files"].as_u64(), tree["dirs"].as_u64()), (Some("/r"), Some(136), Some(3), Some(3)));
+        // Only the largest child is shown and the depth limit stops below it
+        assert_eq!(tree["children"].as_array().unwrap().len(), 1);
+        assert_eq!(tree["children"][0]["path"], "/r/a");
+        assert_eq!(tree["children"][0]["has_children"], true);
+        assert!(tree["children"][0].get("children").is_none());
+        assert_eq!(tree["omitted"], serde_json::json!({"count": 1, "size_bytes": 15}));
+    }
+
+    #[test]
+    fn test_with_files() {
+        let logger = NoopEventHandler{};
+        let mut writer = Vec::new();
+        let mut visitor = DirectoryAnalyzerVisitor::with_files();
+
+        visitor.visit(&Arc::new(ResourceMetadata::new(&"/a".to_string(), true, false, 0, 96, false)), &mut writer, &logger);
+        visitor.visit(&Arc::new(ResourceMetadata::new(&"/a/foo.txt".to_string(), false, false, 0, 100, false)), &mut writer, &logger);
+        visitor.visit(&Arc::new(ResourceMetadata::new(&"/a/link".to_string(), false, true, 0, 7, false)), &mut writer, &logger);
+
+        let files = &visitor.root().children["a"].files;
+        assert_eq!(files.len(), 2);
+        assert_eq!((files[0].name.as_str(), files[0].size_bytes, files[0].is_symlink), ("foo.txt", 100, false));
+        assert!(files[1].is_symlink);
+        assert!(DirectoryAnalyzerVisitor::new().root().files.is_empty());
     }
 
     #[test]
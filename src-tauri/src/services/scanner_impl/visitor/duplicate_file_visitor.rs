@@ -0,0 +1,292 @@
+use std::io;
+use std::sync::Arc;
+use log::{error, warn};
+use serde_json::json;
+use crate::services::file_impl::streaming_content_comparator::StreamingContentComparator;
+use crate::services::file_impl::streaming_fingerprint_service::StreamingFingerprintService;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::services::scanner_impl::duplicate_detector::DuplicateDetector;
+use crate::services::scanner_impl::duplicate_directory_detector::DuplicateDirectoryDetector;
+use crate::services::scanner_impl::hashing_scheduler::HashingScheduler;
+use crate::services::scanner_impl::keeper_selector::KeeperSelector;
+use crate::services::scanner_impl::visitor::directory_analyzer_visitor::DirectoryAnalyzerVisitor;
+use crate::state::duplicate_directory::{DuplicateDirectorySet, SubsetDirectory};
+use crate::state::duplicate_set::DuplicateSet;
+use crate::state::fingerprint::FingerprintAlgorithm;
+use crate::state::hashing_progress::HashingProgress;
+use crate::state::keeper_policy::KeeperDecision;
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::util::cancellation_token::CancellationToken;
+
+const MIN_SIZE_BYTES: u64 = 1;
+const BLOCK_BYTES: u64 = 4 * 1024;
+const BUFFER_BYTES: usize = 1024 * 1024;
+// Also how often a rate capped scheduler throttles
+const PROGRESS_INTERVAL_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Collects files while the scan runs and finds duplicates among them once it's done, see
+/// `DuplicateDetector`. The duplicate sets are published as a `duplicate-event` and hashing of
+/// large files reports `fingerprint-progress-event`s with the files and bytes hashed and the
+/// time left. Copied directories found on top of the
+/// duplicate files are published as a `duplicate-directory-event`, see `with_directories`, and
+/// the copies a keeper policy picked as a `keeper-event`, see `with_keeper_selector`.
+pub(crate) struct DuplicateFileVisitor {
+    detector: DuplicateDetector,
+    duplicates: Vec<DuplicateSet>,
+    directories: Option<(DirectoryAnalyzerVisitor, DuplicateDirectoryDetector)>,
+    keeper_selector: Option<KeeperSelector>,
+}
+
+impl DuplicateFileVisitor {
+    /// With `verify` set, duplicates are compared byte for byte before they're reported.
+    pub(crate) fn new(algorithm: FingerprintAlgorithm, verify: bool, cancellation: CancellationToken) -> Self {
+        let fingerprint_service = StreamingFingerprintService::new(algorithm, BUFFER_BYTES, PROGRESS_INTERVAL_BYTES, cancellation.clone());
+        let mut detector = DuplicateDetector::new(MIN_SIZE_BYTES, BLOCK_BYTES, Box::new(fingerprint_service));
+        if verify {
+            detector = detector.with_verification(Box::new(StreamingContentComparator::new(BUFFER_BYTES, cancellation)));
+        }
+        DuplicateFileVisitor {
+            detector,
+            duplicates: Vec::new(),
+            directories: None,
+            keeper_selector: None,
+        }
+    }
+
+    /// Hashes with `scheduler` instead of one worker per core without a rate cap.
+    pub(crate) fn with_scheduler(mut self, scheduler: HashingScheduler) -> Self {
+        self.detector = self.detector.with_scheduler(scheduler);
+        self
+    }
+
+    /// Picks a keeper for every duplicate set and stages actions for the other copies.
+    pub(crate) fn with_keeper_selector(mut self, keeper_selector: KeeperSelector) -> Self {
+        self.keeper_selector = Some(keeper_selector);
+        self
+    }
+
+    /// Also reports identical directory trees and, with `include_subsets`, directories whose
+    /// files are all found in another one. Keeps the whole scanned tree in memory.
+    pub(crate) fn with_directories(mut self, include_subsets: bool) -> Self {
+        self.directories = Some((DirectoryAnalyzerVisitor::with_files(), DuplicateDirectoryDetector::new(MIN_SIZE_BYTES, include_subsets)));
+        self
+    }
+
+    /// Duplicate sets found by the last recap.
+    pub(crate) fn duplicates(&self) -> &[DuplicateSet] {
+        &self.duplicates
+    }
+}
+
+impl DuplicateFileVisitor {
+    fn write_duplicates(duplicates: &[DuplicateSet], wasted_bytes: u64, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "Duplicate Files: {} sets, {} wasted bytes", duplicates.len(), wasted_bytes)?;
+        for set in duplicates {
+            writeln!(w, "Wasted: {:>16}, Size: {:>16}, Copies: {}, {:?}", set.wasted_bytes(), set.size_bytes, set.paths.len(), set.verification)?;
+            for path in &set.paths {
+                writeln!(w, "    {}", path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_keepers(decisions: &[KeeperDecision], staged_bytes: u64, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "Keepers: {} sets, {} bytes staged", decisions.len(), staged_bytes)?;
+        for decision in decisions {
+            writeln!(w, "Keep {} ({})", decision.keeper, decision.reasons.join(", "))?;
+            for action in &decision.actions {
+                writeln!(w, "    {} {}", action.action, action.path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn recap_keepers(decisions: &[KeeperDecision], w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let staged_bytes: u64 = decisions.iter().flat_map(|d| &d.actions).map(|a| a.bytes).sum();
+
+        if let Err(e) = Self::write_keepers(decisions, staged_bytes, w) {
+            error!("Failed to write the keepers recap: {}", e);
+        }
+
+        match serde_json::to_string(&json!({"staged_bytes": staged_bytes, "decisions": decisions})) {
+            Ok(payload) => logger.publish("keeper-event", payload),
+            Err(e) => error!("Failed to serialize keeper decisions: {}", e),
+        }
+    }
+
+    fn write_directories(sets: &[DuplicateDirectorySet], subsets: &[SubsetDirectory], wasted_bytes: u64, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "Duplicate Directories: {} sets, {} wasted bytes, {} subsets", sets.len(), wasted_bytes, subsets.len())?;
+        for set in sets {
+            writeln!(w, "Wasted: {:>16}, Size: {:>16}, Copies: {}", set.wasted_bytes(), set.size_bytes, set.paths.len())?;
+            for path in &set.paths {
+                writeln!(w, "    {}", path)?;
+            }
+        }
+        for subset in subsets {
+            writeln!(w, "Reclaimable: {:>16}, {} is contained in {}", subset.size_bytes, subset.path, subset.superset)?;
+        }
+        Ok(())
+    }
+
+    fn recap_directories(sets: &[DuplicateDirectorySet], subsets: &[SubsetDirectory], w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let wasted_bytes: u64 = sets.iter().map(DuplicateDirectorySet::wasted_bytes).sum();
+        let reclaimable_bytes: u64 = subsets.iter().map(|s| s.size_bytes).sum();
+
+        if let Err(e) = Self::write_directories(sets, subsets, wasted_bytes, w) {
+            error!("Failed to write the duplicate directories recap: {}", e);
+        }
+
+        let sets: Vec<_> = sets
+            .iter()
+            .map(|s| json!({"size_bytes": s.size_bytes, "wasted_bytes": s.wasted_bytes(), "paths": s.paths}))
+            .collect();
+        let payload = json!({"wasted_bytes": wasted_bytes, "reclaimable_bytes": reclaimable_bytes, "sets": sets, "subsets": subsets});
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("duplicate-directory-event", payload),
+            Err(e) => error!("Failed to serialize duplicate directories: {}", e),
+        }
+    }
+}
+
+impl Visitable for DuplicateFileVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+        self.detector.add(metadata);
+        if let Some((tree, _)) = &mut self.directories {
+            tree.visit(metadata, writer, logger);
+        }
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let mut progress = |status: &HashingProgress| match serde_json::to_string(status) {
+            Ok(payload) => logger.publish("fingerprint-progress-event", payload),
+            Err(e) => error!("Failed to serialize hashing progress: {}", e),
+        };
+        self.duplicates = match self.detector.detect(&mut progress) {
+            Ok(duplicates) => duplicates,
+            Err(e) => {
+                warn!("Duplicate detection stopped: {}", e);
+                if let Err(e) = writeln!(w, "Duplicate Files: {}", e) {
+                    error!("Failed to write the duplicate files recap: {}", e);
+                }
+                return;
+            }
+        };
+        let wasted_bytes: u64 = self.duplicates.iter().map(DuplicateSet::wasted_bytes).sum();
+
+        if let Err(e) = Self::write_duplicates(&self.duplicates, wasted_bytes, w) {
+            error!("Failed to write the duplicate files recap: {}", e);
+        }
+
+        let sets: Vec<_> = self.duplicates
+            .iter()
+            .map(|s| json!({"size_bytes": s.size_bytes, "fingerprint": s.fingerprint, "verification": s.verification, "wasted_bytes": s.wasted_bytes(), "paths": s.paths}))
+            .collect();
+        match serde_json::to_string(&json!({"wasted_bytes": wasted_bytes, "sets": sets})) {
+            Ok(payload) => logger.publish("duplicate-event", payload),
+            Err(e) => error!("Failed to serialize duplicates: {}", e),
+        }
+
+        if let Some(selector) = &self.keeper_selector {
+            let decisions: Vec<KeeperDecision> = self.duplicates.iter().filter_map(|set| selector.select(set)).collect();
+            Self::recap_keepers(&decisions, w, logger);
+        }
+
+        if let Some((tree, detector)) = &self.directories {
+            let (sets, subsets) = detector.detect(tree.root(), &self.duplicates);
+            Self::recap_directories(&sets, &subsets, w, logger);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "DuplicateFileVisitor"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::tempdir;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+    use crate::state::keeper_policy::KeeperPolicy;
+    use super::*;
+
+    #[test]
+    fn test_recap_publishes_duplicates() {
+        let dir = tempdir().unwrap();
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = DuplicateFileVisitor::new(FingerprintAlgorithm::default(), true, CancellationToken::new());
+
+        for name in ["a", "b", "c"] {
+            let path = dir.path().join(name);
+            fs::write(&path, if name == "c" { "other" } else { "dupes" }).unwrap();
+            let metadata = Arc::new(ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, 0, 5, false));
+            visitor.visit(&metadata, &mut writer, &logger);
+        }
+        visitor.recap(&mut writer, &logger);
+
+        let messages = logger.messages("duplicate-event");
+        assert_eq!(messages.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&messages[0]).unwrap();
+        assert_eq!(payload["wasted_bytes"], 5);
+        assert_eq!(payload["sets"][0]["paths"].as_array().unwrap().len(), 2);
+        assert!(payload["sets"][0]["fingerprint"].as_str().unwrap().starts_with("xxh3-64:"));
+        assert_eq!(payload["sets"][0]["verification"], "verified");
+        assert_eq!(visitor.duplicates().len(), 1);
+        assert!(String::from_utf8(writer).unwrap().contains("Duplicate Files: 1 sets, 5 wasted bytes"));
+    }
+
+    #[test]
+    fn test_recap_publishes_duplicate_directories() {
+        let dir = tempdir().unwrap();
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = DuplicateFileVisitor::new(FingerprintAlgorithm::default(), false, CancellationToken::new()).with_directories(false);
+
+        for name in ["a", "b"] {
+            let sub = dir.path().join(name);
+            fs::create_dir(&sub).unwrap();
+            fs::write(sub.join("f"), "dupes").unwrap();
+            visitor.visit(&Arc::new(ResourceMetadata::new(&sub.to_string_lossy().to_string(), true, false, 0, 0, false)), &mut writer, &logger);
+            visitor.visit(&Arc::new(ResourceMetadata::new(&sub.join("f").to_string_lossy().to_string(), false, false, 0, 5, false)), &mut writer, &logger);
+        }
+        visitor.recap(&mut writer, &logger);
+
+        let messages = logger.messages("duplicate-directory-event");
+        assert_eq!(messages.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&messages[0]).unwrap();
+        assert_eq!(payload["wasted_bytes"], 5);
+        assert_eq!(payload["sets"][0]["paths"].as_array().unwrap().len(), 2);
+        assert!(payload["subsets"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recap_publishes_keepers() {
+        let dir = tempdir().unwrap();
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let selector = KeeperSelector::new(vec![KeeperPolicy::ShortestPath], "hardlink").unwrap();
+        let mut visitor = DuplicateFileVisitor::new(FingerprintAlgorithm::default(), false, CancellationToken::new()).with_keeper_selector(selector);
+
+        for name in ["copy", "original-name"] {
+            let path = dir.path().join(name);
+            fs::write(&path, "dupes").unwrap();
+            visitor.visit(&Arc::new(ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, 0, 5, false)), &mut writer, &logger);
+        }
+        visitor.recap(&mut writer, &logger);
+
+        let messages = logger.messages("keeper-event");
+        assert_eq!(messages.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&messages[0]).unwrap();
+        assert_eq!(payload["staged_bytes"], 5);
+        assert!(payload["decisions"][0]["keeper"].as_str().unwrap().ends_with("/copy"));
+        assert_eq!(payload["decisions"][0]["reasons"][0], "shortest path");
+        assert_eq!(payload["decisions"][0]["actions"][0]["action"], "hardlink");
+    }
+
+    #[test]
+    fn test_name_validation() {
+        assert_eq!("DuplicateFileVisitor", DuplicateFileVisitor::new(FingerprintAlgorithm::default(), true, CancellationToken::new()).name());
+    }
+}
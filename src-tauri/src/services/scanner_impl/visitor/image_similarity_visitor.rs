@@ -0,0 +1,122 @@
+use std::io;
+use std::sync::Arc;
+use log::{error, warn};
+use serde_json::json;
+use crate::services::file_impl::image_perceptual_hasher::ImagePerceptualHasher;
+use crate::services::file_impl::mime_guess_file_type_detector::MimeGuessFileTypeDetector;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::services::scanner_impl::image_similarity_detector::ImageSimilarityDetector;
+use crate::state::image_cluster::{ImageCluster, PerceptualHashAlgorithm};
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::util::cancellation_token::CancellationToken;
+
+const PROGRESS_INTERVAL_IMAGES: usize = 100;
+
+/// Collects images while the scan runs and clusters the ones that look alike once it's done,
+/// see `ImageSimilarityDetector`. Clusters are published as an `image-similarity-event`, hashing
+/// reports `image-hash-progress-event`s.
+pub(crate) struct ImageSimilarityVisitor {
+    max_distance: u32,
+    detector: ImageSimilarityDetector,
+    clusters: Vec<ImageCluster>,
+}
+
+impl ImageSimilarityVisitor {
+    /// Images whose hashes are at most `max_distance` of 64 bits apart end up in the same cluster.
+    pub(crate) fn new(algorithm: PerceptualHashAlgorithm, max_distance: u32, cancellation: CancellationToken) -> Self {
+        let detector = ImageSimilarityDetector::new(Box::new(ImagePerceptualHasher::new(algorithm)), Box::new(MimeGuessFileTypeDetector), max_distance, cancellation);
+        ImageSimilarityVisitor { max_distance, detector, clusters: Vec::new() }
+    }
+
+    fn write_clusters(clusters: &[ImageCluster], wasted_bytes: u64, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "Similar Images: {} clusters, {} wasted bytes", clusters.len(), wasted_bytes)?;
+        for cluster in clusters {
+            writeln!(w, "Wasted: {:>16}, Images: {}", cluster.wasted_bytes(), cluster.images.len())?;
+            for image in &cluster.images {
+                writeln!(w, "    {:>2} {}", image.distance, image.path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Visitable for ImageSimilarityVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        self.detector.add(metadata);
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let mut progress = |hashed: usize, total: usize| {
+            if hashed.is_multiple_of(PROGRESS_INTERVAL_IMAGES) || hashed == total {
+                logger.publish("image-hash-progress-event", json!({"hashed_images": hashed, "total_images": total}).to_string());
+            }
+        };
+        self.clusters = match self.detector.detect(&mut progress) {
+            Some(clusters) => clusters,
+            None => {
+                warn!("Image similarity detection was cancelled");
+                if let Err(e) = writeln!(w, "Similar Images: cancelled") {
+                    error!("Failed to write the similar images recap: {}", e);
+                }
+                return;
+            }
+        };
+        let wasted_bytes: u64 = self.clusters.iter().map(ImageCluster::wasted_bytes).sum();
+
+        if let Err(e) = Self::write_clusters(&self.clusters, wasted_bytes, w) {
+            error!("Failed to write the similar images recap: {}", e);
+        }
+
+        let clusters: Vec<_> = self.clusters
+            .iter()
+            .map(|c| json!({"wasted_bytes": c.wasted_bytes(), "images": c.images}))
+            .collect();
+        let payload = json!({"algorithm": self.detector.algorithm().name(), "max_distance": self.max_distance, "wasted_bytes": wasted_bytes, "clusters": clusters});
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("image-similarity-event", payload),
+            Err(e) => error!("Failed to serialize image clusters: {}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "ImageSimilarityVisitor"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, Rgb, RgbImage};
+    use tempfile::tempdir;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+    use super::*;
+
+    #[test]
+    fn test_recap_publishes_clusters() {
+        let dir = tempdir().unwrap();
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = ImageSimilarityVisitor::new(PerceptualHashAlgorithm::DHash, 4, CancellationToken::new());
+
+        let image = RgbImage::from_fn(64, 48, |x, y| Rgb([(x * 4) as u8, (y * 5) as u8, ((x ^ y) * 3) as u8]));
+        for (name, format) in [("a.png", ImageFormat::Png), ("b.jpg", ImageFormat::Jpeg)] {
+            let path = dir.path().join(name);
+            image.save_with_format(&path, format).unwrap();
+            let size = std::fs::metadata(&path).unwrap().len();
+            visitor.visit(&Arc::new(ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, 0, size, false)), &mut writer, &logger);
+        }
+        visitor.recap(&mut writer, &logger);
+
+        let messages = logger.messages("image-similarity-event");
+        assert_eq!(messages.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&messages[0]).unwrap();
+        assert_eq!(payload["algorithm"], "dhash");
+        assert_eq!(payload["clusters"][0]["images"].as_array().unwrap().len(), 2);
+        assert_eq!(logger.messages("image-hash-progress-event").len(), 1);
+    }
+
+    #[test]
+    fn test_name_validation() {
+        assert_eq!("ImageSimilarityVisitor", ImageSimilarityVisitor::new(PerceptualHashAlgorithm::DHash, 4, CancellationToken::new()).name());
+    }
+}
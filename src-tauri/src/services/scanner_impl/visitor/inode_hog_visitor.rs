@@ -0,0 +1,252 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use log::{error, warn};
+use serde_json::json;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::util::mount_info::{find_mount, inode_usage, mounts, InodeUsage, Mount};
+use crate::util::util::{add_groupings_u64, trim_dir};
+
+/// Entries directly in a directory, every one of them takes an inode.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct DirectoryCounts {
+    path: String,
+    entries: u64,
+    files: u64,
+    size_bytes: u64,
+    tiny_files: u64,
+}
+
+impl DirectoryCounts {
+    fn average_bytes(&self) -> u64 {
+        self.size_bytes.checked_div(self.files).unwrap_or(0)
+    }
+
+    fn row(&self) -> serde_json::Value {
+        json!({
+            "path": self.path,
+            "entries": self.entries,
+            "files": self.files,
+            "dirs": self.entries - self.files,
+            "size_bytes": self.size_bytes,
+            "tiny_files": self.tiny_files,
+            "average_bytes": self.average_bytes(),
+        })
+    }
+}
+
+/// Finds where the inodes go: directories ranked by the entries directly in them, by their
+/// tiny files and, among the ones with at least `min_files` files, by the smallest average file
+/// size. The recap adds the inode usage from statvfs of every filesystem the scan crossed into
+/// and publishes it all as an `inode-hog-event`.
+pub(crate) struct InodeHogVisitor {
+    k: usize,
+    tiny_file_bytes: u64,
+    min_files: u64,
+    scan_root: String,
+    open_directories: Vec<DirectoryCounts>,
+    by_entries: BinaryHeap<Reverse<(u64, DirectoryCounts)>>,
+    by_tiny_files: BinaryHeap<Reverse<(u64, DirectoryCounts)>>,
+    // Largest average on top, to be dropped first
+    by_average: BinaryHeap<(u64, DirectoryCounts)>,
+}
+
+impl InodeHogVisitor {
+    pub(crate) fn new(k: usize, tiny_file_bytes: u64, min_files: u64) -> Self {
+        InodeHogVisitor {
+            k: k.max(1),
+            tiny_file_bytes,
+            min_files,
+            scan_root: "/".to_string(),
+            open_directories: Vec::new(),
+            by_entries: BinaryHeap::new(),
+            by_tiny_files: BinaryHeap::new(),
+            by_average: BinaryHeap::new(),
+        }
+    }
+
+    fn push_largest(heap: &mut BinaryHeap<Reverse<(u64, DirectoryCounts)>>, k: usize, key: u64, counts: &DirectoryCounts) {
+        if key == 0 || (heap.len() == k && heap.peek().is_some_and(|Reverse((smallest, _))| key <= *smallest)) {
+            return;
+        }
+        heap.push(Reverse((key, counts.clone())));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    fn exit_directory(&mut self) {
+        let counts = match self.open_directories.pop() {
+            Some(counts) => counts,
+            None => return,
+        };
+        Self::push_largest(&mut self.by_entries, self.k, counts.entries, &counts);
+        Self::push_largest(&mut self.by_tiny_files, self.k, counts.tiny_files, &counts);
+        if counts.files >= self.min_files.max(1) {
+            self.by_average.push((counts.average_bytes(), counts));
+            if self.by_average.len() > self.k {
+                self.by_average.pop();
+            }
+        }
+    }
+
+    fn ranked(heap: &BinaryHeap<Reverse<(u64, DirectoryCounts)>>) -> Vec<&DirectoryCounts> {
+        let mut ranked: Vec<&(u64, DirectoryCounts)> = heap.iter().map(|Reverse(e)| e).collect();
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.path.cmp(&b.1.path)));
+        ranked.into_iter().map(|(_, counts)| counts).collect()
+    }
+
+    /// The filesystem of the scan root and the ones mounted below it, pseudo filesystems
+    /// without inodes left out.
+    fn filesystems(&self) -> Vec<(Mount, InodeUsage)> {
+        let mounts = mounts();
+        let mut crossed: Vec<&Mount> = find_mount(&mounts, &self.scan_root).into_iter().collect();
+        crossed.extend(mounts.iter().filter(|m| m.mount_point != self.scan_root && Path::new(&m.mount_point).starts_with(&self.scan_root)));
+        crossed
+            .into_iter()
+            .filter_map(|m| match inode_usage(&m.mount_point) {
+                Ok(usage) if usage.total > 0 => Some((m.clone(), usage)),
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("Failed to get the inode usage of {}: {}", m.mount_point, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn write_usage(&self, filesystems: &[(Mount, InodeUsage)], by_entries: &[&DirectoryCounts], w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "\nInodes below {}:", self.scan_root)?;
+        for (mount, usage) in filesystems {
+            writeln!(w, "{:>6.1}% of {} inodes used on {} ({})", usage.used_ratio() * 100.0, add_groupings_u64(usage.total), mount.mount_point, mount.fs_type)?;
+        }
+        writeln!(w, "Most entries:")?;
+        for counts in by_entries {
+            writeln!(w, "{:>12} entries, {:>12} tiny, {:>12} bytes average {}", add_groupings_u64(counts.entries), add_groupings_u64(counts.tiny_files), add_groupings_u64(counts.average_bytes()), counts.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Visitable for InodeHogVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        let tiny_file_bytes = self.tiny_file_bytes;
+        let directory = match self.open_directories.last_mut() {
+            Some(directory) => directory,
+            None => return,
+        };
+        directory.entries += 1;
+        if !metadata.is_dir() {
+            directory.files += 1;
+            directory.size_bytes += metadata.size_bytes();
+            if metadata.size_bytes() <= tiny_file_bytes {
+                directory.tiny_files += 1;
+            }
+        }
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        while !self.open_directories.is_empty() {
+            self.exit_directory();
+        }
+        let by_entries = Self::ranked(&self.by_entries);
+        let by_tiny_files = Self::ranked(&self.by_tiny_files);
+        let mut by_average: Vec<&(u64, DirectoryCounts)> = self.by_average.iter().collect();
+        by_average.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.files.cmp(&a.1.files)));
+        let filesystems = self.filesystems();
+
+        if let Err(e) = self.write_usage(&filesystems, &by_entries, w) {
+            error!("Failed to write the inode recap: {}", e);
+        }
+
+        let filesystems: Vec<_> = filesystems
+            .iter()
+            .map(|(mount, usage)| json!({
+                "mount_point": mount.mount_point,
+                "fs_type": mount.fs_type,
+                "total": usage.total,
+                "free": usage.free,
+                "available": usage.available,
+                "used": usage.used(),
+                "used_ratio": usage.used_ratio(),
+            }))
+            .collect();
+        let payload = json!({
+            "root": self.scan_root,
+            "tiny_file_bytes": self.tiny_file_bytes,
+            "filesystems": filesystems,
+            "by_entries": by_entries.iter().map(|c| c.row()).collect::<Vec<_>>(),
+            "by_tiny_files": by_tiny_files.iter().map(|c| c.row()).collect::<Vec<_>>(),
+            "by_average_size": by_average.iter().map(|(_, c)| c.row()).collect::<Vec<_>>(),
+        });
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("inode-hog-event", payload),
+            Err(e) => error!("Failed to serialize inode hogs: {}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "InodeHogVisitor"
+    }
+
+    fn on_scan_start(&mut self, root: &str, _logger: &dyn EventHandler) {
+        self.scan_root = trim_dir(root);
+    }
+
+    fn on_dir_enter(&mut self, metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+        self.open_directories.push(DirectoryCounts { path: metadata.get_path().clone(), ..Default::default() });
+    }
+
+    fn on_dir_exit(&mut self, _metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+        self.exit_directory();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+    use crate::services::scanner_impl::resource_scanner::ResourceScanner;
+    use crate::state::registry::Registry;
+
+    #[test]
+    fn test_inode_hogs() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        let spool = root.join("spool");
+        let media = root.join("media");
+        fs::create_dir_all(&spool).unwrap();
+        fs::create_dir_all(&media).unwrap();
+        for i in 0..20 {
+            fs::write(spool.join(format!("msg-{}", i)), vec![0u8; 100]).unwrap();
+        }
+        for i in 0..3 {
+            fs::write(media.join(format!("movie-{}", i)), vec![0u8; 10_000]).unwrap();
+        }
+        fs::create_dir(media.join("posters")).unwrap();
+        fs::write(media.join("posters/a"), vec![0u8; 10]).unwrap();
+
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = InodeHogVisitor::new(2, 1024, 3);
+        let root = root.to_string_lossy().to_string();
+        ResourceScanner::new().full_scan(&mut Registry::new(), &root, &mut [&mut visitor], &mut writer, &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let event: serde_json::Value = serde_json::from_str(&logger.messages("inode-hog-event")[0]).unwrap();
+        let paths = |ranking: &str| -> Vec<String> {
+            event[ranking].as_array().unwrap().iter().map(|r| r["path"].as_str().unwrap().strip_prefix(root.as_str()).unwrap().to_string()).collect()
+        };
+        assert_eq!(paths("by_entries"), vec!["/spool", "/media"]);
+        assert_eq!(event["by_entries"][1], json!({"path": format!("{}/media", root), "entries": 4, "files": 3, "dirs": 1, "size_bytes": 30_000, "tiny_files": 0, "average_bytes": 10_000}));
+        assert_eq!(paths("by_tiny_files"), vec!["/spool", "/media/posters"]);
+        assert_eq!(paths("by_average_size"), vec!["/spool", "/media"]);
+        assert!(!event["filesystems"].as_array().unwrap().is_empty());
+    }
+}
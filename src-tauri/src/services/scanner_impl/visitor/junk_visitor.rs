@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use log::error;
+use serde::Serialize;
+use serde_json::json;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::state::junk_rule::JunkRule;
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::util::util::add_groupings_u64;
+
+/// Staged for junk directories with the rule that matched, the commit removes the whole
+/// directory if the rule still matches it.
+pub const DELETE_DIRECTORY: &str = "delete_directory";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct JunkFinding {
+    pub(crate) path: String,
+    pub(crate) rule: JunkRule,
+    pub(crate) directory: bool,
+    pub(crate) size_bytes: u64,
+    pub(crate) files: u64,
+}
+
+/// Classifies regenerable files and directories with junk rules, see `JunkRule`. A junk
+/// directory is reported once with everything below it, nothing inside it is classified on its
+/// own. The recap publishes the reclaimable bytes per category and every finding with the
+/// action that deletes it as a `junk-event`, directories going through `delete_directory`.
+pub(crate) struct JunkVisitor {
+    rules: Vec<JunkRule>,
+    // Junk directory the scan is in, it's complete once the scan exits it
+    current: Option<JunkFinding>,
+    findings: Vec<JunkFinding>,
+}
+
+impl JunkVisitor {
+    pub(crate) fn new(rules: Vec<JunkRule>) -> Self {
+        JunkVisitor { rules, current: None, findings: Vec::new() }
+    }
+
+    fn classify(&self, path: &str, is_dir: bool) -> Option<&JunkRule> {
+        self.rules.iter().find(|r| r.matches(path, is_dir))
+    }
+
+    fn is_in_current(&self, path: &str) -> bool {
+        self.current.as_ref().is_some_and(|c| Path::new(path).starts_with(&c.path))
+    }
+
+    fn close_current(&mut self) {
+        if let Some(finding) = self.current.take() {
+            self.findings.push(finding);
+        }
+    }
+
+    /// Reclaimable bytes and findings of each category, largest first.
+    fn categories(&self) -> Vec<(String, u64, usize)> {
+        let mut categories: BTreeMap<&str, (u64, usize)> = BTreeMap::new();
+        for finding in &self.findings {
+            let category = categories.entry(&finding.rule.category).or_default();
+            category.0 += finding.size_bytes;
+            category.1 += 1;
+        }
+        let mut categories: Vec<(String, u64, usize)> = categories.into_iter().map(|(c, (bytes, count))| (c.to_string(), bytes, count)).collect();
+        categories.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        categories
+    }
+
+    fn write_categories(&self, categories: &[(String, u64, usize)], reclaimable_bytes: u64, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "\nReclaimable junk: {} bytes in {} findings", add_groupings_u64(reclaimable_bytes), self.findings.len())?;
+        for (category, bytes, count) in categories {
+            writeln!(w, "{:>16}: {:>16} bytes, {} findings", category, add_groupings_u64(*bytes), count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Visitable for JunkVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        let path = metadata.get_path();
+        if self.is_in_current(path) {
+            if let Some(current) = self.current.as_mut().filter(|_| !metadata.is_dir()) {
+                current.size_bytes += metadata.size_bytes();
+                current.files += 1;
+            }
+            return;
+        }
+        // Scans without directory hooks never say they left the junk directory
+        self.close_current();
+
+        let is_dir = metadata.is_dir() && !metadata.is_symlink();
+        let rule = match self.classify(path, is_dir) {
+            Some(rule) => rule,
+            None => return,
+        };
+        let finding = JunkFinding {
+            path: path.clone(),
+            rule: rule.clone(),
+            directory: is_dir,
+            size_bytes: if is_dir { 0 } else { metadata.size_bytes() },
+            files: if is_dir { 0 } else { 1 },
+        };
+        if is_dir {
+            self.current = Some(finding);
+        } else {
+            self.findings.push(finding);
+        }
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        self.close_current();
+        self.findings.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.path.cmp(&b.path)));
+        let categories = self.categories();
+        let reclaimable_bytes: u64 = categories.iter().map(|(_, bytes, _)| bytes).sum();
+
+        if let Err(e) = self.write_categories(&categories, reclaimable_bytes, w) {
+            error!("Failed to write the junk recap: {}", e);
+        }
+
+        let categories: Vec<_> = categories
+            .iter()
+            .map(|(category, bytes, count)| json!({"category": category, "size_bytes": bytes, "findings": count}))
+            .collect();
+        let findings: Vec<_> = self.findings
+            .iter()
+            .map(|f| {
+                let action = match f.directory {
+                    true => json!({"action": DELETE_DIRECTORY, "path": f.path, "bytes": f.size_bytes, "rule": f.rule}),
+                    false => json!({"action": "delete", "path": f.path, "bytes": f.size_bytes}),
+                };
+                json!({
+                    "path": f.path,
+                    "category": f.rule.category,
+                    "pattern": f.rule.pattern,
+                    "directory": f.directory,
+                    "size_bytes": f.size_bytes,
+                    "files": f.files,
+                    "action": action,
+                })
+            })
+            .collect();
+        let payload = json!({"reclaimable_bytes": reclaimable_bytes, "categories": categories, "findings": findings});
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("junk-event", payload),
+            Err(e) => error!("Failed to serialize junk: {}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "JunkVisitor"
+    }
+
+    fn on_dir_exit(&mut self, metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+        if self.current.as_ref().is_some_and(|c| &c.path == metadata.get_path()) {
+            self.close_current();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+    use crate::services::scanner_impl::resource_scanner::ResourceScanner;
+    use crate::state::registry::Registry;
+
+    #[test]
+    fn test_classifies_junk() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("work");
+        let files = [
+            ("app/package.json", 10),
+            ("app/node_modules/left-pad/index.js", 100),
+            ("app/node_modules/left-pad/node_modules/x/index.js", 50),
+            ("app/src/.main.js.swp", 7),
+            ("notes/target/report.txt", 1000),
+            ("crate/Cargo.toml", 5),
+            ("crate/target/debug/app", 400),
+            (".DS_Store", 3),
+        ];
+        for (path, size) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0u8; size]).unwrap();
+        }
+
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = JunkVisitor::new(JunkRule::builtin());
+        let root = root.to_string_lossy().to_string();
+        ResourceScanner::new().full_scan(&mut Registry::new(), &root, &mut [&mut visitor], &mut writer, &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let event: serde_json::Value = serde_json::from_str(&logger.messages("junk-event")[0]).unwrap();
+        let findings: Vec<(&str, &str, u64, u64)> = event["findings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| (f["path"].as_str().unwrap().strip_prefix(root.as_str()).unwrap(), f["action"]["action"].as_str().unwrap(), f["size_bytes"].as_u64().unwrap(), f["files"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(findings, vec![
+            ("/crate/target", DELETE_DIRECTORY, 400, 1),
+            ("/app/node_modules", DELETE_DIRECTORY, 150, 2),
+            ("/app/src/.main.js.swp", "delete", 7, 1),
+            ("/.DS_Store", "delete", 3, 1),
+        ]);
+        assert_eq!(event["reclaimable_bytes"], 560);
+        assert_eq!(event["categories"][0], json!({"category": "build_artifacts", "size_bytes": 400, "findings": 1}));
+        assert_eq!(event["findings"][0]["action"]["rule"], json!({"category": "build_artifacts", "pattern": "target", "directory": true, "marker": "Cargo.toml"}));
+    }
+}
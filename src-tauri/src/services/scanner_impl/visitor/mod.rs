@@ -2,3 +2,12 @@ pub mod progress_visitor;
 pub mod directory_analyzer_visitor;
 pub mod scan_stats_visitor;
 pub mod top_k_resource_visitor;
+pub mod duplicate_file_visitor;
+pub mod image_similarity_visitor;
+pub mod text_similarity_visitor;
+pub mod type_breakdown_visitor;
+pub mod age_analysis_visitor;
+pub mod symlink_visitor;
+pub mod junk_visitor;
+pub mod inode_hog_visitor;
+pub mod ownership_visitor;
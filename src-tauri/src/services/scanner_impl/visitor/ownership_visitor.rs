@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use log::error;
+use serde::Serialize;
+use serde_json::json;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::state::resource_metadata::{Ownership, ResourceMetadata};
+use crate::util::user_database::UserDatabase;
+use crate::util::util::add_groupings_u64;
+
+const SETUID: u32 = 0o4000;
+const SETGID: u32 = 0o2000;
+const STICKY: u32 = 0o1000;
+const WORLD_WRITABLE: u32 = 0o002;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Usage {
+    files: u64,
+    size_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct Anomaly {
+    pub(crate) path: String,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    /// Permission bits in octal, e.g. `4755`
+    pub(crate) permissions: String,
+    pub(crate) size_bytes: u64,
+}
+
+/// Anomalies of one kind, only the first `max_anomalies` are listed but all are counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct AnomalyList {
+    count: u64,
+    entries: Vec<Anomaly>,
+}
+
+/// Sums the files and bytes of every owner and group, with their names from the system's user
+/// database, and reports risky permissions: world-writable files and directories without
+/// the sticky bit, setuid and setgid files, and resources whose owner or group isn't known. The
+/// recap publishes it all as an `ownership-event`. Registries saved before ownership was
+/// recorded count their resources as unknown until they're rescanned.
+pub(crate) struct OwnershipVisitor {
+    database: UserDatabase,
+    max_anomalies: usize,
+    users: HashMap<u32, Usage>,
+    groups: HashMap<u32, Usage>,
+    unknown: Usage,
+    world_writable: AnomalyList,
+    setuid: AnomalyList,
+    setgid: AnomalyList,
+    orphan_owner: AnomalyList,
+    orphan_group: AnomalyList,
+}
+
+impl OwnershipVisitor {
+    pub(crate) fn new(database: UserDatabase, max_anomalies: usize) -> Self {
+        OwnershipVisitor {
+            database,
+            max_anomalies,
+            users: HashMap::new(),
+            groups: HashMap::new(),
+            unknown: Usage::default(),
+            world_writable: AnomalyList::default(),
+            setuid: AnomalyList::default(),
+            setgid: AnomalyList::default(),
+            orphan_owner: AnomalyList::default(),
+            orphan_group: AnomalyList::default(),
+        }
+    }
+
+    fn add(list: &mut AnomalyList, max_anomalies: usize, metadata: &ResourceMetadata, ownership: Ownership) {
+        list.count += 1;
+        if list.entries.len() < max_anomalies {
+            list.entries.push(Anomaly {
+                path: metadata.get_path().clone(),
+                uid: ownership.uid,
+                gid: ownership.gid,
+                permissions: format!("{:04o}", ownership.mode & 0o7777),
+                size_bytes: metadata.size_bytes(),
+            });
+        }
+    }
+
+    /// Usage of each id with its name, largest first.
+    fn ranked(usage: &HashMap<u32, Usage>, mut name: impl FnMut(u32) -> Option<String>) -> Vec<serde_json::Value> {
+        let mut ranked: Vec<(&u32, &Usage)> = usage.iter().collect();
+        ranked.sort_by(|a, b| b.1.size_bytes.cmp(&a.1.size_bytes).then_with(|| a.0.cmp(b.0)));
+        ranked
+            .into_iter()
+            .map(|(id, u)| json!({"id": id, "name": name(*id), "files": u.files, "size_bytes": u.size_bytes}))
+            .collect()
+    }
+
+    fn write_usage(&self, users: &[serde_json::Value], w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "\nUsage by owner:")?;
+        for user in users {
+            let name = user["name"].as_str().map(str::to_string).unwrap_or_else(|| format!("#{}", user["id"]));
+            writeln!(w, "{:>16} bytes, {:>12} files {}", add_groupings_u64(user["size_bytes"].as_u64().unwrap_or(0)), add_groupings_u64(user["files"].as_u64().unwrap_or(0)), name)?;
+        }
+        writeln!(w, "World-writable: {}, setuid: {}, setgid: {}, unknown owner: {}, unknown group: {}",
+                 self.world_writable.count, self.setuid.count, self.setgid.count, self.orphan_owner.count, self.orphan_group.count)
+    }
+
+    fn anomalies(list: &AnomalyList) -> serde_json::Value {
+        json!({"count": list.count, "entries": list.entries})
+    }
+}
+
+impl Visitable for OwnershipVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        let size_bytes = if metadata.is_dir() { 0 } else { metadata.size_bytes() };
+        let files = if metadata.is_dir() { 0 } else { 1 };
+        let ownership = match metadata.ownership() {
+            Some(ownership) => ownership,
+            None => {
+                self.unknown.files += files;
+                self.unknown.size_bytes += size_bytes;
+                return;
+            }
+        };
+        for usage in [self.users.entry(ownership.uid).or_default(), self.groups.entry(ownership.gid).or_default()] {
+            usage.files += files;
+            usage.size_bytes += size_bytes;
+        }
+
+        let mode = ownership.mode;
+        let max_anomalies = self.max_anomalies;
+        // Link permissions are always 0777 and never checked
+        if !metadata.is_symlink() {
+            if mode & WORLD_WRITABLE != 0 && !(metadata.is_dir() && mode & STICKY != 0) {
+                Self::add(&mut self.world_writable, max_anomalies, metadata, ownership);
+            }
+            // Directories inherit their group to new files with setgid, that's expected
+            if !metadata.is_dir() && mode & SETUID != 0 {
+                Self::add(&mut self.setuid, max_anomalies, metadata, ownership);
+            }
+            if !metadata.is_dir() && mode & SETGID != 0 {
+                Self::add(&mut self.setgid, max_anomalies, metadata, ownership);
+            }
+        }
+        // Without a database every owner would look orphaned
+        if self.database.has_users() && self.database.user_name(ownership.uid).is_none() {
+            Self::add(&mut self.orphan_owner, max_anomalies, metadata, ownership);
+        }
+        if self.database.has_groups() && self.database.group_name(ownership.gid).is_none() {
+            Self::add(&mut self.orphan_group, max_anomalies, metadata, ownership);
+        }
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let users = Self::ranked(&self.users, |uid| self.database.user_name(uid).map(str::to_string));
+        let groups = Self::ranked(&self.groups, |gid| self.database.group_name(gid).map(str::to_string));
+
+        if let Err(e) = self.write_usage(&users, w) {
+            error!("Failed to write the ownership recap: {}", e);
+        }
+
+        let payload = json!({
+            "users": users,
+            "groups": groups,
+            "unknown": {"files": self.unknown.files, "size_bytes": self.unknown.size_bytes},
+            "anomalies": {
+                "world_writable": Self::anomalies(&self.world_writable),
+                "setuid": Self::anomalies(&self.setuid),
+                "setgid": Self::anomalies(&self.setgid),
+                "orphan_owner": Self::anomalies(&self.orphan_owner),
+                "orphan_group": Self::anomalies(&self.orphan_group),
+            },
+        });
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("ownership-event", payload),
+            Err(e) => error!("Failed to serialize ownership: {}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "OwnershipVisitor"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+    use tempfile::TempDir;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+    use crate::services::scanner_impl::resource_scanner::ResourceScanner;
+    use crate::state::registry::Registry;
+
+    #[test]
+    fn test_usage_and_anomalies() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join("tmp")).unwrap();
+        fs::set_permissions(root.join("tmp"), fs::Permissions::from_mode(0o1777)).unwrap();
+        for (name, size, mode) in [("plain", 10, 0o644), ("shared", 20, 0o666), ("tool", 30, 0o4755), ("tmp/run", 40, 0o2755)] {
+            fs::write(root.join(name), vec![0u8; size]).unwrap();
+            fs::set_permissions(root.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+        symlink(root.join("plain"), root.join("link")).unwrap();
+        let owner = fs::metadata(&root).unwrap();
+        let database = UserDatabase::parse(&format!("me:x:{}:0::/:\n", owner.uid()), &format!("other:x:{}:\n", owner.gid().wrapping_add(1)));
+
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = OwnershipVisitor::new(database, 2);
+        let root = root.to_string_lossy().to_string();
+        ResourceScanner::new().full_scan(&mut Registry::new(), &root, &mut [&mut visitor], &mut writer, &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let event: serde_json::Value = serde_json::from_str(&logger.messages("ownership-event")[0]).unwrap();
+        let paths = |kind: &str| -> Vec<String> {
+            event["anomalies"][kind]["entries"].as_array().unwrap().iter().map(|a| a["path"].as_str().unwrap().strip_prefix(root.as_str()).unwrap().to_string()).collect()
+        };
+        assert_eq!(event["users"].as_array().unwrap().len(), 1);
+        assert_eq!(event["users"][0]["name"], "me");
+        assert_eq!(event["users"][0]["files"], 5);
+        assert_eq!(event["groups"][0]["name"], serde_json::Value::Null);
+        assert_eq!(paths("world_writable"), vec!["/shared"]);
+        assert_eq!(paths("setuid"), vec!["/tool"]);
+        assert_eq!(event["anomalies"]["setuid"]["entries"][0]["permissions"], "4755");
+        assert_eq!(paths("setgid"), vec!["/tmp/run"]);
+        assert_eq!(event["anomalies"]["orphan_owner"]["count"], 0);
+        assert_eq!(event["anomalies"]["orphan_group"]["count"], 7);
+        assert_eq!(event["anomalies"]["orphan_group"]["entries"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_unknown_ownership() {
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = OwnershipVisitor::new(UserDatabase::default(), 10);
+        visitor.visit(&Arc::new(ResourceMetadata::new(&"/a".to_string(), false, false, 0, 5, false)), &mut writer, &logger);
+        let owned = ResourceMetadata::new(&"/b".to_string(), false, false, 0, 7, false).with_ownership(Some(Ownership { uid: 4242, gid: 4242, mode: 0o100644 }));
+        visitor.visit(&Arc::new(owned), &mut writer, &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let event: serde_json::Value = serde_json::from_str(&logger.messages("ownership-event")[0]).unwrap();
+        assert_eq!(event["unknown"], json!({"files": 1, "size_bytes": 5}));
+        assert_eq!(event["users"][0], json!({"id": 4242, "name": null, "files": 1, "size_bytes": 7}));
+        assert_eq!(event["anomalies"]["orphan_owner"]["count"], 0);
+    }
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::sync::Arc;
 use crate::{Visitable};
 use std::time::{Instant};
 use chrono::Utc;
@@ -99,7 +100,7 @@ impl ProgressVisitor {
 }
 
 impl Visitable for ProgressVisitor {
-    fn visit(&mut self, metadata: &ResourceMetadata, writer: &mut dyn io::Write, logger: &dyn EventHandler) {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, writer: &mut dyn io::Write, logger: &dyn EventHandler) {
         // Simulate file and directory scanning logic here
         // For demonstration purposes, let's just increment the counters
         if metadata.is_dir() {
@@ -141,7 +142,7 @@ impl Visitable for ProgressVisitor {
 }
 
 lazy_static! {
-    static ref DUMMY_METADATA: ResourceMetadata = ResourceMetadata::new(&("dummy".to_string()), false, false, 0, 0, false);
+    static ref DUMMY_METADATA: Arc<ResourceMetadata> = Arc::new(ResourceMetadata::new(&("dummy".to_string()), false, false, 0, 0, false));
 }
 
 #[cfg(test)]
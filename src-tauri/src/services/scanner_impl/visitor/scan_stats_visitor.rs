@@ -1,4 +1,5 @@
 use std::io;
+use std::sync::Arc;
 use crate::services::scanner_api::event_handler::EventHandler;
 use crate::state::resource_metadata::ResourceMetadata;
 use crate::state::scan_stats::ScanStats;
@@ -10,7 +11,7 @@ pub(crate) struct ScanStatsVisitor {
 }
 
 impl Visitable for ScanStatsVisitor {
-    fn visit(&mut self, metadata: &ResourceMetadata, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
         if metadata.is_dir() {
             self.stats.increment_directory();
         } else {
@@ -74,8 +75,8 @@ mod tests {
         let logger = NoopEventHandler{};
 
         let mut visitor = ScanStatsVisitor::new();
-        let file = ResourceMetadata::new(&f.to_string(), false, false, 0, 1024, false);
-        let dir = ResourceMetadata::new(&d.to_string(), true, false, 0, 1024, false);
+        let file = Arc::new(ResourceMetadata::new(&f.to_string(), false, false, 0, 1024, false));
+        let dir = Arc::new(ResourceMetadata::new(&d.to_string(), true, false, 0, 1024, false));
         visitor.visit(&file, &mut writer, &logger);
         visitor.visit(&dir, &mut writer, &logger);
 
@@ -0,0 +1,189 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::sync::Arc;
+use log::{error, warn};
+use serde::Serialize;
+use serde_json::json;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::util::util::trim_dir;
+
+/// Staged for dangling links, the commit only removes a link that is still dangling.
+pub const DELETE_DANGLING_LINK: &str = "delete_dangling_link";
+
+/// Where resolving a symlink ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Resolution {
+    Resolved(PathBuf),
+    Dangling,
+    Loop,
+}
+
+/// Follows `path` to the file it finally points at, through links in any of its components.
+pub(crate) fn resolve(path: &str) -> io::Result<Resolution> {
+    match fs::canonicalize(path) {
+        Ok(resolved) => Ok(Resolution::Resolved(resolved)),
+        Err(e) if e.kind() == ErrorKind::NotFound || e.raw_os_error() == Some(libc::ENOTDIR) => Ok(Resolution::Dangling),
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => Ok(Resolution::Loop),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct SymlinkReport {
+    pub(crate) path: String,
+    /// As stored in the link
+    pub(crate) target: String,
+    /// Only for links that resolve
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) resolved: Option<String>,
+    pub(crate) size_bytes: u64,
+}
+
+/// Resolves every symlink of the scan and reports the dangling ones, the ones that end up
+/// outside the scanned root and the ones caught in a loop, as a `symlink-event`. Dangling links
+/// come with a `delete_dangling_link` action the UI can stage as is.
+pub(crate) struct SymlinkVisitor {
+    scan_root: PathBuf,
+    dangling: Vec<SymlinkReport>,
+    escaping: Vec<SymlinkReport>,
+    loops: Vec<SymlinkReport>,
+    resolved: usize,
+}
+
+impl SymlinkVisitor {
+    pub(crate) fn new() -> Self {
+        SymlinkVisitor {
+            scan_root: PathBuf::from("/"),
+            dangling: Vec::new(),
+            escaping: Vec::new(),
+            loops: Vec::new(),
+            resolved: 0,
+        }
+    }
+
+    fn write_summary(&self, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "\nSymlinks: {} resolved below {}", self.resolved, self.scan_root.display())?;
+        Self::write_reports(w, "Dangling", &self.dangling)?;
+        Self::write_reports(w, "Escaping the root", &self.escaping)?;
+        Self::write_reports(w, "Loops", &self.loops)
+    }
+
+    fn write_reports(w: &mut dyn io::Write, title: &str, reports: &[SymlinkReport]) -> io::Result<()> {
+        writeln!(w, "{}: {}", title, reports.len())?;
+        for report in reports {
+            match &report.resolved {
+                Some(resolved) => writeln!(w, "    {} -> {} ({})", report.path, report.target, resolved),
+                None => writeln!(w, "    {} -> {}", report.path, report.target),
+            }?;
+        }
+        Ok(())
+    }
+}
+
+impl Visitable for SymlinkVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        if !metadata.is_symlink() {
+            return;
+        }
+        let path = metadata.get_path();
+        let target = match fs::read_link(path) {
+            Ok(target) => target.to_string_lossy().to_string(),
+            Err(e) => {
+                warn!("Failed to read symlink {}: {}", path, e);
+                return;
+            }
+        };
+        let mut report = SymlinkReport { path: path.clone(), target, resolved: None, size_bytes: metadata.size_bytes() };
+        match resolve(path) {
+            Ok(Resolution::Resolved(resolved)) => {
+                self.resolved += 1;
+                if !resolved.starts_with(&self.scan_root) {
+                    report.resolved = Some(resolved.to_string_lossy().to_string());
+                    self.escaping.push(report);
+                }
+            }
+            Ok(Resolution::Dangling) => self.dangling.push(report),
+            Ok(Resolution::Loop) => self.loops.push(report),
+            Err(e) => warn!("Failed to resolve symlink {}: {}", path, e),
+        }
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        if let Err(e) = self.write_summary(w) {
+            error!("Failed to write the symlink recap: {}", e);
+        }
+
+        let dangling: Vec<_> = self.dangling
+            .iter()
+            .map(|r| json!({"path": r.path, "target": r.target, "size_bytes": r.size_bytes, "action": {"action": DELETE_DANGLING_LINK, "path": r.path, "bytes": r.size_bytes}}))
+            .collect();
+        let payload = json!({"root": self.scan_root, "dangling": dangling, "escaping": self.escaping, "loops": self.loops});
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("symlink-event", payload),
+            Err(e) => error!("Failed to serialize symlinks: {}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "SymlinkVisitor"
+    }
+
+    fn on_scan_start(&mut self, root: &str, _logger: &dyn EventHandler) {
+        // Links are compared by where they really end up, so the root must be too
+        let root = trim_dir(root);
+        self.scan_root = fs::canonicalize(&root).unwrap_or_else(|_| PathBuf::from(root));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::TempDir;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+
+    #[test]
+    fn test_dangling_escaping_and_loops() {
+        let dir = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("file"), b"content").unwrap();
+        fs::write(outside.path().join("elsewhere"), b"content").unwrap();
+
+        let links = [
+            ("ok", root.join("file")),
+            ("dangling", root.join("gone")),
+            ("escaping", outside.path().join("elsewhere")),
+            ("loop-a", root.join("loop-b")),
+            ("loop-b", root.join("loop-a")),
+        ];
+        for (name, target) in &links {
+            symlink(target, root.join(name)).unwrap();
+        }
+
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = SymlinkVisitor::new();
+        visitor.on_scan_start(&root.to_string_lossy(), &logger);
+        for (name, _) in &links {
+            let path = root.join(name).to_string_lossy().to_string();
+            visitor.visit(&Arc::new(ResourceMetadata::new(&path, false, true, 0, 10, false)), &mut writer, &logger);
+        }
+        visitor.visit(&Arc::new(ResourceMetadata::new(&root.join("file").to_string_lossy().to_string(), false, false, 0, 7, false)), &mut writer, &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let event: serde_json::Value = serde_json::from_str(&logger.messages("symlink-event")[0]).unwrap();
+        let names = |kind: &str| -> Vec<String> {
+            event[kind].as_array().unwrap().iter().map(|r| r["path"].as_str().unwrap().rsplit('/').next().unwrap().to_string()).collect()
+        };
+        assert_eq!(names("dangling"), vec!["dangling"]);
+        assert_eq!(names("escaping"), vec!["escaping"]);
+        assert_eq!(names("loops"), vec!["loop-a", "loop-b"]);
+        assert_eq!(event["dangling"][0]["action"]["action"], DELETE_DANGLING_LINK);
+        assert_eq!(visitor.resolved, 2);
+    }
+}
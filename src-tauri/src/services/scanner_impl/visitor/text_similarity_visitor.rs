@@ -0,0 +1,122 @@
+use std::io;
+use std::sync::Arc;
+use log::{error, warn};
+use serde_json::json;
+use crate::services::file_impl::mime_guess_file_type_detector::MimeGuessFileTypeDetector;
+use crate::services::file_impl::shingling_text_signature_service::ShinglingTextSignatureService;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::services::scanner_impl::text_similarity_detector::TextSimilarityDetector;
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::state::text_cluster::{TextCluster, TextSignatureAlgorithm};
+use crate::util::cancellation_token::CancellationToken;
+
+const MAX_SIZE_BYTES: u64 = 64 * 1024 * 1024;
+const PROGRESS_INTERVAL_FILES: usize = 100;
+
+/// Collects text files while the scan runs and clusters the near-identical ones once it's
+/// done, see `TextSimilarityDetector`. Clusters are published as a `text-similarity-event`,
+/// signing reports `text-signature-progress-event`s.
+pub(crate) struct TextSimilarityVisitor {
+    threshold: f64,
+    detector: TextSimilarityDetector,
+    clusters: Vec<TextCluster>,
+}
+
+impl TextSimilarityVisitor {
+    /// Files at least `threshold` similar, between 0 and 1, end up in the same cluster.
+    pub(crate) fn new(algorithm: TextSignatureAlgorithm, threshold: f64, cancellation: CancellationToken) -> Self {
+        let detector = TextSimilarityDetector::new(Box::new(ShinglingTextSignatureService::new(algorithm)), Box::new(MimeGuessFileTypeDetector), threshold, MAX_SIZE_BYTES, cancellation);
+        TextSimilarityVisitor { threshold, detector, clusters: Vec::new() }
+    }
+
+    fn write_clusters(clusters: &[TextCluster], wasted_bytes: u64, w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "Similar Texts: {} clusters, {} wasted bytes", clusters.len(), wasted_bytes)?;
+        for cluster in clusters {
+            writeln!(w, "Wasted: {:>16}, Files: {}", cluster.wasted_bytes(), cluster.files.len())?;
+            for file in &cluster.files {
+                writeln!(w, "    {:.2} {}", file.similarity, file.path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Visitable for TextSimilarityVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        self.detector.add(metadata);
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        let mut progress = |signed: usize, total: usize| {
+            if signed.is_multiple_of(PROGRESS_INTERVAL_FILES) || signed == total {
+                logger.publish("text-signature-progress-event", json!({"signed_files": signed, "total_files": total}).to_string());
+            }
+        };
+        self.clusters = match self.detector.detect(&mut progress) {
+            Some(clusters) => clusters,
+            None => {
+                warn!("Text similarity detection was cancelled");
+                if let Err(e) = writeln!(w, "Similar Texts: cancelled") {
+                    error!("Failed to write the similar texts recap: {}", e);
+                }
+                return;
+            }
+        };
+        let wasted_bytes: u64 = self.clusters.iter().map(TextCluster::wasted_bytes).sum();
+
+        if let Err(e) = Self::write_clusters(&self.clusters, wasted_bytes, w) {
+            error!("Failed to write the similar texts recap: {}", e);
+        }
+
+        let clusters: Vec<_> = self.clusters
+            .iter()
+            .map(|c| json!({"wasted_bytes": c.wasted_bytes(), "files": c.files}))
+            .collect();
+        let payload = json!({"algorithm": self.detector.algorithm().name(), "threshold": self.threshold, "wasted_bytes": wasted_bytes, "clusters": clusters});
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("text-similarity-event", payload),
+            Err(e) => error!("Failed to serialize text clusters: {}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "TextSimilarityVisitor"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use tempfile::tempdir;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+    use super::*;
+
+    #[test]
+    fn test_recap_publishes_clusters() {
+        let dir = tempdir().unwrap();
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let mut visitor = TextSimilarityVisitor::new(TextSignatureAlgorithm::MinHash, 0.8, CancellationToken::new());
+
+        let log: String = (0..200).map(|i| format!("request {} served in {} ms\n", i, i % 13)).collect();
+        for (name, content) in [("a.log", log.clone()), ("b.log", format!("{}one more line\n", log))] {
+            let path = dir.path().join(name);
+            fs::write(&path, &content).unwrap();
+            visitor.visit(&Arc::new(ResourceMetadata::new(&path.to_string_lossy().to_string(), false, false, 0, content.len() as u64, false)), &mut writer, &logger);
+        }
+        visitor.recap(&mut writer, &logger);
+
+        let messages = logger.messages("text-similarity-event");
+        assert_eq!(messages.len(), 1);
+        let payload: serde_json::Value = serde_json::from_str(&messages[0]).unwrap();
+        assert_eq!(payload["algorithm"], "minhash");
+        assert_eq!(payload["clusters"][0]["files"].as_array().unwrap().len(), 2);
+        assert!(String::from_utf8(writer).unwrap().contains("Similar Texts: 1 clusters"));
+    }
+
+    #[test]
+    fn test_name_validation() {
+        assert_eq!("TextSimilarityVisitor", TextSimilarityVisitor::new(TextSignatureAlgorithm::MinHash, 0.8, CancellationToken::new()).name());
+    }
+}
@@ -1,29 +1,115 @@
-use std::collections::BinaryHeap;
-use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::cmp::{Ordering, Reverse};
 use std::{fs, io};
+use std::sync::Arc;
 use chrono::{DateTime, Utc};
+use log::{debug, error};
+use serde_json::{json, Value};
 use crate::services::file_api::compression_checker::CompressionChecker;
 use crate::services::file_api::file_type_detector::FileTypeDetector;
 use crate::services::file_impl::mime_compression_checker::MimeCompressionChecker;
 use crate::services::file_impl::mime_guess_file_type_detector::MimeGuessFileTypeDetector;
+use crate::state::registry_snapshot::DirectoryRollup;
 use crate::state::resource_metadata::ResourceMetadata;
+use crate::state::visitor_config::DEFAULT_TOP_K;
 use crate::services::scanner_api::event_handler::EventHandler;
 use crate::services::scanner_api::visitable::Visitable;
 
+const DIRECTORY_MIME_TYPE: &str = "inode/directory";
+
+/// Bytes, files and directories below a directory that's still open.
+struct OpenDirectory {
+    metadata: Arc<ResourceMetadata>,
+    rollup: DirectoryRollup,
+}
+
+/// A directory ranked by `key`, ties broken by path.
+struct RankedDirectory {
+    key: i64,
+    metadata: Arc<ResourceMetadata>,
+    rollup: DirectoryRollup,
+    growth_bytes: i64,
+}
+
+impl PartialEq for RankedDirectory {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankedDirectory {}
+
+impl PartialOrd for RankedDirectory {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RankedDirectory {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key).then_with(|| other.metadata.get_path().cmp(self.metadata.get_path()))
+    }
+}
+
+/// Keeps the `k` largest items pushed.
+struct TopK<T: Ord> {
+    k: usize,
+    heap: BinaryHeap<Reverse<T>>,
+}
+
+impl<T: Ord> TopK<T> {
+    fn new(k: usize) -> Self {
+        TopK { k, heap: BinaryHeap::with_capacity(k) }
+    }
+
+    fn push(&mut self, item: T) {
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(item));
+        } else if self.heap.peek().is_some_and(|smallest| item > smallest.0) {
+            self.heap.pop();
+            self.heap.push(Reverse(item));
+        }
+    }
+
+    /// Largest first.
+    fn sorted(&self) -> Vec<&T> {
+        let mut items: Vec<&T> = self.heap.iter().map(|r| &r.0).collect();
+        items.sort_by(|a, b| b.cmp(a));
+        items
+    }
+}
+
+/// Ranks the `k` largest files, published as a `top-k-event`, and with directories the `k`
+/// directories with the most bytes, the most files and the most growth since the previous
+/// scan, published in the same shape as `top-k-dir-size-event`, `top-k-dir-files-event` and
+/// `top-k-dir-growth-event`. Directory totals cover the files below them and are taken as the
+/// scan exits each directory, growth is only ranked given the rollups of a previous scan.
 pub(crate) struct TopKResourceVisitor {
-    top_resources: BinaryHeap<Reverse<ResourceMetadata>>,
+    k: usize,
+    top_resources: BinaryHeap<Reverse<Arc<ResourceMetadata>>>,
+    directories: bool,
+    previous: Option<HashMap<String, DirectoryRollup>>,
+    open_directories: Vec<OpenDirectory>,
+    by_size: TopK<RankedDirectory>,
+    by_files: TopK<RankedDirectory>,
+    by_growth: TopK<RankedDirectory>,
 }
 
 impl Visitable for TopKResourceVisitor {
-    fn visit(&mut self, metadata: &ResourceMetadata, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
         if !metadata.is_dir() {
-            if self.top_resources.len() < 50 {
+            if self.top_resources.len() < self.k {
                 // If the heap is not full, just push the new metadata
-                self.top_resources.push(Reverse(metadata.clone()));
+                self.top_resources.push(Reverse(Arc::clone(metadata)));
             } else if metadata.size_bytes() > self.top_resources.peek().unwrap().0.size_bytes() {
                 // If the new metadata is larger than the smallest in the heap, replace the smallest
                 self.top_resources.pop();
-                self.top_resources.push(Reverse(metadata.clone()));
+                self.top_resources.push(Reverse(Arc::clone(metadata)));
+            }
+
+            if let Some(parent) = self.open_directories.last_mut() {
+                parent.rollup.file_count += 1;
+                parent.rollup.size_bytes += metadata.size_bytes();
             }
         }
     }
@@ -31,84 +117,204 @@ impl Visitable for TopKResourceVisitor {
     fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
         let reversed_sorted_resources: Vec<_> = self.top_resources.clone().into_sorted_vec().into_iter().collect();
 
-        let mut s = String::from("[");
-
-        let mut first = true;
-        write!(w, "Top 50 Largest Resources:\n").expect("TODO: panic message");
+        writeln!(w, "Top {} Largest Resources:", self.k).expect("TODO: panic message");
+        let mut rows = Vec::new();
         for (i, metadata) in reversed_sorted_resources.iter().enumerate() {
             let metadata = &metadata.0;
             let padded_ranking = format!("{:<5}", i + 1); // Padded to 5 characters for ranking
             let padded_bytes = format!("{:>16}", metadata.size_bytes()); // Padded to 50 characters for bytes
-            write!(w, "Rank: {}, Bytes: {}, Path: {}\n", padded_ranking, padded_bytes, metadata.get_path()).expect("TODO: panic message");
-
-            let m = match fs::symlink_metadata(metadata.get_path()) {
-                Ok(metadata) => metadata,
-                Err(e) => {
-                    println!("Error accessing file ({}) metadata: {:?}", metadata.get_path(), e);
-                    continue; // Skip this iteration of the loop
-                }
-            };
-
-            if !first {
-                s.push_str(",");
-            } else {
-                first = false;
-            }
-
-            let custom_format = "%y%m%d";
-            let now = Utc::now();
-            // Last access time
-            let last_access_time = m.accessed().unwrap();
-            let last_access_datetime: DateTime<Utc> = last_access_time.into();
-            let last_access_iso_string = last_access_datetime.format(custom_format).to_string();
-            let last_access_duration = now.signed_duration_since(last_access_datetime);
-            let last_access_days = last_access_duration.num_days();
-
-            // Modified time
-            let modified_time = m.modified().unwrap();
-            let modified_datetime: DateTime<Utc> = modified_time.into();
-            let modified_iso_string = modified_datetime.format(custom_format).to_string();
-            let modified_duration = now.signed_duration_since(modified_datetime);
-            let modified_days = modified_duration.num_days();
-
+            writeln!(w, "Rank: {}, Bytes: {}, Path: {}", padded_ranking, padded_bytes, metadata.get_path()).expect("TODO: panic message");
 
             let detector = MimeGuessFileTypeDetector;
             let mimetype = detector.get_file_type(metadata.get_path()).unwrap();
-            let compression_checker = MimeCompressionChecker;
+            rows.push(Self::row(i + 1, metadata, metadata.size_bytes(), &mimetype));
+        }
+        logger.publish("top-k-event", Value::Array(rows).to_string());
 
+        if self.directories {
+            // Directories a replay or cancelled scan didn't exit
+            while !self.open_directories.is_empty() {
+                self.exit_directory();
+            }
+            self.recap_directories(w, logger, "size", "top-k-dir-size-event", &self.by_size);
+            self.recap_directories(w, logger, "files", "top-k-dir-files-event", &self.by_files);
+            if self.previous.is_some() {
+                self.recap_directories(w, logger, "growth", "top-k-dir-growth-event", &self.by_growth);
+            }
+        }
+    }
 
-            s.push_str(&format!("{{\"rank\": \"{}\", \"bytes\": \"{}\", \"path\": \"{}\", \"mime_type\": \"{}\", \"compressible\": \"{}\", \"modified\": {:?}, \"accessed\": {:?}, \"modified_days\": {}, \"accessed_days\": {}}}",
-                                i + 1, metadata.size_bytes(), metadata.get_path(), mimetype.clone(), compression_checker.is_compressible(&mimetype), modified_iso_string, last_access_iso_string, modified_days, last_access_days));
+    fn name(&self) -> &'static str {
+        "TopKLargestResources"
+    }
 
+    fn on_dir_enter(&mut self, metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+        if self.directories {
+            self.open_directories.push(OpenDirectory { metadata: Arc::clone(metadata), rollup: DirectoryRollup::default() });
         }
+    }
 
-        s.push_str("]");
-
-        logger.publish("top-k-event", s);
+    fn on_dir_exit(&mut self, _metadata: &Arc<ResourceMetadata>, _logger: &dyn EventHandler) {
+        if self.directories {
+            self.exit_directory();
+        }
     }
+}
 
-    fn name(&self) -> &'static str {
-        "Top50LargestResources"
+impl Default for TopKResourceVisitor {
+    fn default() -> Self {
+        TopKResourceVisitor::with_k(DEFAULT_TOP_K)
     }
 }
 
 impl TopKResourceVisitor {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn with_k(k: usize) -> Self {
+        let k = k.max(1);
         TopKResourceVisitor {
-            top_resources: BinaryHeap::with_capacity(50),
+            k,
+            top_resources: BinaryHeap::with_capacity(k),
+            directories: false,
+            previous: None,
+            open_directories: Vec::new(),
+            by_size: TopK::new(k),
+            by_files: TopK::new(k),
+            by_growth: TopK::new(k),
         }
     }
+
+    /// Also ranks directories, by growth against `previous` rollups keyed by path when given.
+    pub(crate) fn with_directories(mut self, previous: Option<HashMap<String, DirectoryRollup>>) -> Self {
+        self.directories = true;
+        self.previous = previous;
+        self
+    }
+
+    fn exit_directory(&mut self) {
+        let Some(OpenDirectory { metadata, rollup }) = self.open_directories.pop() else {
+            return;
+        };
+        if let Some(parent) = self.open_directories.last_mut() {
+            parent.rollup.size_bytes += rollup.size_bytes;
+            parent.rollup.file_count += rollup.file_count;
+            parent.rollup.dir_count += rollup.dir_count + 1;
+        }
+
+        let growth_bytes = match &self.previous {
+            Some(previous) => rollup.size_bytes as i64 - previous.get(metadata.get_path()).map_or(0, |r| r.size_bytes as i64),
+            None => 0,
+        };
+        let ranked = |key: i64| RankedDirectory { key, metadata: Arc::clone(&metadata), rollup: rollup.clone(), growth_bytes };
+        self.by_size.push(ranked(rollup.size_bytes as i64));
+        self.by_files.push(ranked(rollup.file_count as i64));
+        if growth_bytes > 0 {
+            self.by_growth.push(ranked(growth_bytes));
+        }
+    }
+
+    fn write_directories(k: usize, ranking: &str, sorted: &[&RankedDirectory], w: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(w, "Top {} Directories by {}:", k, ranking)?;
+        for (i, directory) in sorted.iter().enumerate() {
+            writeln!(w, "Rank: {:<5}, Bytes: {:>16}, Files: {:>10}, Growth: {:>16}, Path: {}", i + 1, directory.rollup.size_bytes, directory.rollup.file_count, directory.growth_bytes, directory.metadata.get_path())?;
+        }
+        Ok(())
+    }
+
+    fn recap_directories(&self, w: &mut dyn io::Write, logger: &dyn EventHandler, ranking: &str, event: &str, ranked: &TopK<RankedDirectory>) {
+        let sorted = ranked.sorted();
+        if let Err(e) = Self::write_directories(self.k, ranking, &sorted, w) {
+            error!("Failed to write the top directories by {} recap: {}", ranking, e);
+        }
+        let mut rows = Vec::new();
+        for (i, directory) in sorted.into_iter().enumerate() {
+            let mut row = Self::row(i + 1, &directory.metadata, directory.rollup.size_bytes, DIRECTORY_MIME_TYPE);
+            row["files"] = json!(directory.rollup.file_count);
+            row["dirs"] = json!(directory.rollup.dir_count);
+            row["growth_bytes"] = json!(directory.growth_bytes);
+            rows.push(row);
+        }
+        logger.publish(event, Value::Array(rows).to_string());
+    }
+
+    /// A row of the inspection table, `bytes` of the file or below the directory.
+    fn row(rank: usize, metadata: &ResourceMetadata, bytes: u64, mimetype: &str) -> Value {
+        // Resources replayed from an imported dump may not exist locally, fall back to the
+        // registry's modified time for both timestamps
+        let (last_access_datetime, modified_datetime): (DateTime<Utc>, DateTime<Utc>) = match fs::symlink_metadata(metadata.get_path()) {
+            Ok(m) => (m.accessed().unwrap().into(), m.modified().unwrap().into()),
+            Err(e) => {
+                debug!("Error accessing file ({}) metadata, using registry times: {:?}", metadata.get_path(), e);
+                let t = DateTime::from_timestamp(metadata.modified(), 0).unwrap_or_default();
+                (t, t)
+            }
+        };
+
+        let custom_format = "%y%m%d";
+        let now = Utc::now();
+        let compression_checker = MimeCompressionChecker;
+        let compressible = if mimetype == DIRECTORY_MIME_TYPE { 0 } else { compression_checker.is_compressible(&mimetype.to_string()) };
+
+        json!({
+            "rank": rank.to_string(),
+            "bytes": bytes.to_string(),
+            "path": metadata.get_path(),
+            "mime_type": mimetype,
+            "compressible": compressible.to_string(),
+            "modified": modified_datetime.format(custom_format).to_string(),
+            "accessed": last_access_datetime.format(custom_format).to_string(),
+            "modified_days": now.signed_duration_since(modified_datetime).num_days(),
+            "accessed_days": now.signed_duration_since(last_access_datetime).num_days(),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::services::scanner_impl::noop_event_handler::NoopEventHandler;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
     use super::*;
 
+    #[test]
+    fn test_directory_rankings() {
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let previous = HashMap::from([("/r/a".to_string(), DirectoryRollup { size_bytes: 100, file_count: 1, dir_count: 0 })]);
+        let mut visitor = TopKResourceVisitor::with_k(2).with_directories(Some(previous));
+
+        let dir = |path: &str| Arc::new(ResourceMetadata::new(&path.to_string(), true, false, 0, 4096, false));
+        let mut file = |visitor: &mut TopKResourceVisitor, path: &str, size: u64| visitor.visit(&Arc::new(ResourceMetadata::new(&path.to_string(), false, false, 0, size, false)), &mut writer, &logger);
+        let (r, a, b) = (dir("/r"), dir("/r/a"), dir("/r/b"));
+        visitor.on_dir_enter(&r, &logger);
+        visitor.on_dir_enter(&a, &logger);
+        file(&mut visitor, "/r/a/x", 300);
+        file(&mut visitor, "/r/a/y", 10);
+        visitor.on_dir_exit(&a, &logger);
+        visitor.on_dir_enter(&b, &logger);
+        for name in ["1", "2", "3"] {
+            file(&mut visitor, &format!("/r/b/{}", name), 1);
+        }
+        visitor.on_dir_exit(&b, &logger);
+        visitor.on_dir_exit(&r, &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let ranking = |event: &str| -> Vec<(String, u64, u64, i64)> {
+            let rows: Vec<serde_json::Value> = serde_json::from_str(&logger.messages(event)[0]).unwrap();
+            rows.iter()
+                .map(|r| (r["path"].as_str().unwrap().to_string(), r["bytes"].as_str().unwrap().parse().unwrap(), r["files"].as_u64().unwrap(), r["growth_bytes"].as_i64().unwrap()))
+                .collect()
+        };
+        assert_eq!(ranking("top-k-dir-size-event"), vec![("/r".to_string(), 313, 5, 313), ("/r/a".to_string(), 310, 2, 210)]);
+        assert_eq!(ranking("top-k-dir-files-event"), vec![("/r".to_string(), 313, 5, 313), ("/r/b".to_string(), 3, 3, 3)]);
+        assert_eq!(ranking("top-k-dir-growth-event"), vec![("/r".to_string(), 313, 5, 313), ("/r/a".to_string(), 310, 2, 210)]);
+
+        let files: Vec<serde_json::Value> = serde_json::from_str(&logger.messages("top-k-event")[0]).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!((files[0]["rank"].as_str(), files[0]["path"].as_str()), (Some("1"), Some("/r/a/x")));
+    }
+
     #[test]
     fn test_recap() {
         // Prepare test data
-        let mut visitor = TopKResourceVisitor::new();
+        let mut visitor = TopKResourceVisitor::default();
 
         // Add resources in ascending order of size_bytes
         for size_bytes in (1..=100).step_by(2) {
@@ -116,7 +322,7 @@ mod tests {
             let is_dir = false;
             let is_symlink = false;
             let modified = 123456789;
-            let metadata = ResourceMetadata::new(&path, is_dir, is_symlink, modified, size_bytes, false);
+            let metadata = Arc::new(ResourceMetadata::new(&path, is_dir, is_symlink, modified, size_bytes, false));
 
             let mut buffer: Vec<u8> = Vec::new();
             let mut writer = io::BufWriter::new(&mut buffer);
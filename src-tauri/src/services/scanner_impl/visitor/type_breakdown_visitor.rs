@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
+use log::{error, info};
+use serde_json::json;
+use crate::services::file_api::file_type_detector::FileTypeDetector;
+use crate::services::file_impl::mime_guess_file_type_detector::MimeGuessFileTypeDetector;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_api::visitable::Visitable;
+use crate::state::resource_metadata::ResourceMetadata;
+use crate::state::type_breakdown::{write_csv, FileCategory, TypeBreakdown, NO_EXTENSION};
+use crate::util::util::{add_groupings_u64, subtrees, trim_dir};
+
+const UNKNOWN_MIME_TYPE: &str = "application/octet-stream";
+
+/// Sums the bytes and files of the scan by extension, MIME type and category, for the scan root
+/// and every directory up to `depth` levels below it. The recap publishes all of them as a
+/// `type-breakdown-event` for charts and, given a `csv_path`, exports them there.
+pub(crate) struct TypeBreakdownVisitor {
+    detector: Box<dyn FileTypeDetector>,
+    depth: usize,
+    csv_path: Option<String>,
+    scan_root: String,
+    breakdowns: BTreeMap<String, TypeBreakdown>,
+}
+
+impl TypeBreakdownVisitor {
+    pub(crate) fn new(depth: usize) -> Self {
+        TypeBreakdownVisitor {
+            detector: Box::new(MimeGuessFileTypeDetector),
+            depth,
+            csv_path: None,
+            scan_root: "/".to_string(),
+            breakdowns: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn with_csv(mut self, path: Option<String>) -> Self {
+        self.csv_path = path;
+        self
+    }
+
+    fn write_root(&self, w: &mut dyn io::Write) -> io::Result<()> {
+        if let Some(root) = self.breakdowns.get(&self.scan_root) {
+            let total = root.total();
+            writeln!(w, "\nSpace by type below {}: {} files, {} bytes", self.scan_root, add_groupings_u64(total.files), add_groupings_u64(total.size_bytes))?;
+            for entry in root.categories() {
+                writeln!(w, "{:>10}: {:>16} bytes, {:>12} files", entry.key, add_groupings_u64(entry.size_bytes), add_groupings_u64(entry.files))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn export_csv(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_csv(&self.breakdowns, &mut writer)
+    }
+}
+
+impl Visitable for TypeBreakdownVisitor {
+    fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        if metadata.is_dir() || metadata.is_symlink() {
+            return;
+        }
+        let path = metadata.get_path();
+        let extension = Path::new(path).extension().map_or(NO_EXTENSION.to_string(), |e| e.to_string_lossy().to_lowercase());
+        let mime_type = self.detector.get_file_type(path).unwrap_or_else(|_| UNKNOWN_MIME_TYPE.to_string());
+        let category = FileCategory::classify(&extension, &mime_type);
+        for subtree in subtrees(&self.scan_root, path, self.depth) {
+            self.breakdowns.entry(subtree).or_default().add(&extension, &mime_type, category, metadata.size_bytes());
+        }
+    }
+
+    fn recap(&mut self, w: &mut dyn io::Write, logger: &dyn EventHandler) {
+        if let Err(e) = self.write_root(w) {
+            error!("Failed to write the type breakdown recap: {}", e);
+        }
+
+        let subtrees: Vec<_> = self.breakdowns
+            .iter()
+            .map(|(path, b)| {
+                let total = b.total();
+                json!({
+                    "path": path,
+                    "files": total.files,
+                    "size_bytes": total.size_bytes,
+                    "categories": b.categories(),
+                    "mime_types": b.mime_types(),
+                    "extensions": b.extensions(),
+                })
+            })
+            .collect();
+        let payload = json!({"root": self.scan_root, "depth": self.depth, "subtrees": subtrees});
+        match serde_json::to_string(&payload) {
+            Ok(payload) => logger.publish("type-breakdown-event", payload),
+            Err(e) => error!("Failed to serialize type breakdown: {}", e),
+        }
+
+        if let Some(path) = &self.csv_path {
+            match self.export_csv(path) {
+                Ok(()) => info!("Exported type breakdown to {}", path),
+                Err(e) => error!("Failed to export type breakdown to {}: {}", path, e),
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "TypeBreakdownVisitor"
+    }
+
+    fn on_scan_start(&mut self, root: &str, _logger: &dyn EventHandler) {
+        self.scan_root = trim_dir(root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::scanner_impl::recording_event_handler::RecordingEventHandler;
+    use crate::state::type_breakdown::TypeTotals;
+
+    #[test]
+    fn test_breakdown_by_subtree() {
+        let logger = RecordingEventHandler::default();
+        let mut writer = Vec::new();
+        let csv = tempfile::NamedTempFile::new().unwrap();
+        let mut visitor = TypeBreakdownVisitor::new(1).with_csv(Some(csv.path().to_string_lossy().to_string()));
+        visitor.on_scan_start("/share/", &logger);
+
+        for (path, size) in [("/share/readme.md", 1), ("/share/video/a.mp4", 300), ("/share/video/2024/b.MKV", 200), ("/share/logs/app.log", 50)] {
+            visitor.visit(&Arc::new(ResourceMetadata::new(&path.to_string(), false, false, 0, size, false)), &mut writer, &logger);
+        }
+        visitor.visit(&Arc::new(ResourceMetadata::new(&"/share/video".to_string(), true, false, 0, 4096, false)), &mut writer, &logger);
+        visitor.recap(&mut writer, &logger);
+
+        let paths: Vec<&String> = visitor.breakdowns.keys().collect();
+        assert_eq!(paths, vec!["/share", "/share/logs", "/share/video"]);
+        let video = &visitor.breakdowns["/share/video"];
+        assert_eq!(video.total(), TypeTotals { files: 2, size_bytes: 500 });
+        assert_eq!(video.by_category[&FileCategory::Media], TypeTotals { files: 2, size_bytes: 500 });
+        assert_eq!(video.by_extension["mkv"], TypeTotals { files: 1, size_bytes: 200 });
+        assert_eq!(visitor.breakdowns["/share"].total(), TypeTotals { files: 4, size_bytes: 551 });
+
+        let event: serde_json::Value = serde_json::from_str(&logger.messages("type-breakdown-event")[0]).unwrap();
+        assert_eq!(event["root"], "/share");
+        assert_eq!(event["subtrees"][0]["categories"][0], json!({"key": "media", "files": 2, "size_bytes": 500}));
+        assert_eq!(event["subtrees"][1]["categories"][0]["key"], "logs");
+
+        let exported = std::fs::read_to_string(csv.path()).unwrap();
+        assert!(exported.lines().any(|l| l == "/share/video,extension,mp4,1,300"), "{}", exported);
+    }
+}
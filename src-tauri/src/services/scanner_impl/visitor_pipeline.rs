@@ -0,0 +1,113 @@
+use crate::services::scanner_api::visitable::Visitable;
+use crate::services::scanner_impl::visitor::age_analysis_visitor::AgeAnalysisVisitor;
+use crate::services::scanner_impl::hashing_scheduler::HashingScheduler;
+use crate::services::scanner_impl::keeper_selector::KeeperSelector;
+use crate::services::scanner_impl::visitor::directory_analyzer_visitor::DirectoryAnalyzerVisitor;
+use crate::services::scanner_impl::visitor::duplicate_file_visitor::DuplicateFileVisitor;
+use crate::services::scanner_impl::visitor::image_similarity_visitor::ImageSimilarityVisitor;
+use crate::services::scanner_impl::visitor::inode_hog_visitor::InodeHogVisitor;
+use crate::services::scanner_impl::visitor::junk_visitor::JunkVisitor;
+use crate::services::scanner_impl::visitor::ownership_visitor::OwnershipVisitor;
+use crate::services::scanner_impl::visitor::progress_visitor::ProgressVisitor;
+use crate::services::scanner_impl::visitor::scan_stats_visitor::ScanStatsVisitor;
+use crate::services::scanner_impl::visitor::symlink_visitor::SymlinkVisitor;
+use crate::services::scanner_impl::visitor::text_similarity_visitor::TextSimilarityVisitor;
+use crate::services::scanner_impl::visitor::top_k_resource_visitor::TopKResourceVisitor;
+use crate::services::scanner_impl::visitor::type_breakdown_visitor::TypeBreakdownVisitor;
+use crate::state::duplicate_set::DuplicateSet;
+use crate::state::registry_snapshot::RegistrySnapshot;
+use crate::state::visitor_config::{DuplicateFilesConfig, VisitorConfig};
+use crate::util::cancellation_token::CancellationToken;
+use crate::util::user_database::UserDatabase;
+
+/// The visitors of a scan, built from their configs and run in the order they were given.
+/// The duplicate file visitor is kept by type so its fingerprints can be saved after the recap.
+pub(crate) struct VisitorPipeline {
+    visitors: Vec<Box<dyn Visitable>>,
+    duplicate_file_visitor: Option<(usize, DuplicateFileVisitor)>,
+}
+
+impl VisitorPipeline {
+    /// Validates the configs, nothing is built if any of them is invalid. `previous` is the last
+    /// snapshot of the scanned root, for visitors that compare against it.
+    pub(crate) fn build(configs: &[VisitorConfig], cancellation: &CancellationToken, previous: Option<&RegistrySnapshot>) -> Result<Self, String> {
+        VisitorConfig::validate_all(configs)?;
+
+        let mut pipeline = VisitorPipeline { visitors: Vec::new(), duplicate_file_visitor: None };
+        for config in configs {
+            let visitor: Box<dyn Visitable> = match config {
+                VisitorConfig::Progress {} => Box::new(ProgressVisitor::new()),
+                VisitorConfig::ScanStats {} => Box::new(ScanStatsVisitor::new()),
+                VisitorConfig::TopK(c) => match c.directories {
+                    true => Box::new(TopKResourceVisitor::with_k(c.k).with_directories(previous.map(|s| s.rollups().clone()))),
+                    false => Box::new(TopKResourceVisitor::with_k(c.k)),
+                },
+                VisitorConfig::DirectoryAnalyzer(c) => Box::new(DirectoryAnalyzerVisitor::new().with_tree(c.depth, c.max_children)),
+                VisitorConfig::TypeBreakdown(c) => Box::new(TypeBreakdownVisitor::new(c.depth).with_csv(c.csv_path.clone())),
+                VisitorConfig::AgeAnalysis(c) => Box::new(AgeAnalysisVisitor::new(c.stale_days, c.depth, c.k)),
+                VisitorConfig::Symlinks {} => Box::new(SymlinkVisitor::new()),
+                VisitorConfig::Junk(c) => Box::new(JunkVisitor::new(c.all_rules())),
+                VisitorConfig::InodeHogs(c) => Box::new(InodeHogVisitor::new(c.k, c.tiny_file_bytes, c.min_files)),
+                VisitorConfig::Ownership(c) => Box::new(OwnershipVisitor::new(UserDatabase::load(), c.max_anomalies)),
+                VisitorConfig::DuplicateFiles(c) => {
+                    pipeline.duplicate_file_visitor = Some((pipeline.visitors.len(), Self::duplicate_file_visitor(c, cancellation)?));
+                    continue;
+                }
+                VisitorConfig::ImageSimilarity(c) => Box::new(ImageSimilarityVisitor::new(c.algorithm, c.max_distance, cancellation.clone())),
+                VisitorConfig::TextSimilarity(c) => Box::new(TextSimilarityVisitor::new(c.algorithm, c.threshold, cancellation.clone())),
+            };
+            pipeline.visitors.push(visitor);
+        }
+        Ok(pipeline)
+    }
+
+    fn duplicate_file_visitor(config: &DuplicateFilesConfig, cancellation: &CancellationToken) -> Result<DuplicateFileVisitor, String> {
+        let mut visitor = DuplicateFileVisitor::new(config.fingerprint_algorithm, config.verify, cancellation.clone());
+        if config.directories {
+            visitor = visitor.with_directories(config.subsets);
+        }
+        if config.hashing_workers.is_some() || config.hashing_bytes_per_second.is_some() {
+            let workers = config.hashing_workers.unwrap_or_else(HashingScheduler::default_workers);
+            visitor = visitor.with_scheduler(HashingScheduler::new(workers, config.hashing_bytes_per_second));
+        }
+        if let Some(policies) = &config.keeper_policies {
+            visitor = visitor.with_keeper_selector(KeeperSelector::new(policies.clone(), &config.keeper_action)?);
+        }
+        Ok(visitor)
+    }
+
+    pub(crate) fn visitors(&mut self) -> Vec<&mut dyn Visitable> {
+        let mut visitors: Vec<&mut dyn Visitable> = self.visitors.iter_mut().map(|v| v.as_mut() as &mut dyn Visitable).collect();
+        if let Some((position, visitor)) = &mut self.duplicate_file_visitor {
+            visitors.insert(*position, visitor);
+        }
+        visitors
+    }
+
+    /// Duplicate sets found by the recap, none without a duplicate file visitor.
+    pub(crate) fn duplicates(&self) -> &[DuplicateSet] {
+        self.duplicate_file_visitor.as_ref().map_or(&[], |(_, v)| v.duplicates())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::visitor_config::TextSimilarityConfig;
+
+    #[test]
+    fn test_builds_in_order() {
+        let configs: Vec<VisitorConfig> = serde_json::from_str(r#"[{"name": "scan_stats"}, {"name": "duplicate_files"}, {"name": "directory_analyzer"}]"#).unwrap();
+        let mut pipeline = VisitorPipeline::build(&configs, &CancellationToken::new(), None).unwrap();
+
+        let names: Vec<&str> = pipeline.visitors().iter().map(|v| v.name()).collect();
+        assert_eq!(names, vec!["ScanStatsVisitor", "DuplicateFileVisitor", "DirectoryAnalyzerVisitor"]);
+        assert!(pipeline.duplicates().is_empty());
+    }
+
+    #[test]
+    fn test_rejects_invalid_configs() {
+        let configs = [VisitorConfig::Progress {}, VisitorConfig::TextSimilarity(TextSimilarityConfig { threshold: -1.0, ..Default::default() })];
+        assert!(VisitorPipeline::build(&configs, &CancellationToken::new(), None).is_err());
+    }
+}
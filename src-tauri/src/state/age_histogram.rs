@@ -0,0 +1,72 @@
+use serde::Serialize;
+
+pub const DAY_SECONDS: i64 = 24 * 60 * 60;
+
+/// Upper bound in days and label of each age bucket, the last one takes everything older.
+pub const AGE_BUCKETS: [(i64, &str); 7] = [
+    (7, "under a week"),
+    (30, "under a month"),
+    (90, "1-3 months"),
+    (180, "3-6 months"),
+    (365, "6-12 months"),
+    (730, "1-2 years"),
+    (i64::MAX, "over 2 years"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AgeBucket {
+    pub label: &'static str,
+    pub files: u64,
+    pub size_bytes: u64,
+}
+
+/// Files and bytes by how many days ago a time of theirs was.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct AgeHistogram {
+    buckets: Vec<AgeBucket>,
+}
+
+impl Default for AgeHistogram {
+    fn default() -> Self {
+        AgeHistogram { buckets: AGE_BUCKETS.iter().map(|(_, label)| AgeBucket { label, files: 0, size_bytes: 0 }).collect() }
+    }
+}
+
+impl AgeHistogram {
+    /// Times in the future count as new.
+    pub fn add(&mut self, now: i64, time: i64, size_bytes: u64) {
+        let days = (now - time).max(0) / DAY_SECONDS;
+        let index = AGE_BUCKETS.iter().position(|(max_days, _)| days < *max_days).unwrap_or(AGE_BUCKETS.len() - 1);
+        self.buckets[index].files += 1;
+        self.buckets[index].size_bytes += size_bytes;
+    }
+}
+
+/// Age histograms of the files of a subtree.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SubtreeAges {
+    pub modified: AgeHistogram,
+    pub accessed: AgeHistogram,
+    pub changed: AgeHistogram,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        let now = 1_000 * DAY_SECONDS;
+        let mut histogram = AgeHistogram::default();
+        histogram.add(now, now + 10, 1);
+        histogram.add(now, now - 6 * DAY_SECONDS, 2);
+        histogram.add(now, now - 7 * DAY_SECONDS, 4);
+        histogram.add(now, 0, 8);
+
+        let sizes: Vec<u64> = histogram.buckets.iter().map(|b| b.size_bytes).collect();
+        assert_eq!(sizes, vec![3, 4, 0, 0, 0, 0, 8]);
+        assert_eq!(histogram.buckets[0].files, 2);
+        assert_eq!(serde_json::to_value(&histogram).unwrap()[6], serde_json::json!({"label": "over 2 years", "files": 1, "size_bytes": 8}));
+    }
+}
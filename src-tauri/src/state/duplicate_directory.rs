@@ -0,0 +1,35 @@
+use serde::Serialize;
+
+/// Directory trees with identical names and content. `size_bytes` is the file content of one tree.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DuplicateDirectorySet {
+    pub size_bytes: u64,
+    pub paths: Vec<String>,
+}
+
+impl DuplicateDirectorySet {
+    /// Bytes that would be reclaimed by keeping a single tree.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size_bytes * (self.paths.len() as u64).saturating_sub(1)
+    }
+}
+
+/// Directory whose every file is also found, under the same relative path and with the same
+/// content, in `superset`. Deleting `path` reclaims `size_bytes`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SubsetDirectory {
+    pub path: String,
+    pub superset: String,
+    pub size_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wasted_bytes() {
+        let set = DuplicateDirectorySet { size_bytes: 10, paths: vec!["/a".to_string(), "/b".to_string(), "/c".to_string()] };
+        assert_eq!(set.wasted_bytes(), 20);
+    }
+}
@@ -0,0 +1,43 @@
+use serde::Serialize;
+use crate::state::fingerprint::Fingerprint;
+
+/// How sure we are that the files of a set are identical.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationLevel {
+    /// Same size and fingerprint
+    Hashed,
+    /// Compared byte for byte
+    Verified,
+}
+
+/// Files with identical content. All paths share `size_bytes` and `fingerprint`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DuplicateSet {
+    pub size_bytes: u64,
+    pub fingerprint: Fingerprint,
+    pub paths: Vec<String>,
+    pub verification: VerificationLevel,
+}
+
+impl DuplicateSet {
+    /// Bytes that would be reclaimed by keeping a single copy.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size_bytes * (self.paths.len() as u64).saturating_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::fingerprint::FingerprintAlgorithm;
+
+    #[test]
+    fn test_wasted_bytes() {
+        let set = DuplicateSet { size_bytes: 10, fingerprint: Fingerprint::new(FingerprintAlgorithm::Xxh3_64, vec![0]), paths: vec!["/a".to_string(), "/b".to_string(), "/c".to_string()], verification: VerificationLevel::Hashed };
+        assert_eq!(set.wasted_bytes(), 20);
+
+        let single = DuplicateSet { size_bytes: 10, fingerprint: Fingerprint::new(FingerprintAlgorithm::Xxh3_64, vec![0]), paths: vec!["/a".to_string()], verification: VerificationLevel::Hashed };
+        assert_eq!(single.wasted_bytes(), 0);
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FingerprintAlgorithm {
+    #[default]
+    Xxh3_64,
+    Xxh3_128,
+    Blake3,
+    Sha256,
+}
+
+impl FingerprintAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FingerprintAlgorithm::Xxh3_64 => "xxh3-64",
+            FingerprintAlgorithm::Xxh3_128 => "xxh3-128",
+            FingerprintAlgorithm::Blake3 => "blake3",
+            FingerprintAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+impl fmt::Display for FingerprintAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for FingerprintAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xxh3-64" => Ok(FingerprintAlgorithm::Xxh3_64),
+            "xxh3-128" => Ok(FingerprintAlgorithm::Xxh3_128),
+            "blake3" => Ok(FingerprintAlgorithm::Blake3),
+            "sha256" => Ok(FingerprintAlgorithm::Sha256),
+            _ => Err(format!("Unknown fingerprint algorithm {}", s)),
+        }
+    }
+}
+
+/// Content digest tagged with the algorithm that produced it. Fingerprints from different
+/// algorithms never compare equal. The text form is `<algorithm>:<hex digest>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    algorithm: FingerprintAlgorithm,
+    digest: Vec<u8>,
+}
+
+impl Fingerprint {
+    pub fn new(algorithm: FingerprintAlgorithm, digest: Vec<u8>) -> Self {
+        Fingerprint { algorithm, digest }
+    }
+
+    pub fn algorithm(&self) -> FingerprintAlgorithm {
+        self.algorithm
+    }
+
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    pub fn hex(&self) -> String {
+        self.digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.hex())
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, hex) = s.split_once(':').ok_or_else(|| format!("Invalid fingerprint {}", s))?;
+        if hex.is_empty() || hex.len() % 2 != 0 {
+            return Err(format!("Invalid fingerprint digest {}", hex));
+        }
+        let digest = (0..hex.len())
+            .step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| format!("Invalid fingerprint digest {}", hex))?;
+        Ok(Fingerprint::new(algorithm.parse()?, digest))
+    }
+}
+
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let fingerprint = Fingerprint::new(FingerprintAlgorithm::Sha256, vec![0x00, 0xab, 0xff]);
+
+        assert_eq!(fingerprint.to_string(), "sha256:00abff");
+        assert_eq!("sha256:00abff".parse::<Fingerprint>(), Ok(fingerprint));
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert!("00abff".parse::<Fingerprint>().is_err());
+        assert!("md5:00abff".parse::<Fingerprint>().is_err());
+        assert!("sha256:0g".parse::<Fingerprint>().is_err());
+        assert!("sha256:abc".parse::<Fingerprint>().is_err());
+    }
+
+    #[test]
+    fn test_algorithms_are_distinct() {
+        let digest = vec![1, 2, 3];
+        assert_ne!(Fingerprint::new(FingerprintAlgorithm::Blake3, digest.clone()), Fingerprint::new(FingerprintAlgorithm::Sha256, digest));
+    }
+}
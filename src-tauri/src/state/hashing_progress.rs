@@ -0,0 +1,49 @@
+use std::time::Duration;
+use serde::Serialize;
+
+/// How far hashing a batch of files got, with the rate so far and the time left at that rate.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct HashingProgress {
+    /// Last file finished
+    pub path: String,
+    pub hashed_files: usize,
+    pub total_files: usize,
+    pub hashed_bytes: u64,
+    pub total_bytes: u64,
+    pub bytes_per_second: u64,
+    pub eta_seconds: Option<u64>,
+}
+
+impl HashingProgress {
+    pub fn new(total_files: usize, total_bytes: u64) -> Self {
+        HashingProgress { total_files, total_bytes, ..Default::default() }
+    }
+
+    /// Recomputes the rate and estimate from the time spent hashing so far. There's no estimate
+    /// until some bytes were hashed.
+    pub fn update_rate(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if self.hashed_bytes == 0 || seconds <= 0.0 {
+            return;
+        }
+        let rate = self.hashed_bytes as f64 / seconds;
+        self.bytes_per_second = rate as u64;
+        self.eta_seconds = Some((self.total_bytes.saturating_sub(self.hashed_bytes) as f64 / rate).ceil() as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_rate() {
+        let mut progress = HashingProgress::new(4, 1000);
+        progress.update_rate(Duration::from_secs(1));
+        assert_eq!(progress.eta_seconds, None);
+
+        progress.hashed_bytes = 250;
+        progress.update_rate(Duration::from_secs(2));
+        assert_eq!((progress.bytes_per_second, progress.eta_seconds), (125, Some(6)));
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::Serialize;
+
+/// 64 bit perceptual hashes, similar images have hashes a small Hamming distance apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PerceptualHashAlgorithm {
+    /// Gradient between neighbouring pixels of a 9x8 thumbnail, fast and robust to resizing
+    #[default]
+    DHash,
+    /// Low frequencies of the DCT of a 32x32 thumbnail, also robust to re-encoding and small edits
+    PHash,
+}
+
+impl PerceptualHashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PerceptualHashAlgorithm::DHash => "dhash",
+            PerceptualHashAlgorithm::PHash => "phash",
+        }
+    }
+}
+
+impl fmt::Display for PerceptualHashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for PerceptualHashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dhash" => Ok(PerceptualHashAlgorithm::DHash),
+            "phash" => Ok(PerceptualHashAlgorithm::PHash),
+            _ => Err(format!("Unknown perceptual hash {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SimilarImage {
+    pub path: String,
+    pub size_bytes: u64,
+    /// Hamming distance to the first image of the cluster
+    pub distance: u32,
+}
+
+/// Images that look alike, the largest one first as it's usually the original.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ImageCluster {
+    pub images: Vec<SimilarImage>,
+}
+
+impl ImageCluster {
+    /// Bytes that would be reclaimed by keeping only the first image.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.images.iter().skip(1).map(|i| i.size_bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for algorithm in [PerceptualHashAlgorithm::DHash, PerceptualHashAlgorithm::PHash] {
+            assert_eq!(algorithm.to_string().parse::<PerceptualHashAlgorithm>(), Ok(algorithm));
+        }
+        assert!("ahash".parse::<PerceptualHashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_wasted_bytes() {
+        let image = |path: &str, size_bytes| SimilarImage { path: path.to_string(), size_bytes, distance: 0 };
+        let cluster = ImageCluster { images: vec![image("/a.jpg", 100), image("/b.jpg", 40), image("/c.jpg", 10)] };
+        assert_eq!(cluster.wasted_bytes(), 50);
+    }
+}
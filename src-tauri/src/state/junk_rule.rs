@@ -0,0 +1,146 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+/// Regenerable files or directories, e.g. `{"category": "build_artifacts", "pattern": "target",
+/// "directory": true, "marker": "Cargo.toml"}`. `pattern` is matched against the last components
+/// of a path, one `/` separated glob per component where `*` and `?` never cross components.
+/// With a `marker` the match only counts when that file sits next to it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct JunkRule {
+    pub category: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub directory: bool,
+    #[serde(default)]
+    pub marker: Option<String>,
+}
+
+impl JunkRule {
+    fn new(category: &str, pattern: &str, directory: bool, marker: Option<&str>) -> Self {
+        JunkRule { category: category.to_string(), pattern: pattern.to_string(), directory, marker: marker.map(str::to_string) }
+    }
+
+    /// Caches, dependencies and build output that tools put back, plus swap files, OS metadata
+    /// and crash dumps nobody misses.
+    pub fn builtin() -> Vec<JunkRule> {
+        vec![
+            JunkRule::new("dependencies", "node_modules", true, Some("package.json")),
+            JunkRule::new("build_artifacts", "target", true, Some("Cargo.toml")),
+            JunkRule::new("build_artifacts", "build", true, Some("build.gradle")),
+            JunkRule::new("build_artifacts", "build", true, Some("build.gradle.kts")),
+            JunkRule::new("build_artifacts", ".gradle", true, None),
+            JunkRule::new("build_artifacts", ".next", true, Some("package.json")),
+            JunkRule::new("build_artifacts", "__pycache__", true, None),
+            JunkRule::new("build_artifacts", "*.pyc", false, None),
+            JunkRule::new("caches", ".pytest_cache", true, None),
+            JunkRule::new("caches", ".cache", true, None),
+            JunkRule::new("caches", "Library/Caches", true, None),
+            JunkRule::new("caches", ".npm/_cacache", true, None),
+            JunkRule::new("caches", ".cargo/registry", true, None),
+            JunkRule::new("caches", ".cargo/git", true, None),
+            JunkRule::new("editor_swap", "*.swp", false, None),
+            JunkRule::new("editor_swap", "*.swo", false, None),
+            JunkRule::new("editor_swap", "*~", false, None),
+            JunkRule::new("os_metadata", ".DS_Store", false, None),
+            JunkRule::new("os_metadata", "Thumbs.db", false, None),
+            JunkRule::new("crash_dumps", "*.dmp", false, None),
+            JunkRule::new("crash_dumps", "*.crash", false, None),
+            JunkRule::new("crash_dumps", "hs_err_pid*.log", false, None),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.category.is_empty() {
+            return Err(format!("junk rule {} has no category", self.pattern));
+        }
+        if self.pattern.is_empty() || self.pattern.split('/').any(str::is_empty) {
+            return Err(format!("junk rule pattern {:?} has empty components", self.pattern));
+        }
+        if self.marker.as_ref().is_some_and(|m| m.is_empty() || m.contains('/')) {
+            return Err(format!("junk rule marker {:?} must be a file name", self.marker));
+        }
+        Ok(())
+    }
+
+    /// Whether the resource at `path` is this rule's junk. Markers are looked up on disk.
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory != is_dir || !self.matches_path(path) {
+            return false;
+        }
+        match (&self.marker, Path::new(path).parent()) {
+            (Some(marker), Some(parent)) => parent.join(marker).exists(),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+
+    fn matches_path(&self, path: &str) -> bool {
+        let patterns: Vec<&str> = self.pattern.split('/').collect();
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        components.len() >= patterns.len()
+            && patterns.iter().rev().zip(components.iter().rev()).all(|(pattern, component)| glob_match(pattern, component))
+    }
+}
+
+/// `*` matches any run of characters, `?` any single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it was matched up to, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.swp", ".notes.txt.swp"));
+        assert!(glob_match("hs_err_pid*.log", "hs_err_pid4242.log"));
+        assert!(glob_match("*~", "main.rs~"));
+        assert!(glob_match("Thumbs.db", "Thumbs.db"));
+        assert!(glob_match("?.pyc", "a.pyc"));
+        assert!(!glob_match("*.swp", "swap.swpx"));
+        assert!(!glob_match("?.pyc", "ab.pyc"));
+    }
+
+    #[test]
+    fn test_matches() {
+        let dir = TempDir::new().unwrap();
+        let project = dir.path().join("project");
+        fs::create_dir(&project).unwrap();
+        let target = project.join("target").to_string_lossy().to_string();
+        let rust = JunkRule::new("build_artifacts", "target", true, Some("Cargo.toml"));
+
+        assert!(!rust.matches(&target, true));
+        fs::write(project.join("Cargo.toml"), b"[package]").unwrap();
+        assert!(rust.matches(&target, true));
+        assert!(!rust.matches(&target, false));
+
+        let caches = JunkRule::new("caches", "Library/Caches", true, None);
+        assert!(caches.matches("/Users/me/Library/Caches", true));
+        assert!(!caches.matches("/Users/me/Caches", true));
+        assert!(JunkRule::builtin().iter().all(|r| r.validate().is_ok()));
+        assert!(JunkRule::new("caches", "a//b", true, None).validate().is_err());
+    }
+}
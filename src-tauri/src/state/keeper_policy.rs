@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+/// Path components of directories whose files are usually stray copies.
+const TRANSIENT_DIRS: [&str; 4] = ["downloads", "tmp", "temp", ".cache"];
+
+/// Rule for picking the copy of a duplicate set that survives. Policies are applied in order,
+/// each one only breaks the ties left by the ones before it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum KeeperPolicy {
+    OldestModified,
+    ShortestPath,
+    /// Copies under an earlier root win, copies under none of them lose
+    PreferredRoots { roots: Vec<String> },
+    /// Copies in fewer Downloads and tmp style directories win
+    AvoidTransient,
+    RecentlyAccessed,
+}
+
+impl KeeperPolicy {
+    /// Sort key of a copy, lower keys win.
+    pub fn rank(&self, path: &str, modified: i64, accessed: i64) -> i64 {
+        match self {
+            KeeperPolicy::OldestModified => modified,
+            KeeperPolicy::ShortestPath => path.chars().count() as i64,
+            KeeperPolicy::PreferredRoots { roots } => roots
+                .iter()
+                .position(|root| Self::is_under(path, root))
+                .unwrap_or(roots.len()) as i64,
+            KeeperPolicy::AvoidTransient => path
+                .split('/')
+                .filter(|c| TRANSIENT_DIRS.contains(&c.to_lowercase().as_str()))
+                .count() as i64,
+            KeeperPolicy::RecentlyAccessed => -accessed,
+        }
+    }
+
+    /// Why a copy picked by this policy was kept, shown next to the keeper.
+    pub fn reason(&self) -> String {
+        match self {
+            KeeperPolicy::OldestModified => "oldest modification time".to_string(),
+            KeeperPolicy::ShortestPath => "shortest path".to_string(),
+            KeeperPolicy::PreferredRoots { .. } => "under a preferred root".to_string(),
+            KeeperPolicy::AvoidTransient => "fewest Downloads and temporary directories in its path".to_string(),
+            KeeperPolicy::RecentlyAccessed => "most recently accessed".to_string(),
+        }
+    }
+
+    fn is_under(path: &str, root: &str) -> bool {
+        let root = root.trim_end_matches('/');
+        path == root || path.starts_with(&format!("{}/", root))
+    }
+}
+
+/// Action staged for a copy that isn't kept, in the shape `commit` takes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StagedAction {
+    pub action: String,
+    pub path: String,
+    pub bytes: u64,
+    pub duplicate_of: String,
+}
+
+/// The copy a policy kept, the policies that decided it and the actions for the other copies.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct KeeperDecision {
+    pub keeper: String,
+    pub reasons: Vec<String>,
+    pub actions: Vec<StagedAction>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank() {
+        let preferred = KeeperPolicy::PreferredRoots { roots: vec!["/photos/".to_string(), "/backup".to_string()] };
+        assert_eq!(preferred.rank("/photos/a.jpg", 0, 0), 0);
+        assert_eq!(preferred.rank("/backup/a.jpg", 0, 0), 1);
+        assert_eq!(preferred.rank("/backups/a.jpg", 0, 0), 2);
+
+        assert_eq!(KeeperPolicy::AvoidTransient.rank("/home/u/Downloads/a.jpg", 0, 0), 1);
+        assert_eq!(KeeperPolicy::AvoidTransient.rank("/home/u/downloaded/a.jpg", 0, 0), 0);
+        assert_eq!(KeeperPolicy::AvoidTransient.rank("/tmp/Downloads/a.jpg", 0, 0), 2);
+        assert!(KeeperPolicy::RecentlyAccessed.rank("/a", 0, 20) < KeeperPolicy::RecentlyAccessed.rank("/b", 0, 10));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let policies: Vec<KeeperPolicy> = serde_json::from_str(r#"[{"policy": "oldest_modified"}, {"policy": "preferred_roots", "roots": ["/a"]}]"#).unwrap();
+        assert_eq!(policies, vec![KeeperPolicy::OldestModified, KeeperPolicy::PreferredRoots { roots: vec!["/a".to_string()] }]);
+    }
+}
@@ -1,2 +1,16 @@
 pub mod scan_stats;
-pub mod resource_metadata;
\ No newline at end of file
+pub mod resource_metadata;
+pub mod registry_snapshot;
+pub mod registry;
+pub mod duplicate_set;
+
+pub mod fingerprint;
+pub mod duplicate_directory;
+pub mod keeper_policy;
+pub mod image_cluster;
+pub mod text_cluster;
+pub mod hashing_progress;
+pub mod visitor_config;
+pub mod type_breakdown;
+pub mod age_histogram;
+pub mod junk_rule;
\ No newline at end of file
@@ -0,0 +1,550 @@
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::HashMap;
+use std::mem::size_of;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+use crate::state::fingerprint::Fingerprint;
+use crate::state::resource_metadata::{Ownership, ResourceMetadata};
+
+pub(crate) type EntryId = u32;
+
+const NONE: EntryId = EntryId::MAX;
+
+/// Modified time recorded for entries that must be resynced by the next incremental scan.
+pub(crate) const INVALIDATED_MTIME: i64 = i64::MIN;
+
+const FLAG_PRESENT: u8 = 0b001;
+const FLAG_DIR: u8 = 0b010;
+const FLAG_SYMLINK: u8 = 0b100;
+const FLAG_OWNERSHIP: u8 = 0b1000;
+
+/// Fixed width registry node. Names live in the shared arena and the full path is rebuilt by
+/// following parent pointers, so each path is stored once as its last component only.
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    modified: i64,
+    size_bytes: u64,
+    uid: u32,
+    gid: u32,
+    mode: u32,
+    parent: EntryId,
+    first_child: EntryId,
+    next_sibling: EntryId,
+    name_offset: u32,
+    name_len: u16,
+    flags: u8,
+}
+
+/// Memory-compact registry of resources keyed by path.
+///
+/// Entries are interned into a tree of fixed width nodes: a name arena holds every path
+/// component once, nodes point at their parent and children, and a hash of (parent, name) maps
+/// to a node id. Nodes are never freed, removing an entry only clears its present flag so
+/// ids stay stable while a scan is running. Intermediate directories that were never inserted
+/// exist as non-present placeholders.
+#[derive(Default)]
+pub struct Registry {
+    names: Vec<u8>,
+    nodes: Vec<Node>,
+    index: HashMap<u64, EntryId>,
+    // (parent, name) pairs whose hash collided with another node
+    overflow: HashMap<(EntryId, Box<str>), EntryId>,
+    // Only the entries that were hashed carry a fingerprint, so they're kept off the nodes
+    fingerprints: HashMap<EntryId, Fingerprint>,
+    len: usize,
+}
+
+fn components(path: &str) -> Vec<&str> {
+    if path == "/" {
+        vec![""]
+    } else {
+        path.split('/').collect()
+    }
+}
+
+fn key(parent: EntryId, name: &str) -> u64 {
+    xxh3_64_with_seed(name.as_bytes(), parent as u64)
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(warnings)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn name(&self, node: &Node) -> &str {
+        let start = node.name_offset as usize;
+        // Names are only ever appended from &str so the arena slice is valid UTF-8
+        std::str::from_utf8(&self.names[start..start + node.name_len as usize]).unwrap_or_default()
+    }
+
+    fn child(&self, parent: EntryId, name: &str) -> Option<EntryId> {
+        match self.index.get(&key(parent, name)) {
+            Some(&id) if self.nodes[id as usize].parent == parent && self.name(&self.nodes[id as usize]) == name => Some(id),
+            Some(_) => self.overflow.get(&(parent, Box::from(name))).copied(),
+            None => None,
+        }
+    }
+
+    fn find(&self, path: &str) -> Option<EntryId> {
+        let mut current = NONE;
+        for component in components(path) {
+            current = self.child(current, component)?;
+        }
+        Some(current)
+    }
+
+    fn find_or_create(&mut self, path: &str) -> EntryId {
+        let mut current = NONE;
+        for component in components(path) {
+            current = match self.child(current, component) {
+                Some(id) => id,
+                None => self.create(current, component),
+            };
+        }
+        current
+    }
+
+    fn create(&mut self, parent: EntryId, name: &str) -> EntryId {
+        let id = self.nodes.len() as EntryId;
+        let name_offset = self.names.len() as u32;
+        let name_len = name.len().min(u16::MAX as usize);
+        self.names.extend_from_slice(&name.as_bytes()[..name_len]);
+
+        let next_sibling = if parent == NONE {
+            NONE
+        } else {
+            std::mem::replace(&mut self.nodes[parent as usize].first_child, id)
+        };
+
+        self.nodes.push(Node {
+            modified: 0,
+            size_bytes: 0,
+            uid: 0,
+            gid: 0,
+            mode: 0,
+            parent,
+            first_child: NONE,
+            next_sibling,
+            name_offset,
+            name_len: name_len as u16,
+            flags: 0,
+        });
+
+        match self.index.entry(key(parent, name)) {
+            Vacant(e) => {
+                e.insert(id);
+            }
+            Occupied(_) => {
+                self.overflow.insert((parent, Box::from(name)), id);
+            }
+        }
+        id
+    }
+
+    /// Rebuilds the full path of an entry from its ancestors.
+    pub(crate) fn path(&self, id: EntryId) -> String {
+        let mut parts = Vec::new();
+        let mut current = id;
+        while current != NONE {
+            let node = &self.nodes[current as usize];
+            parts.push(self.name(node));
+            current = node.parent;
+        }
+        parts.reverse();
+
+        let path = parts.join("/");
+        if path.is_empty() && !parts.is_empty() {
+            "/".to_string()
+        } else {
+            path
+        }
+    }
+
+    fn materialize(&self, id: EntryId, path: &str) -> Option<ResourceMetadata> {
+        let node = &self.nodes[id as usize];
+        if node.flags & FLAG_PRESENT == 0 {
+            return None;
+        }
+
+        Some(ResourceMetadata::new(&path.to_string(), node.flags & FLAG_DIR != 0, node.flags & FLAG_SYMLINK != 0, node.modified, node.size_bytes, false)
+            .with_fingerprint(self.fingerprints.get(&id).cloned())
+            .with_ownership((node.flags & FLAG_OWNERSHIP != 0).then_some(Ownership { uid: node.uid, gid: node.gid, mode: node.mode })))
+    }
+
+    pub fn contains_key(&self, path: &str) -> bool {
+        self.find(path).is_some_and(|id| self.nodes[id as usize].flags & FLAG_PRESENT != 0)
+    }
+
+    pub fn get(&self, path: &str) -> Option<ResourceMetadata> {
+        self.find(path).and_then(|id| self.materialize(id, path))
+    }
+
+    pub(crate) fn get_by_id(&self, id: EntryId) -> Option<ResourceMetadata> {
+        self.materialize(id, &self.path(id))
+    }
+
+    /// Inserts or replaces the entry at the metadata's path. A known fingerprint survives the
+    /// update as long as the content looks unchanged, i.e. same modified time and size.
+    pub fn insert(&mut self, metadata: &ResourceMetadata) -> EntryId {
+        let id = self.find_or_create(metadata.get_path());
+        let node = &mut self.nodes[id as usize];
+        if node.flags & FLAG_PRESENT == 0 {
+            self.len += 1;
+        }
+
+        match metadata.fingerprint() {
+            Some(fingerprint) => {
+                self.fingerprints.insert(id, fingerprint.clone());
+            }
+            None if node.modified != metadata.modified() || node.size_bytes != metadata.size_bytes() => {
+                self.fingerprints.remove(&id);
+            }
+            None => {}
+        }
+
+        node.modified = metadata.modified();
+        node.size_bytes = metadata.size_bytes();
+        let ownership = metadata.ownership();
+        let Ownership { uid, gid, mode } = ownership.unwrap_or(Ownership { uid: 0, gid: 0, mode: 0 });
+        (node.uid, node.gid, node.mode) = (uid, gid, mode);
+        node.flags = FLAG_PRESENT
+            | if metadata.is_dir() { FLAG_DIR } else { 0 }
+            | if metadata.is_symlink() { FLAG_SYMLINK } else { 0 }
+            | if ownership.is_some() { FLAG_OWNERSHIP } else { 0 };
+        id
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<ResourceMetadata> {
+        let id = self.find(path)?;
+        let removed = self.materialize(id, path)?;
+        self.nodes[id as usize].flags &= !FLAG_PRESENT;
+        self.fingerprints.remove(&id);
+        self.len -= 1;
+        Some(removed)
+    }
+
+    /// Removes `root` and every entry below it.
+    pub fn remove_subtree(&mut self, root: &str) {
+        if let Some(id) = self.find(root) {
+            for id in self.walk_ids(id) {
+                let node = &mut self.nodes[id as usize];
+                if node.flags & FLAG_PRESENT != 0 {
+                    node.flags &= !FLAG_PRESENT;
+                    self.len -= 1;
+                }
+                self.fingerprints.remove(&id);
+            }
+        }
+    }
+
+    /// Drops everything below `path` and marks the entry itself as changed, forcing the next
+    /// incremental scan to rediscover the subtree from the filesystem.
+    pub fn invalidate(&mut self, path: &str) {
+        if let Some(id) = self.find(path) {
+            for child in self.walk_ids(id).into_iter().skip(1) {
+                let node = &mut self.nodes[child as usize];
+                if node.flags & FLAG_PRESENT != 0 {
+                    node.flags &= !FLAG_PRESENT;
+                    self.len -= 1;
+                }
+                self.fingerprints.remove(&child);
+            }
+            self.nodes[id as usize].modified = INVALIDATED_MTIME;
+            self.fingerprints.remove(&id);
+        }
+    }
+
+    /// Present entries whose parent directory is missing although a higher ancestor is present.
+    /// Scan roots have no present ancestors and are never reported.
+    pub fn orphans(&self) -> Vec<String> {
+        let present = |id: EntryId| self.nodes[id as usize].flags & FLAG_PRESENT != 0;
+        let mut orphans: Vec<String> = (0..self.nodes.len() as EntryId)
+            .filter(|id| present(*id))
+            .filter(|id| {
+                let parent = self.nodes[*id as usize].parent;
+                if parent == NONE || present(parent) {
+                    return false;
+                }
+                let mut ancestor = self.nodes[parent as usize].parent;
+                while ancestor != NONE {
+                    if present(ancestor) {
+                        return true;
+                    }
+                    ancestor = self.nodes[ancestor as usize].parent;
+                }
+                false
+            })
+            .map(|id| self.path(id))
+            .collect();
+        orphans.sort();
+        orphans
+    }
+
+    /// Records the content fingerprint of a present entry, returns false if there is no such entry.
+    pub fn set_fingerprint(&mut self, path: &str, fingerprint: Fingerprint) -> bool {
+        match self.find(path) {
+            Some(id) if self.nodes[id as usize].flags & FLAG_PRESENT != 0 => {
+                self.fingerprints.insert(id, fingerprint);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Paths of the present children of `path`, sorted.
+    pub fn children(&self, path: &str) -> Vec<String> {
+        let mut children: Vec<String> = match self.find(path) {
+            Some(id) => self.child_ids(id)
+                .filter(|c| self.nodes[*c as usize].flags & FLAG_PRESENT != 0)
+                .map(|c| self.path(c))
+                .collect(),
+            None => Vec::new(),
+        };
+        children.sort();
+        children
+    }
+
+    fn child_ids(&self, id: EntryId) -> impl Iterator<Item = EntryId> + '_ {
+        let mut current = self.nodes[id as usize].first_child;
+        std::iter::from_fn(move || {
+            if current == NONE {
+                return None;
+            }
+            let id = current;
+            current = self.nodes[id as usize].next_sibling;
+            Some(id)
+        })
+    }
+
+    fn walk_ids(&self, root: EntryId) -> Vec<EntryId> {
+        let mut ids = Vec::new();
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            ids.push(id);
+            let mut children: Vec<EntryId> = self.child_ids(id).collect();
+            // Push in reverse name order so children are walked in name order
+            children.sort_by(|a, b| self.name(&self.nodes[*b as usize]).cmp(self.name(&self.nodes[*a as usize])));
+            stack.extend(children);
+        }
+        ids
+    }
+
+    /// Ids of the present entries at and below `root` in depth-first order, children in name
+    /// order. Walking ids instead of paths keeps large scans from materializing every path up front.
+    pub(crate) fn walk(&self, root: &str) -> Vec<EntryId> {
+        match self.find(root) {
+            Some(id) => self.walk_ids(id)
+                .into_iter()
+                .filter(|id| self.nodes[*id as usize].flags & FLAG_PRESENT != 0)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Every present entry, materialized one at a time.
+    pub fn iter(&self) -> impl Iterator<Item = ResourceMetadata> + '_ {
+        (0..self.nodes.len() as EntryId).filter_map(move |id| {
+            if self.nodes[id as usize].flags & FLAG_PRESENT == 0 {
+                None
+            } else {
+                self.get_by_id(id)
+            }
+        })
+    }
+
+    /// Approximate heap footprint in bytes, used to track the memory targets in the benchmarks.
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.names.capacity()
+            + self.nodes.capacity() * size_of::<Node>()
+            + self.index.capacity() * (size_of::<(u64, EntryId)>() + 1)
+            + self.overflow.iter().map(|((_, n), _)| n.len() + size_of::<((EntryId, Box<str>), EntryId)>()).sum::<usize>()
+            + self.fingerprints.capacity() * (size_of::<(EntryId, Fingerprint)>() + 1)
+            + self.fingerprints.values().map(|f| f.digest().len()).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn metadata(path: &str, is_dir: bool, size: u64) -> ResourceMetadata {
+        ResourceMetadata::new(&path.to_string(), is_dir, false, 42, size, false)
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut registry = Registry::new();
+        registry.insert(&metadata("/a", true, 0));
+        registry.insert(&metadata("/a/b.txt", false, 10));
+
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get("/a/b.txt"), Some(metadata("/a/b.txt", false, 10)));
+        assert!(registry.contains_key("/a"));
+        assert!(!registry.contains_key("/a/missing"));
+    }
+
+    #[test]
+    fn test_ownership() {
+        let mut registry = Registry::new();
+        let owned = metadata("/a", false, 1).with_ownership(Some(Ownership { uid: 1000, gid: 100, mode: 0o104755 }));
+        registry.insert(&owned);
+        assert_eq!(registry.get("/a"), Some(owned));
+
+        registry.insert(&metadata("/a", false, 1));
+        assert_eq!(registry.get("/a").unwrap().ownership(), None);
+    }
+
+    #[test]
+    fn test_intermediate_dirs_are_placeholders() {
+        let mut registry = Registry::new();
+        registry.insert(&metadata("/x/y/z", false, 1));
+
+        assert_eq!(registry.len(), 1);
+        assert!(!registry.contains_key("/x"));
+        assert!(registry.get("/x/y").is_none());
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut registry = Registry::new();
+        registry.insert(&metadata("/a", false, 1));
+        registry.insert(&metadata("/a", false, 2));
+
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.get("/a").unwrap().size_bytes(), 2);
+    }
+
+    #[test]
+    fn test_remove_and_reinsert() {
+        let mut registry = Registry::new();
+        registry.insert(&metadata("/a", true, 0));
+        registry.insert(&metadata("/a/b", false, 5));
+
+        assert_eq!(registry.remove("/a/b"), Some(metadata("/a/b", false, 5)));
+        assert_eq!(registry.remove("/a/b"), None);
+        assert_eq!(registry.len(), 1);
+
+        registry.insert(&metadata("/a/b", false, 6));
+        assert_eq!(registry.get("/a/b").unwrap().size_bytes(), 6);
+    }
+
+    #[test]
+    fn test_remove_subtree() {
+        let mut registry = Registry::new();
+        for m in [metadata("/a", true, 0), metadata("/a/b", true, 0), metadata("/a/b/c", false, 1), metadata("/ab", false, 1)] {
+            registry.insert(&m);
+        }
+
+        registry.remove_subtree("/a");
+
+        assert_eq!(registry.len(), 1);
+        assert!(registry.contains_key("/ab"));
+    }
+
+    #[test]
+    fn test_paths_round_trip() {
+        let mut registry = Registry::new();
+        for p in ["/", "/a", "relative/file.txt", "single"] {
+            let id = registry.insert(&metadata(p, false, 0));
+            assert_eq!(registry.path(id), p);
+        }
+        assert_eq!(registry.len(), 4);
+    }
+
+    #[test]
+    fn test_walk_and_children() {
+        let mut registry = Registry::new();
+        for (p, is_dir) in [("/r", true), ("/r/b", true), ("/r/b/f", false), ("/r/a", false)] {
+            registry.insert(&metadata(p, is_dir, 0));
+        }
+
+        let walked: Vec<String> = registry.walk("/r").into_iter().map(|id| registry.path(id)).collect();
+
+        assert_eq!(walked, vec!["/r", "/r/a", "/r/b", "/r/b/f"]);
+        assert_eq!(registry.children("/r"), vec!["/r/a", "/r/b"]);
+        assert_eq!(registry.iter().count(), 4);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let mut registry = Registry::new();
+        for m in [metadata("/a", true, 0), metadata("/a/b", true, 0), metadata("/a/b/c", false, 1)] {
+            registry.insert(&m);
+        }
+
+        registry.invalidate("/a/b");
+
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get("/a/b").unwrap().modified(), INVALIDATED_MTIME);
+        assert_eq!(registry.get("/a").unwrap().modified(), 42);
+    }
+
+    #[test]
+    fn test_fingerprints() {
+        let fingerprint: Fingerprint = "sha256:00ff".parse().unwrap();
+        let mut registry = Registry::new();
+        registry.insert(&metadata("/a", true, 0));
+        registry.insert(&metadata("/a/f", false, 1).with_fingerprint(Some(fingerprint.clone())));
+        assert!(!registry.set_fingerprint("/a/missing", fingerprint.clone()));
+
+        // Unchanged content keeps its fingerprint
+        registry.insert(&metadata("/a/f", false, 1));
+        assert_eq!(registry.get("/a/f").unwrap().fingerprint(), Some(&fingerprint));
+
+        // A size change drops it
+        registry.insert(&metadata("/a/f", false, 2));
+        assert_eq!(registry.get("/a/f").unwrap().fingerprint(), None);
+
+        assert!(registry.set_fingerprint("/a/f", fingerprint.clone()));
+        registry.remove_subtree("/a");
+        registry.insert(&metadata("/a/f", false, 2));
+        assert_eq!(registry.get("/a/f").unwrap().fingerprint(), None);
+    }
+
+    #[test]
+    fn test_orphans() {
+        let mut registry = Registry::new();
+        for m in [metadata("/root", true, 0), metadata("/root/gone/child", false, 1), metadata("/root/ok", false, 1), metadata("/other/scan", true, 0)] {
+            registry.insert(&m);
+        }
+
+        assert_eq!(registry.orphans(), vec!["/root/gone/child"]);
+    }
+
+    /// Memory benchmark for a synthetic 1M entry tree, run with `cargo test -- --ignored --nocapture`.
+    /// Target: under 100 bytes per entry for the compact registry, at least 2.5x smaller than the
+    /// previous `HashMap<String, ResourceMetadata>` layout (~265 bytes per entry for these paths).
+    #[test]
+    #[ignore]
+    fn bench_registry_memory() {
+        const ENTRIES: usize = 1_000_000;
+        let start = Instant::now();
+        let mut registry = Registry::new();
+        let mut legacy_bytes = 0;
+
+        for i in 0..ENTRIES {
+            let path = format!("/home/user/projects/project{}/src/module{}/file{}.rs", i % 100, i % 1000, i);
+            let m = metadata(&path, false, i as u64);
+            // Key and ResourceMetadata::path each own a copy of the path
+            legacy_bytes += 2 * path.capacity() + size_of::<(String, ResourceMetadata)>() + 1;
+            registry.insert(&m);
+        }
+
+        let compact_bytes = registry.memory_bytes();
+        let per_entry = compact_bytes / registry.len();
+        println!("entries={} compact={} bytes ({} per entry) legacy={} bytes ({} per entry) elapsed={:?}",
+                 registry.len(), compact_bytes, per_entry, legacy_bytes, legacy_bytes / ENTRIES, start.elapsed());
+
+        assert!(per_entry < 100);
+        assert!(2 * legacy_bytes > 5 * compact_bytes);
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::Serialize;
+use crate::state::registry::Registry;
+use crate::util::util::trim_dir;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DirectoryRollup {
+    pub size_bytes: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SnapshotEntry {
+    pub is_dir: bool,
+    pub modified: i64,
+    pub size_bytes: u64,
+}
+
+/// Compact, point-in-time view of the registry for a single scan run. Every directory under the
+/// root gets a cumulative rollup, individual entries are only kept when requested.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistrySnapshot {
+    id: String,
+    timestamp: i64,
+    root: String,
+    rollups: HashMap<String, DirectoryRollup>,
+    entries: HashMap<String, SnapshotEntry>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Growth {
+    pub path: String,
+    pub before: u64,
+    pub after: u64,
+    pub delta: i64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SizedPath {
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SnapshotDiff {
+    pub from: String,
+    pub to: String,
+    pub growers: Vec<Growth>,
+    /// Large files the older snapshot has no entry for, either new or grown past the entry
+    /// threshold since, smaller files aren't recorded to tell them apart
+    pub new_or_newly_large_files: Vec<SizedPath>,
+    pub deleted_trees: Vec<SizedPath>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SeriesPoint {
+    pub snapshot: String,
+    pub timestamp: i64,
+    pub size_bytes: u64,
+}
+
+impl RegistrySnapshot {
+    pub(crate) fn new(id: &str, timestamp: i64, root: &str, rollups: HashMap<String, DirectoryRollup>, entries: HashMap<String, SnapshotEntry>) -> Self {
+        RegistrySnapshot {
+            id: id.to_string(),
+            timestamp,
+            root: root.to_string(),
+            rollups,
+            entries,
+        }
+    }
+
+    /// Builds a snapshot of everything under `root`, ignoring a trailing `/`. Entries whose size
+    /// is at least `min_entry_bytes` are kept individually, `None` keeps rollups only.
+    pub(crate) fn capture(id: &str, timestamp: i64, root: &str, registry: &Registry, min_entry_bytes: Option<u64>) -> Self {
+        let root = &trim_dir(root);
+        let mut rollups: HashMap<String, DirectoryRollup> = HashMap::new();
+        let mut entries = HashMap::new();
+
+        for metadata in registry.walk(root).into_iter().filter_map(|id| registry.get_by_id(id)) {
+            let path = metadata.get_path();
+            if metadata.is_dir() {
+                rollups.entry(path.clone()).or_default();
+            }
+
+            if let Some(threshold) = min_entry_bytes {
+                if metadata.size_bytes() >= threshold {
+                    entries.insert(path.clone(), SnapshotEntry {
+                        is_dir: metadata.is_dir(),
+                        modified: metadata.modified(),
+                        size_bytes: metadata.size_bytes(),
+                    });
+                }
+            }
+
+            // Roll the entry up into every ancestor until the scan root is reached
+            let mut current = Path::new(path).parent();
+            while let Some(parent) = current {
+                if !parent.starts_with(root) {
+                    break;
+                }
+
+                let rollup = rollups.entry(parent.to_string_lossy().to_string()).or_default();
+                if metadata.is_dir() {
+                    rollup.dir_count += 1;
+                } else {
+                    rollup.file_count += 1;
+                    rollup.size_bytes += metadata.size_bytes();
+                }
+                current = parent.parent();
+            }
+        }
+
+        RegistrySnapshot::new(id, timestamp, root, rollups, entries)
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub(crate) fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub(crate) fn root(&self) -> &str {
+        &self.root
+    }
+
+    pub(crate) fn rollups(&self) -> &HashMap<String, DirectoryRollup> {
+        &self.rollups
+    }
+
+    pub(crate) fn entries(&self) -> &HashMap<String, SnapshotEntry> {
+        &self.entries
+    }
+
+    pub(crate) fn rollup(&self, path: &str) -> Option<&DirectoryRollup> {
+        self.rollups.get(path)
+    }
+
+    /// Compares `self` (older) against `other` (newer). Growers and new or newly large files are
+    /// limited to `limit` rows each, the files are only reported when at least `min_file_bytes`
+    /// in size.
+    pub(crate) fn diff(&self, other: &RegistrySnapshot, limit: usize, min_file_bytes: u64) -> SnapshotDiff {
+        let mut growers: Vec<Growth> = other.rollups.iter()
+            .filter_map(|(path, after)| {
+                let before = self.rollups.get(path).map(|r| r.size_bytes).unwrap_or(0);
+                let delta = after.size_bytes as i64 - before as i64;
+                if delta > 0 {
+                    Some(Growth { path: path.clone(), before, after: after.size_bytes, delta })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        growers.sort_by(|a, b| b.delta.cmp(&a.delta).then_with(|| a.path.cmp(&b.path)));
+        growers.truncate(limit);
+
+        let mut new_or_newly_large_files: Vec<SizedPath> = other.entries.iter()
+            .filter(|(path, e)| !e.is_dir && e.size_bytes >= min_file_bytes && !self.entries.contains_key(*path))
+            .map(|(path, e)| SizedPath { path: path.clone(), size_bytes: e.size_bytes })
+            .collect();
+        new_or_newly_large_files.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.path.cmp(&b.path)));
+        new_or_newly_large_files.truncate(limit);
+
+        // Only report the top-most directory of a removed subtree
+        let mut deleted_trees: Vec<SizedPath> = self.rollups.iter()
+            .filter(|(path, _)| !other.rollups.contains_key(*path))
+            .filter(|(path, _)| {
+                Path::new(path).parent()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .is_none_or(|p| other.rollups.contains_key(&p) || !self.rollups.contains_key(&p))
+            })
+            .map(|(path, r)| SizedPath { path: path.clone(), size_bytes: r.size_bytes })
+            .collect();
+        deleted_trees.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.path.cmp(&b.path)));
+
+        SnapshotDiff {
+            from: self.id.clone(),
+            to: other.id.clone(),
+            growers,
+            new_or_newly_large_files,
+            deleted_trees,
+        }
+    }
+}
+
+/// Cumulative size of `path` across `snapshots`, ordered oldest first. Snapshots that don't
+/// contain the directory are skipped.
+pub(crate) fn size_series(snapshots: &[RegistrySnapshot], path: &str) -> Vec<SeriesPoint> {
+    let mut series: Vec<SeriesPoint> = snapshots.iter()
+        .filter_map(|s| s.rollup(path).map(|r| SeriesPoint {
+            snapshot: s.id().to_string(),
+            timestamp: s.timestamp(),
+            size_bytes: r.size_bytes,
+        }))
+        .collect();
+    series.sort_by_key(|p| p.timestamp);
+    series
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::state::resource_metadata::ResourceMetadata;
+
+    fn registry(entries: &[(&str, bool, u64)]) -> Registry {
+        let mut registry = Registry::new();
+        for (p, is_dir, size) in entries {
+            registry.insert(&ResourceMetadata::new(&p.to_string(), *is_dir, false, 0, *size, false));
+        }
+        registry
+    }
+
+    #[test]
+    fn test_capture_rolls_up_ancestors() {
+        let r = registry(&[
+            ("/a", true, 96),
+            ("/a/foo.txt", false, 100),
+            ("/a/b", true, 96),
+            ("/a/b/bar.txt", false, 50),
+            ("/other/baz.txt", false, 1000),
+        ]);
+
+        let snapshot = RegistrySnapshot::capture("s1", 1, "/a", &r, None);
+
+        assert_eq!(snapshot.rollup("/a"), Some(&DirectoryRollup { size_bytes: 150, file_count: 2, dir_count: 1 }));
+        assert_eq!(snapshot.rollup("/a/b"), Some(&DirectoryRollup { size_bytes: 50, file_count: 1, dir_count: 0 }));
+        assert!(snapshot.rollup("/other").is_none());
+        assert!(snapshot.entries().is_empty());
+    }
+
+    #[test]
+    fn test_capture_compares_whole_path_components() {
+        let r = registry(&[("/a", true, 96), ("/a/f", false, 10), ("/ab", true, 96), ("/ab/g", false, 20)]);
+
+        let snapshot = RegistrySnapshot::capture("s1", 1, "/a/", &r, None);
+
+        assert_eq!(snapshot.rollup("/a"), Some(&DirectoryRollup { size_bytes: 10, file_count: 1, dir_count: 0 }));
+        assert!(snapshot.rollup("/ab").is_none());
+    }
+
+    #[test]
+    fn test_capture_keeps_entries_above_threshold() {
+        let r = registry(&[("/a", true, 96), ("/a/small", false, 10), ("/a/big", false, 5000)]);
+
+        let snapshot = RegistrySnapshot::capture("s1", 1, "/a", &r, Some(1000));
+
+        assert_eq!(snapshot.entries().len(), 1);
+        assert!(snapshot.entries().contains_key("/a/big"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = RegistrySnapshot::capture("s1", 1, "/a", &registry(&[
+            ("/a", true, 0),
+            ("/a/b", true, 0),
+            ("/a/b/f", false, 100),
+            ("/a/b/grown", false, 10),
+            ("/a/old", true, 0),
+            ("/a/old/deep", true, 0),
+            ("/a/old/deep/f", false, 700),
+        ]), Some(1000));
+        let after = RegistrySnapshot::capture("s2", 2, "/a", &registry(&[
+            ("/a", true, 0),
+            ("/a/b", true, 0),
+            ("/a/b/f", false, 100),
+            ("/a/b/grown", false, 2000),
+            ("/a/b/new", false, 5000),
+        ]), Some(1000));
+
+        let diff = before.diff(&after, 10, 1000);
+
+        assert_eq!(diff.growers, vec![
+            Growth { path: "/a/b".to_string(), before: 110, after: 7100, delta: 6990 },
+            Growth { path: "/a".to_string(), before: 810, after: 7100, delta: 6290 },
+        ]);
+        // The older snapshot had no entry for the grown file, it's reported like a new one
+        assert_eq!(diff.new_or_newly_large_files, vec![
+            SizedPath { path: "/a/b/new".to_string(), size_bytes: 5000 },
+            SizedPath { path: "/a/b/grown".to_string(), size_bytes: 2000 },
+        ]);
+        assert_eq!(diff.deleted_trees, vec![SizedPath { path: "/a/old".to_string(), size_bytes: 700 }]);
+    }
+
+    #[test]
+    fn test_size_series() {
+        let r1 = registry(&[("/a", true, 0), ("/a/f", false, 10)]);
+        let r2 = registry(&[("/a", true, 0), ("/a/f", false, 30)]);
+        let snapshots = vec![
+            RegistrySnapshot::capture("s2", 2, "/a", &r2, None),
+            RegistrySnapshot::capture("s1", 1, "/a", &r1, None),
+        ];
+
+        let series = size_series(&snapshots, "/a");
+
+        assert_eq!(series.iter().map(|p| p.size_bytes).collect::<Vec<_>>(), vec![10, 30]);
+        assert!(size_series(&snapshots, "/missing").is_empty());
+    }
+}
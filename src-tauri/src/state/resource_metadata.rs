@@ -1,6 +1,17 @@
 use std::{fmt};
 use std::cmp::Ordering;
-use xxhash_rust::xxh3::xxh3_64;
+use log::warn;
+use crate::services::file_api::fingerprint_service::FingerprintService;
+use crate::services::file_impl::streaming_fingerprint_service::StreamingFingerprintService;
+use crate::state::fingerprint::Fingerprint;
+
+/// Owner, group and permission bits with the file type, as in `st_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ownership {
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ResourceMetadata {
@@ -9,18 +20,22 @@ pub struct ResourceMetadata {
     is_file: bool,
     is_symlink: bool,
     modified: i64,
+    // Access and status change times, only known for resources the scan just looked at
+    accessed: Option<i64>,
+    changed: Option<i64>,
+    // Unknown for registries written before it was recorded
+    ownership: Option<Ownership>,
     file_size_bytes: u64,
-    fingerprint: u64,
+    fingerprint: Option<Fingerprint>,
 }
 
 impl ResourceMetadata {
     pub(crate) fn new(p: &String, is_dir: bool, is_symlink: bool, modified: i64, file_size_bytes: u64, fingerprint: bool) -> Self {
         let fingerprint = if fingerprint {
-            let file_path = p;
-            let file_content = std::fs::read(file_path).expect("Failed to read file content");
-            xxh3_64(&file_content)
+            // Unreadable files are left without a fingerprint rather than failing the scan
+            StreamingFingerprintService::default().fingerprint(p, &mut |_, _| {}).map_err(|e| warn!("Failed to fingerprint {}: {}", p, e)).ok()
         } else {
-            0
+            None
         };
 
         ResourceMetadata {
@@ -29,6 +44,9 @@ impl ResourceMetadata {
             is_file: !is_dir,
             is_symlink,
             modified,
+            accessed: None,
+            changed: None,
+            ownership: None,
             file_size_bytes,
             fingerprint,
         }
@@ -55,10 +73,31 @@ impl ResourceMetadata {
         self.modified
     }
 
+    pub(crate) fn accessed(&self) -> Option<i64> { self.accessed }
+
+    pub(crate) fn changed(&self) -> Option<i64> { self.changed }
+
+    pub(crate) fn ownership(&self) -> Option<Ownership> { self.ownership }
+
     pub(crate) fn size_bytes(&self) -> u64 { self.file_size_bytes }
 
-    #[allow(warnings)]
-    pub(crate) fn fingerprint(&self) -> u64 { self.fingerprint }
+    pub(crate) fn fingerprint(&self) -> Option<&Fingerprint> { self.fingerprint.as_ref() }
+
+    pub(crate) fn with_fingerprint(mut self, fingerprint: Option<Fingerprint>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    pub(crate) fn with_ownership(mut self, ownership: Option<Ownership>) -> Self {
+        self.ownership = ownership;
+        self
+    }
+
+    pub(crate) fn with_times(mut self, accessed: i64, changed: i64) -> Self {
+        self.accessed = Some(accessed);
+        self.changed = Some(changed);
+        self
+    }
 }
 
 impl fmt::Display for ResourceMetadata {
@@ -93,6 +132,7 @@ mod tests {
     use std::collections::HashMap;
     use std::io;
     use std::io::Write;
+    use std::sync::Arc;
     use tempfile::NamedTempFile;
     use crate::services::scanner_api::event_handler::EventHandler;
     use crate::services::scanner_impl::noop_event_handler::NoopEventHandler;
@@ -116,7 +156,7 @@ mod tests {
     }
 
     impl Visitable for VisitorMock {
-        fn visit(&mut self, metadata: &ResourceMetadata, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
+        fn visit(&mut self, metadata: &Arc<ResourceMetadata>, _writer: &mut dyn io::Write, _logger: &dyn EventHandler) {
             self.visited.insert(self.name, true);
             // Add specific assertions based on your needs
             assert_eq!(metadata.is_dir(), true);
@@ -138,7 +178,7 @@ mod tests {
         let is_symlink = false;
         let modified = 123456789;
 
-        let metadata = ResourceMetadata::new(&path, is_dir, is_symlink, modified, 0, false);
+        let metadata = Arc::new(ResourceMetadata::new(&path, is_dir, is_symlink, modified, 0, false));
         let mut buffer: Vec<u8> = Vec::new();
         let mut writer = io::BufWriter::new(&mut buffer);
 
@@ -162,7 +202,7 @@ mod tests {
         let is_symlink = false;
         let modified = 123456789;
 
-        let metadata = ResourceMetadata::new(&path, is_dir, is_symlink, modified, 0, false);
+        let metadata = Arc::new(ResourceMetadata::new(&path, is_dir, is_symlink, modified, 0, false));
 
         let mut buffer: Vec<u8> = Vec::new();
         let mut writer = io::BufWriter::new(&mut buffer);
@@ -225,7 +265,13 @@ mod tests {
 
         let metadata = ResourceMetadata::new(&path, is_dir, is_symlink, modified, 0, true);
 
-        let hash = metadata.fingerprint();
-        assert_eq!(hash, 1307564309130158671);
+        let hash = metadata.fingerprint().map(|f| f.to_string());
+        assert_eq!(hash, Some(format!("xxh3-64:{:016x}", 1307564309130158671u64)));
+    }
+
+    #[test]
+    fn test_unreadable_file_has_no_fingerprint() {
+        let metadata = ResourceMetadata::new(&"/does/not/exist".to_string(), false, false, 0, 0, true);
+        assert_eq!(metadata.fingerprint(), None);
     }
 }
\ No newline at end of file
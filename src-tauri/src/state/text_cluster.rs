@@ -0,0 +1,100 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextSignatureAlgorithm {
+    /// Minimum hashes of the shingles under many permutations, estimates their Jaccard similarity
+    #[default]
+    MinHash,
+    /// Single 64 bit hash summing the bits of all shingles, cheaper but coarser
+    SimHash,
+}
+
+impl TextSignatureAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextSignatureAlgorithm::MinHash => "minhash",
+            TextSignatureAlgorithm::SimHash => "simhash",
+        }
+    }
+}
+
+impl fmt::Display for TextSignatureAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for TextSignatureAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "minhash" => Ok(TextSignatureAlgorithm::MinHash),
+            "simhash" => Ok(TextSignatureAlgorithm::SimHash),
+            _ => Err(format!("Unknown text signature {}", s)),
+        }
+    }
+}
+
+/// Summary of the word shingles of a text, close signatures mean mostly the same text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextSignature {
+    MinHash(Vec<u64>),
+    SimHash(u64),
+}
+
+impl TextSignature {
+    /// Estimated similarity between 0 and 1. Signatures of different algorithms or sizes are 0 apart.
+    pub fn similarity(&self, other: &TextSignature) -> f64 {
+        match (self, other) {
+            (TextSignature::MinHash(a), TextSignature::MinHash(b)) if a.len() == b.len() && !a.is_empty() => {
+                a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+            }
+            (TextSignature::SimHash(a), TextSignature::SimHash(b)) => 1.0 - (a ^ b).count_ones() as f64 / 64.0,
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SimilarText {
+    pub path: String,
+    pub size_bytes: u64,
+    /// Estimated similarity to the first file of the cluster
+    pub similarity: f64,
+}
+
+/// Files that are mostly the same text, the most recently modified one first.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TextCluster {
+    pub files: Vec<SimilarText>,
+}
+
+impl TextCluster {
+    /// Bytes that would be reclaimed by keeping only the first file.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.files.iter().skip(1).map(|f| f.size_bytes).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for algorithm in [TextSignatureAlgorithm::MinHash, TextSignatureAlgorithm::SimHash] {
+            assert_eq!(algorithm.to_string().parse::<TextSignatureAlgorithm>(), Ok(algorithm));
+        }
+        assert!("lsh".parse::<TextSignatureAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(TextSignature::MinHash(vec![1, 2, 3, 4]).similarity(&TextSignature::MinHash(vec![1, 2, 0, 4])), 0.75);
+        assert_eq!(TextSignature::SimHash(0).similarity(&TextSignature::SimHash(0xffff)), 0.75);
+        assert_eq!(TextSignature::SimHash(0).similarity(&TextSignature::MinHash(vec![0])), 0.0);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use csv::WriterBuilder;
+use serde::Serialize;
+
+pub const NO_EXTENSION: &str = "(none)";
+
+const ARCHIVE_EXTENSIONS: [&str; 14] = ["zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "jar", "war", "iso", "dmg", "deb"];
+const DOCUMENT_EXTENSIONS: [&str; 13] = ["pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp", "rtf", "md", "txt"];
+const CODE_EXTENSIONS: [&str; 24] = [
+    "rs", "py", "js", "jsx", "ts", "tsx", "java", "kt", "c", "h", "cpp", "hpp", "cs", "go", "rb", "php", "sh", "swift",
+    "scala", "sql", "toml", "yaml", "yml", "json",
+];
+const BINARY_EXTENSIONS: [&str; 12] = ["exe", "dll", "so", "dylib", "o", "a", "lib", "bin", "class", "pyc", "wasm", "rlib"];
+const LOG_EXTENSIONS: [&str; 2] = ["log", "out"];
+
+/// High level kind of a file, picked from its extension and failing that its MIME type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileCategory {
+    Media,
+    Archives,
+    Documents,
+    Code,
+    Binaries,
+    Logs,
+    Other,
+}
+
+impl FileCategory {
+    pub fn classify(extension: &str, mime_type: &str) -> FileCategory {
+        let extension = extension.to_lowercase();
+        if LOG_EXTENSIONS.contains(&extension.as_str()) {
+            return FileCategory::Logs;
+        }
+        if ARCHIVE_EXTENSIONS.contains(&extension.as_str()) {
+            return FileCategory::Archives;
+        }
+        if DOCUMENT_EXTENSIONS.contains(&extension.as_str()) {
+            return FileCategory::Documents;
+        }
+        if CODE_EXTENSIONS.contains(&extension.as_str()) {
+            return FileCategory::Code;
+        }
+        if BINARY_EXTENSIONS.contains(&extension.as_str()) {
+            return FileCategory::Binaries;
+        }
+        match mime_type.split_once('/') {
+            Some(("image" | "video" | "audio", _)) => FileCategory::Media,
+            Some(("text", "css" | "html" | "javascript" | "xml")) | Some(("application", "javascript" | "json" | "xml")) => FileCategory::Code,
+            Some(("text", subtype)) if subtype.starts_with("x-") => FileCategory::Code,
+            Some(("text", _)) => FileCategory::Documents,
+            Some(("application", "octet-stream")) => FileCategory::Binaries,
+            Some(("application", subtype)) if subtype.starts_with("vnd.") || subtype.starts_with("msword") => FileCategory::Documents,
+            _ => FileCategory::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileCategory::Media => "media",
+            FileCategory::Archives => "archives",
+            FileCategory::Documents => "documents",
+            FileCategory::Code => "code",
+            FileCategory::Binaries => "binaries",
+            FileCategory::Logs => "logs",
+            FileCategory::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for FileCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TypeTotals {
+    pub files: u64,
+    pub size_bytes: u64,
+}
+
+/// Row of a breakdown, one extension, MIME type or category.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TypeEntry {
+    pub key: String,
+    pub files: u64,
+    pub size_bytes: u64,
+}
+
+/// Bytes and files of a subtree by extension, MIME type and category.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeBreakdown {
+    pub by_extension: HashMap<String, TypeTotals>,
+    pub by_mime_type: HashMap<String, TypeTotals>,
+    pub by_category: HashMap<FileCategory, TypeTotals>,
+}
+
+impl TypeBreakdown {
+    pub fn add(&mut self, extension: &str, mime_type: &str, category: FileCategory, size_bytes: u64) {
+        for totals in [
+            self.by_extension.entry(extension.to_string()).or_default(),
+            self.by_mime_type.entry(mime_type.to_string()).or_default(),
+            self.by_category.entry(category).or_default(),
+        ] {
+            totals.files += 1;
+            totals.size_bytes += size_bytes;
+        }
+    }
+
+    pub fn total(&self) -> TypeTotals {
+        self.by_category.values().fold(TypeTotals::default(), |total, t| TypeTotals { files: total.files + t.files, size_bytes: total.size_bytes + t.size_bytes })
+    }
+
+    pub fn extensions(&self) -> Vec<TypeEntry> {
+        Self::entries(self.by_extension.iter().map(|(k, t)| (k.clone(), t)))
+    }
+
+    pub fn mime_types(&self) -> Vec<TypeEntry> {
+        Self::entries(self.by_mime_type.iter().map(|(k, t)| (k.clone(), t)))
+    }
+
+    pub fn categories(&self) -> Vec<TypeEntry> {
+        Self::entries(self.by_category.iter().map(|(k, t)| (k.name().to_string(), t)))
+    }
+
+    /// Largest first.
+    fn entries<'a>(totals: impl Iterator<Item=(String, &'a TypeTotals)>) -> Vec<TypeEntry> {
+        let mut entries: Vec<TypeEntry> = totals.map(|(key, t)| TypeEntry { key, files: t.files, size_bytes: t.size_bytes }).collect();
+        entries.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.key.cmp(&b.key)));
+        entries
+    }
+}
+
+/// Writes breakdowns as CSV with a `subtree,kind,key,files,size_bytes` header, `kind` being
+/// `category`, `mime_type` or `extension`.
+pub fn write_csv<'a>(breakdowns: impl IntoIterator<Item=(&'a String, &'a TypeBreakdown)>, w: &mut dyn io::Write) -> io::Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(w);
+    writer.write_record(["subtree", "kind", "key", "files", "size_bytes"])?;
+    for (subtree, breakdown) in breakdowns {
+        for (kind, entries) in [("category", breakdown.categories()), ("mime_type", breakdown.mime_types()), ("extension", breakdown.extensions())] {
+            for entry in entries {
+                writer.write_record([subtree.as_str(), kind, &entry.key, &entry.files.to_string(), &entry.size_bytes.to_string()])?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(FileCategory::classify("MP4", "video/mp4"), FileCategory::Media);
+        assert_eq!(FileCategory::classify("tgz", "application/octet-stream"), FileCategory::Archives);
+        assert_eq!(FileCategory::classify("log", "text/plain"), FileCategory::Logs);
+        assert_eq!(FileCategory::classify("csv", "text/csv"), FileCategory::Documents);
+        assert_eq!(FileCategory::classify("rs", "text/x-rust"), FileCategory::Code);
+        assert_eq!(FileCategory::classify("", "application/octet-stream"), FileCategory::Binaries);
+        assert_eq!(FileCategory::classify("woff2", "font/woff2"), FileCategory::Other);
+    }
+
+    #[test]
+    fn test_breakdown_csv() {
+        let mut breakdown = TypeBreakdown::default();
+        breakdown.add("mp4", "video/mp4", FileCategory::Media, 300);
+        breakdown.add("png", "image/png", FileCategory::Media, 20);
+        breakdown.add("log", "text/plain", FileCategory::Logs, 100);
+
+        assert_eq!(breakdown.total(), TypeTotals { files: 3, size_bytes: 420 });
+        assert_eq!(breakdown.categories(), vec![
+            TypeEntry { key: "media".to_string(), files: 2, size_bytes: 320 },
+            TypeEntry { key: "logs".to_string(), files: 1, size_bytes: 100 },
+        ]);
+
+        let mut csv = Vec::new();
+        write_csv([(&"/data".to_string(), &breakdown)], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 2 + 3 + 3);
+        assert_eq!(lines[0], "subtree,kind,key,files,size_bytes");
+        assert_eq!(lines[1], "/data,category,media,2,320");
+        assert_eq!(lines[3], "/data,mime_type,video/mp4,1,300");
+        assert_eq!(lines[8], "/data,extension,png,1,20");
+    }
+}
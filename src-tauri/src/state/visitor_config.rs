@@ -0,0 +1,365 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use crate::state::fingerprint::FingerprintAlgorithm;
+use crate::state::image_cluster::PerceptualHashAlgorithm;
+use crate::state::junk_rule::JunkRule;
+use crate::state::keeper_policy::KeeperPolicy;
+use crate::state::text_cluster::TextSignatureAlgorithm;
+
+pub const DEFAULT_IMAGE_DISTANCE: u32 = 8;
+pub const DEFAULT_TEXT_THRESHOLD: f64 = 0.8;
+pub const DEFAULT_TOP_K: usize = 50;
+pub const DEFAULT_TREE_DEPTH: usize = 3;
+pub const DEFAULT_TREE_CHILDREN: usize = 50;
+pub const DEFAULT_SUBTREE_DEPTH: usize = 1;
+pub const DEFAULT_STALE_DAYS: u64 = 365;
+pub const DEFAULT_TINY_FILE_BYTES: u64 = 4096;
+pub const DEFAULT_MIN_FILES: u64 = 100;
+pub const DEFAULT_MAX_ANOMALIES: usize = 1000;
+const KEEPER_ACTIONS: [&str; 2] = ["delete", "hardlink"];
+
+/// A visitor enabled for a scan, picked by `name` with its config next to it, e.g.
+/// `{"name": "text_similarity", "threshold": 0.9}`. Fields left out take their defaults,
+/// unknown names and fields are rejected.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case", deny_unknown_fields)]
+pub enum VisitorConfig {
+    Progress {},
+    ScanStats {},
+    TopK(TopKConfig),
+    DirectoryAnalyzer(DirectoryAnalyzerConfig),
+    TypeBreakdown(TypeBreakdownConfig),
+    AgeAnalysis(AgeAnalysisConfig),
+    Symlinks {},
+    Junk(JunkConfig),
+    InodeHogs(InodeHogsConfig),
+    Ownership(OwnershipConfig),
+    DuplicateFiles(DuplicateFilesConfig),
+    ImageSimilarity(ImageSimilarityConfig),
+    TextSimilarity(TextSimilarityConfig),
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct TopKConfig {
+    /// Entries of each ranking
+    pub k: usize,
+    /// Also rank directories by size, file count and growth since the previous scan
+    pub directories: bool,
+}
+
+impl Default for TopKConfig {
+    fn default() -> Self {
+        TopKConfig { k: DEFAULT_TOP_K, directories: true }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct DirectoryAnalyzerConfig {
+    /// Levels below the scan root in the published tree
+    pub depth: usize,
+    /// Largest children of each directory in the published tree
+    pub max_children: usize,
+}
+
+impl Default for DirectoryAnalyzerConfig {
+    fn default() -> Self {
+        DirectoryAnalyzerConfig { depth: DEFAULT_TREE_DEPTH, max_children: DEFAULT_TREE_CHILDREN }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct TypeBreakdownConfig {
+    /// Levels below the scan root broken down on their own
+    pub depth: usize,
+    /// CSV file the breakdowns are exported to
+    pub csv_path: Option<String>,
+}
+
+impl Default for TypeBreakdownConfig {
+    fn default() -> Self {
+        TypeBreakdownConfig { depth: DEFAULT_SUBTREE_DEPTH, csv_path: None }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct AgeAnalysisConfig {
+    /// Days without being modified or read after which files and directories are stale
+    pub stale_days: u64,
+    /// Levels below the scan root with their own age histograms
+    pub depth: usize,
+    /// Largest stale files and directories listed
+    pub k: usize,
+}
+
+impl Default for AgeAnalysisConfig {
+    fn default() -> Self {
+        AgeAnalysisConfig { stale_days: DEFAULT_STALE_DAYS, depth: DEFAULT_SUBTREE_DEPTH, k: DEFAULT_TOP_K }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct JunkConfig {
+    /// Also classify with the built-in rules, checked after `rules`
+    pub builtin_rules: bool,
+    pub rules: Vec<JunkRule>,
+}
+
+impl Default for JunkConfig {
+    fn default() -> Self {
+        JunkConfig { builtin_rules: true, rules: Vec::new() }
+    }
+}
+
+impl JunkConfig {
+    pub fn all_rules(&self) -> Vec<JunkRule> {
+        let mut rules = self.rules.clone();
+        if self.builtin_rules {
+            rules.extend(JunkRule::builtin());
+        }
+        rules
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct InodeHogsConfig {
+    /// Directories of each ranking
+    pub k: usize,
+    /// Files up to this size count as tiny
+    pub tiny_file_bytes: u64,
+    /// Files a directory needs to be ranked by its average file size
+    pub min_files: u64,
+}
+
+impl Default for InodeHogsConfig {
+    fn default() -> Self {
+        InodeHogsConfig { k: DEFAULT_TOP_K, tiny_file_bytes: DEFAULT_TINY_FILE_BYTES, min_files: DEFAULT_MIN_FILES }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct OwnershipConfig {
+    /// Anomalies of each kind listed, the rest are only counted
+    pub max_anomalies: usize,
+}
+
+impl Default for OwnershipConfig {
+    fn default() -> Self {
+        OwnershipConfig { max_anomalies: DEFAULT_MAX_ANOMALIES }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct DuplicateFilesConfig {
+    #[serde(deserialize_with = "from_str")]
+    pub fingerprint_algorithm: FingerprintAlgorithm,
+    /// Compare duplicates byte for byte before they're reported
+    pub verify: bool,
+    /// Also report copied directories
+    pub directories: bool,
+    /// With `directories`, also report directories whose files are all found in another one
+    pub subsets: bool,
+    /// Picks the copy of each duplicate set to keep, nothing is staged without policies
+    pub keeper_policies: Option<Vec<KeeperPolicy>>,
+    /// `delete` or `hardlink`, staged for the copies that aren't kept
+    pub keeper_action: String,
+    /// Threads hashing duplicate candidates, one per core (up to 8) when left out
+    pub hashing_workers: Option<usize>,
+    /// Cap on the bytes read per second while hashing
+    pub hashing_bytes_per_second: Option<u64>,
+}
+
+impl Default for DuplicateFilesConfig {
+    fn default() -> Self {
+        DuplicateFilesConfig {
+            fingerprint_algorithm: FingerprintAlgorithm::default(),
+            verify: true,
+            directories: false,
+            subsets: false,
+            keeper_policies: None,
+            keeper_action: "delete".to_string(),
+            hashing_workers: None,
+            hashing_bytes_per_second: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct ImageSimilarityConfig {
+    #[serde(deserialize_with = "from_str")]
+    pub algorithm: PerceptualHashAlgorithm,
+    /// Bits two image hashes may differ by
+    pub max_distance: u32,
+}
+
+impl Default for ImageSimilarityConfig {
+    fn default() -> Self {
+        ImageSimilarityConfig { algorithm: PerceptualHashAlgorithm::default(), max_distance: DEFAULT_IMAGE_DISTANCE }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct TextSimilarityConfig {
+    #[serde(deserialize_with = "from_str")]
+    pub algorithm: TextSignatureAlgorithm,
+    /// Similarity between 0 and 1 texts need to be clustered
+    pub threshold: f64,
+}
+
+impl Default for TextSimilarityConfig {
+    fn default() -> Self {
+        TextSimilarityConfig { algorithm: TextSignatureAlgorithm::default(), threshold: DEFAULT_TEXT_THRESHOLD }
+    }
+}
+
+impl VisitorConfig {
+    /// Visitors of a scan that doesn't pick its own.
+    pub fn defaults() -> Vec<VisitorConfig> {
+        vec![VisitorConfig::Progress {}, VisitorConfig::ScanStats {}, VisitorConfig::TopK(TopKConfig::default()), VisitorConfig::Symlinks {}]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VisitorConfig::Progress {} => "progress",
+            VisitorConfig::ScanStats {} => "scan_stats",
+            VisitorConfig::TopK(_) => "top_k",
+            VisitorConfig::DirectoryAnalyzer(_) => "directory_analyzer",
+            VisitorConfig::TypeBreakdown(_) => "type_breakdown",
+            VisitorConfig::AgeAnalysis(_) => "age_analysis",
+            VisitorConfig::Symlinks {} => "symlinks",
+            VisitorConfig::Junk(_) => "junk",
+            VisitorConfig::InodeHogs(_) => "inode_hogs",
+            VisitorConfig::Ownership(_) => "ownership",
+            VisitorConfig::DuplicateFiles(_) => "duplicate_files",
+            VisitorConfig::ImageSimilarity(_) => "image_similarity",
+            VisitorConfig::TextSimilarity(_) => "text_similarity",
+        }
+    }
+
+    /// Checks the values serde can't, like ranges.
+    pub fn validate(&self) -> Result<(), String> {
+        let invalid = |reason: String| Err(format!("Invalid {} config: {}", self.name(), reason));
+        match self {
+            VisitorConfig::TopK(c) if c.k == 0 => invalid("k must be at least 1".to_string()),
+            VisitorConfig::DirectoryAnalyzer(c) if c.max_children == 0 => invalid("maxChildren must be at least 1".to_string()),
+            VisitorConfig::TypeBreakdown(c) if c.csv_path.as_deref() == Some("") => invalid("csvPath is empty".to_string()),
+            VisitorConfig::AgeAnalysis(c) if c.stale_days == 0 => invalid("staleDays must be at least 1".to_string()),
+            VisitorConfig::AgeAnalysis(c) if c.k == 0 => invalid("k must be at least 1".to_string()),
+            VisitorConfig::Junk(c) if c.rules.is_empty() && !c.builtin_rules => invalid("no rules".to_string()),
+            VisitorConfig::Junk(c) => c.rules.iter().try_for_each(JunkRule::validate).or_else(invalid),
+            VisitorConfig::InodeHogs(c) if c.k == 0 => invalid("k must be at least 1".to_string()),
+            VisitorConfig::DuplicateFiles(c) if !KEEPER_ACTIONS.contains(&c.keeper_action.as_str()) => invalid(format!("unknown keeper action {}", c.keeper_action)),
+            VisitorConfig::DuplicateFiles(c) if c.subsets && !c.directories => invalid("subsets need directories".to_string()),
+            VisitorConfig::DuplicateFiles(c) if c.hashing_workers == Some(0) => invalid("hashingWorkers must be at least 1".to_string()),
+            VisitorConfig::DuplicateFiles(c) if c.hashing_bytes_per_second == Some(0) => invalid("hashingBytesPerSecond must be at least 1".to_string()),
+            VisitorConfig::ImageSimilarity(c) if c.max_distance > 64 => invalid(format!("maxDistance {} is over 64 bits", c.max_distance)),
+            VisitorConfig::TextSimilarity(c) if !(0.0..=1.0).contains(&c.threshold) => invalid(format!("threshold {} is not between 0 and 1", c.threshold)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Validates every config and that no visitor is enabled twice.
+    pub fn validate_all(configs: &[VisitorConfig]) -> Result<(), String> {
+        let mut names = HashSet::new();
+        for config in configs {
+            if !names.insert(config.name()) {
+                return Err(format!("Visitor {} is enabled more than once", config.name()));
+            }
+            config.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Reads a JSON array of configs from a file.
+    pub fn load(path: &str) -> Result<Vec<VisitorConfig>, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read visitors from {}: {}", path, e))?;
+        serde_json::from_str(&content).map_err(|e| format!("Invalid visitors in {}: {}", path, e))
+    }
+}
+
+/// `name` or `name:{json config}`, as given on the command line.
+impl FromStr for VisitorConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, config) = s.split_once(':').unwrap_or((s, "{}"));
+        let mut value: Value = serde_json::from_str(config).map_err(|e| format!("Invalid config of visitor {}: {}", name, e))?;
+        match value.as_object_mut() {
+            Some(fields) => fields.insert("name".to_string(), Value::String(name.to_string())),
+            None => return Err(format!("Config of visitor {} is not an object", name)),
+        };
+        serde_json::from_value(value).map_err(|e| format!("Invalid visitor {}: {}", name, e))
+    }
+}
+
+fn from_str<'de, D: Deserializer<'de>, T>(deserializer: D) -> Result<T, D::Error> where T: FromStr, T::Err: Display {
+    String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_configs() {
+        let configs: Vec<VisitorConfig> = serde_json::from_str(r#"[
+            {"name": "top_k"},
+            {"name": "text_similarity", "algorithm": "simhash"},
+            {"name": "duplicate_files", "keeperPolicies": [{"policy": "shortest_path"}], "keeperAction": "hardlink"}
+        ]"#).unwrap();
+
+        assert_eq!(configs[0], VisitorConfig::TopK(TopKConfig::default()));
+        assert_eq!(configs[1], VisitorConfig::TextSimilarity(TextSimilarityConfig { algorithm: TextSignatureAlgorithm::SimHash, threshold: DEFAULT_TEXT_THRESHOLD }));
+        assert_eq!(configs[2], VisitorConfig::DuplicateFiles(DuplicateFilesConfig {
+            keeper_policies: Some(vec![KeeperPolicy::ShortestPath]),
+            keeper_action: "hardlink".to_string(),
+            ..Default::default()
+        }));
+        assert!(VisitorConfig::validate_all(&configs).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_unknown_and_misconfigured_visitors() {
+        for json in [
+            r#"{"name": "spinning_beachball"}"#,
+            r#"{"name": "progress", "verbose": true}"#,
+            r#"{"name": "image_similarity", "maxDistanse": 4}"#,
+            r#"{"name": "image_similarity", "algorithm": "ahash"}"#,
+        ] {
+            assert!(serde_json::from_str::<VisitorConfig>(json).is_err(), "{}", json);
+        }
+
+        let invalid = [
+            VisitorConfig::TextSimilarity(TextSimilarityConfig { threshold: 1.5, ..Default::default() }),
+            VisitorConfig::DuplicateFiles(DuplicateFilesConfig { keeper_action: "shred".to_string(), ..Default::default() }),
+            VisitorConfig::DuplicateFiles(DuplicateFilesConfig { subsets: true, ..Default::default() }),
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+        assert!(VisitorConfig::validate_all(&[VisitorConfig::ScanStats {}, VisitorConfig::ScanStats {}]).is_err());
+    }
+
+    #[test]
+    fn test_from_command_line() {
+        assert_eq!("scan_stats".parse::<VisitorConfig>(), Ok(VisitorConfig::ScanStats {}));
+        assert_eq!(
+            r#"image_similarity:{"maxDistance": 4}"#.parse::<VisitorConfig>(),
+            Ok(VisitorConfig::ImageSimilarity(ImageSimilarityConfig { max_distance: 4, ..Default::default() }))
+        );
+        assert!("image_similarity:4".parse::<VisitorConfig>().is_err());
+    }
+}
@@ -0,0 +1,19 @@
+use log::info;
+use tauri::{command, Window};
+use crate::services::scanner_impl::visitor::directory_analyzer_visitor::DirectoryAnalyzerVisitor;
+use crate::state::visitor_config::{DEFAULT_TREE_CHILDREN, DEFAULT_TREE_DEPTH};
+use crate::ui::handler::tauri_event_handler::TauriEventHandler;
+use crate::replay_registry;
+
+/// Publishes the tree below `path` from the registry as a `directory-tree-event`, so the UI can
+/// drill into a directory the tree of the scan stopped at without scanning again.
+#[command]
+pub async fn directory_tree(w: Window, uid: &str, path: &str, depth: Option<usize>) -> Result<String, String> {
+    info!("[{}] directory_tree path = {} depth = {:?}", uid, path, depth);
+    let logger = TauriEventHandler { window: w };
+
+    let visitor = || DirectoryAnalyzerVisitor::new().with_tree(depth.unwrap_or(DEFAULT_TREE_DEPTH), DEFAULT_TREE_CHILDREN);
+    replay_registry(uid, path, visitor, &logger).await?;
+
+    Ok(format!("Published the tree below {}", path))
+}
@@ -1,2 +1,7 @@
 pub(crate) mod scan_filesystem;
-pub(crate) mod staged_resource_manager;
\ No newline at end of file
+pub(crate) mod staged_resource_manager;
+pub(crate) mod snapshot_manager;
+pub(crate) mod registry_exchange;
+pub(crate) mod registry_verification;
+pub(crate) mod directory_tree;
+pub(crate) mod type_breakdown;
\ No newline at end of file
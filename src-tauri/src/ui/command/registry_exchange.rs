@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use log::{error, info};
+use tauri::{command, Window};
+use crate::services::file_impl::streaming_fingerprint_service::StreamingFingerprintService;
+use crate::services::registry_api::registry_codec::RegistryCodec;
+use crate::services::registry_impl::fingerprint_manifest::write_manifest;
+use crate::services::registry_impl::ncdu_registry_codec::NcduRegistryCodec;
+use crate::services::scanner_impl::resource_scanner::ResourceScanner;
+use crate::services::scanner_impl::visitor_pipeline::VisitorPipeline;
+use crate::state::fingerprint::FingerprintAlgorithm;
+use crate::state::registry::Registry;
+use crate::state::visitor_config::VisitorConfig;
+use crate::util::cancellation_token::CancellationToken;
+use crate::ui::handler::tauri_event_handler::TauriEventHandler;
+use crate::{load_registry, lock_registry, registry_path, save_registry};
+
+/// Imports an ncdu JSON dump into the registry, replacing anything previously known under the
+/// dump's root, then runs the scan visitors over the imported entries.
+#[command]
+pub async fn import_ncdu(w: Window, uid: &str, path: &str) -> Result<String, String> {
+    info!("[{}] import_ncdu start path = {}", uid, path);
+    let logger = TauriEventHandler { window: w };
+    let file_path = registry_path();
+
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let (root, imported) = NcduRegistryCodec.import(&mut BufReader::new(file)).map_err(|e| {
+        error!("Failed to import {}: {}", path, e);
+        format!("Failed to import {}: {}", path, e)
+    })?;
+    info!("Imported {} resources under {}", imported.len(), root);
+
+    let registry_lock = lock_registry(uid).await.map_err(|e| format!("Failed to lock registry: {}", e))?;
+    let mut registry = Registry::new();
+    if Path::new(&file_path).exists() {
+        load_registry(&mut registry, &file_path).map_err(|e| format!("Failed to load registry: {}", e))?;
+    }
+    registry.remove_subtree(&root);
+    for m in imported.iter() {
+        registry.insert(&m);
+    }
+    save_registry(&mut registry, &file_path).map_err(|e| format!("Failed to save registry: {}", e))?;
+    drop(registry_lock);
+
+    let mut pipeline = VisitorPipeline::build(&VisitorConfig::defaults(), &CancellationToken::new(), None)?;
+    let mut visitors = pipeline.visitors();
+
+    let mut writer = BufWriter::new(io::stdout());
+    ResourceScanner::new().replay(&root, &registry, &mut visitors, &mut writer, &logger);
+
+    for visitable_instance in &mut visitors {
+        info!("executing {}", visitable_instance.name());
+        visitable_instance.recap(&mut writer, &logger);
+        writer.flush().unwrap();
+    }
+
+    info!("[{}] import_ncdu end", uid);
+    Ok(root)
+}
+
+/// Exports the registry entries under `root` as an ncdu JSON dump that `ncdu -f` can open.
+#[command]
+pub async fn export_ncdu(uid: &str, root: &str, path: &str) -> Result<String, String> {
+    info!("[{}] export_ncdu root = {} path = {}", uid, root, path);
+    let file_path = registry_path();
+
+    let mut registry = Registry::new();
+    {
+        let _registry_lock = lock_registry(uid).await.map_err(|e| format!("Failed to lock registry: {}", e))?;
+        load_registry(&mut registry, &file_path).map_err(|e| format!("Failed to load registry: {}", e))?;
+    }
+
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    NcduRegistryCodec.export(&registry, root, &mut BufWriter::new(file)).map_err(|e| {
+        error!("Failed to export {}: {}", root, e);
+        format!("Failed to export {}: {}", root, e)
+    })?;
+
+    Ok(format!("Exported {} to {}", root, path))
+}
+
+/// Exports a checksum manifest of the files under `root` using `algorithm`, e.g. `sha256` to
+/// compare with backup manifests. Fingerprints computed along the way are kept in the registry.
+#[command]
+pub async fn export_manifest(uid: &str, root: &str, path: &str, algorithm: &str) -> Result<String, String> {
+    info!("[{}] export_manifest root = {} path = {} algorithm = {}", uid, root, path, algorithm);
+    let algorithm: FingerprintAlgorithm = algorithm.parse()?;
+    let file_path = registry_path();
+
+    let _registry_lock = lock_registry(uid).await.map_err(|e| format!("Failed to lock registry: {}", e))?;
+    let mut registry = Registry::new();
+    load_registry(&mut registry, &file_path).map_err(|e| format!("Failed to load registry: {}", e))?;
+
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    let fingerprint_service = StreamingFingerprintService::new(algorithm, 1024 * 1024, u64::MAX, CancellationToken::new());
+    let written = write_manifest(&mut registry, root, &fingerprint_service, &mut BufWriter::new(file)).map_err(|e| {
+        error!("Failed to export manifest of {}: {}", root, e);
+        format!("Failed to export manifest of {}: {}", root, e)
+    })?;
+
+    save_registry(&mut registry, &file_path).map_err(|e| format!("Failed to save registry: {}", e))?;
+    Ok(format!("Exported {} {} fingerprints to {}", written, algorithm, path))
+}
@@ -0,0 +1,43 @@
+use chrono::Utc;
+use log::{error, info};
+use tauri::{command, Window};
+use crate::services::registry_impl::registry_verifier::RegistryVerifier;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::ui::handler::tauri_event_handler::TauriEventHandler;
+use crate::{lock_registry, registry_path, save_registry};
+
+/// Verifies the persisted registry, checking `sample_fraction` of its entries against the
+/// filesystem, and publishes the report. With `repair` set, a registry that isn't clean is
+/// repaired and saved back.
+#[command]
+pub async fn verify_registry(w: Window, uid: &str, sample_fraction: f64, repair: bool) -> Result<String, String> {
+    info!("[{}] verify_registry sample_fraction = {} repair = {}", uid, sample_fraction, repair);
+    let logger = TauriEventHandler { window: w };
+    let file_path = registry_path();
+
+    let _registry_lock = lock_registry(uid).await.map_err(|e| format!("Failed to lock registry: {}", e))?;
+
+    // Seeding from the clock samples a different slice of the registry on every run
+    let verifier = RegistryVerifier::new(sample_fraction, Utc::now().timestamp() as u64);
+    let (mut registry, report) = verifier.verify(&file_path).map_err(|e| {
+        error!("Failed to verify registry: {}", e);
+        format!("Failed to verify registry: {}", e)
+    })?;
+
+    match serde_json::to_string(&report) {
+        Ok(json) => logger.publish("registry-verify-event", json),
+        Err(e) => error!("Failed to serialize verification report: {}", e),
+    }
+
+    if report.is_clean() {
+        return Ok("Registry is healthy".to_string());
+    }
+    if !repair {
+        return Ok("Registry has problems, run a repair to fix them".to_string());
+    }
+
+    verifier.repair(&mut registry, &report);
+    save_registry(&mut registry, &file_path).map_err(|e| format!("Failed to save registry: {}", e))?;
+    info!("[{}] verify_registry repaired", uid);
+    Ok("Registry repaired".to_string())
+}
@@ -1,78 +1,226 @@
 use std::collections::HashMap;
-use std::{env};
 use std::io::{self, BufWriter, Write};
 use std::path::Path;
+use std::sync::Mutex;
 use std::time::{Instant};
-use log::{debug, info};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use serde::Deserialize;
 use tauri::command;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::ui::handler::log_event_handler::LogEventHandler;
 use crate::ui::handler::tauri_event_handler::TauriEventHandler;
+use crate::state::registry::Registry;
 use crate::state::resource_metadata::ResourceMetadata;
-use crate::services::scanner_impl::{
-    resource_scanner::ResourceScanner,
-    visitor::{progress_visitor::ProgressVisitor, scan_stats_visitor::ScanStatsVisitor, top_k_resource_visitor::TopKResourceVisitor},
-};
-use crate::services::scanner_api::visitable::Visitable;
-use crate::{load_registry, save_registry};
-use crate::util::util::play_sound;
+use crate::services::scanner_impl::resource_scanner::ResourceScanner;
+use crate::services::scanner_impl::visitor_pipeline::VisitorPipeline;
+use crate::services::registry_api::snapshot_store::SnapshotStore;
+use crate::state::keeper_policy::KeeperPolicy;
+use crate::state::visitor_config::{DuplicateFilesConfig, ImageSimilarityConfig, TextSimilarityConfig, VisitorConfig};
+use crate::state::registry_snapshot::RegistrySnapshot;
+use crate::ui::command::snapshot_manager::{latest_snapshot, snapshot_store, SNAPSHOT_ENTRY_THRESHOLD_BYTES};
+use crate::{load_registry, lock_registry, registry_path, save_registry};
+use crate::util::cancellation_token::CancellationToken;
+use crate::util::util::{play_sound, trim_dir};
 
-#[command]
-pub async fn scan_filesystem(w: tauri::Window, uid: &str, path: &str) -> Result<&'static str, String> {
-    info!("[{}] scan_filesystem start scanning root = {}", uid, path);
-    let temp_dir = env::temp_dir();
-    let file_path = temp_dir.join("output.csv");
-    let logger = TauriEventHandler { window: w };
-    let path_owned = path.to_owned();
-
-    let handle = tokio::spawn(async move {
-        let root = path_owned.clone();
-        debug!("Register visitors:");
-
-        let mut scan_stats_visitor = ScanStatsVisitor::new();
-        let mut progress_visitor = ProgressVisitor::new();
-        let mut top_resources_visitor = TopKResourceVisitor::new();
-        let mut visitors: Vec<&mut dyn Visitable> = vec![
-            &mut progress_visitor,
-            &mut scan_stats_visitor,
-            &mut top_resources_visitor,
-        ];
-
-        for v in &mut *visitors {
-            debug!("Visitor registered: {}", v.name());
-        }
+lazy_static! {
+    // Cancellation tokens of the scans in progress, keyed by the uid that started them
+    static ref ACTIVE_SCANS: Mutex<HashMap<String, CancellationToken>> = Mutex::new(HashMap::new());
+}
 
-        let start_time = Instant::now();
-        let mut scanner = ResourceScanner::new();
-        let mut registry: HashMap<String, ResourceMetadata> = HashMap::new();
-        let mut writer = BufWriter::new(io::stdout());
-
-        if Path::new(&file_path).exists() {
-            load_registry(&mut registry, &file_path).expect("Failed to load registry");
-            if !registry.contains_key(&root) {
-                let p = Path::new(&root);
-                let m = ResourceMetadata::new(&root, p.is_dir(), p.is_symlink(), 0, 0, false);
-                registry.insert(root.clone(), m);
-            }
-            info!("Registry loaded with {} resources", registry.len());
-            scanner.incremental_scan(&root, &mut registry, &mut visitors, &mut writer, &logger);
-        } else {
-            info!("Starting full resource scan");
-            scanner.full_scan(&mut registry, &root, &mut visitors, &mut writer, &logger);
-            info!("Finished full resource scan elapsed time = {:?}", start_time.elapsed());
-        }
+/// Visitors of a scan. `visitors`, or else a JSON file of them in `visitorsFile`, picks every
+/// visitor by name with its config, see `VisitorConfig`. Without either the default visitors run
+/// plus the analyses turned on by the flags below, all off unless noted.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    visitors: Option<Vec<VisitorConfig>>,
+    visitors_file: Option<String>,
+    duplicate_detection: Option<bool>,
+    fingerprint_algorithm: Option<String>,
+    /// On by default
+    verify_duplicates: Option<bool>,
+    duplicate_directories: Option<bool>,
+    directory_subsets: Option<bool>,
+    /// Picks the copy of each duplicate set to keep, nothing is staged without policies
+    keeper_policies: Option<Vec<KeeperPolicy>>,
+    /// `delete` (default) or `hardlink`, staged for the copies that aren't kept
+    keeper_action: Option<String>,
+    image_similarity: Option<bool>,
+    /// `dhash` (default) or `phash`
+    perceptual_hash: Option<String>,
+    /// Bits two image hashes may differ by, 8 by default
+    image_distance: Option<u32>,
+    text_similarity: Option<bool>,
+    /// `minhash` (default) or `simhash`
+    text_signature: Option<String>,
+    /// Similarity between 0 and 1 texts need to be clustered, 0.8 by default
+    text_threshold: Option<f64>,
+    /// Threads hashing duplicate candidates, one per core (up to 8) by default
+    hashing_workers: Option<usize>,
+    /// Cap on the bytes read per second while hashing, none by default
+    hashing_bytes_per_second: Option<u64>,
+}
 
-        save_registry(&mut registry, &file_path).expect("Failed to save registry");
+impl ScanOptions {
+    fn visitor_configs(&self) -> Result<Vec<VisitorConfig>, String> {
+        if let Some(visitors) = &self.visitors {
+            return Ok(visitors.clone());
+        }
+        if let Some(path) = &self.visitors_file {
+            return VisitorConfig::load(path);
+        }
 
-        for visitable_instance in &mut visitors {
-            info!("executing {}", visitable_instance.name());
-            visitable_instance.recap(&mut writer, &logger);
-            writer.flush().unwrap();
+        let mut configs = VisitorConfig::defaults();
+        if self.duplicate_detection.unwrap_or(false) {
+            let defaults = DuplicateFilesConfig::default();
+            configs.push(VisitorConfig::DuplicateFiles(DuplicateFilesConfig {
+                fingerprint_algorithm: self.fingerprint_algorithm.as_deref().map(str::parse).transpose()?.unwrap_or(defaults.fingerprint_algorithm),
+                verify: self.verify_duplicates.unwrap_or(defaults.verify),
+                directories: self.duplicate_directories.unwrap_or(false),
+                subsets: self.duplicate_directories.unwrap_or(false) && self.directory_subsets.unwrap_or(false),
+                keeper_policies: self.keeper_policies.clone(),
+                keeper_action: self.keeper_action.clone().unwrap_or(defaults.keeper_action),
+                hashing_workers: self.hashing_workers,
+                hashing_bytes_per_second: self.hashing_bytes_per_second,
+            }));
+        }
+        if self.image_similarity.unwrap_or(false) {
+            let defaults = ImageSimilarityConfig::default();
+            configs.push(VisitorConfig::ImageSimilarity(ImageSimilarityConfig {
+                algorithm: self.perceptual_hash.as_deref().map(str::parse).transpose()?.unwrap_or(defaults.algorithm),
+                max_distance: self.image_distance.unwrap_or(defaults.max_distance),
+            }));
         }
+        if self.text_similarity.unwrap_or(false) {
+            let defaults = TextSimilarityConfig::default();
+            configs.push(VisitorConfig::TextSimilarity(TextSimilarityConfig {
+                algorithm: self.text_signature.as_deref().map(str::parse).transpose()?.unwrap_or(defaults.algorithm),
+                threshold: self.text_threshold.unwrap_or(defaults.threshold),
+            }));
+        }
+        Ok(configs)
+    }
+}
+
+#[command]
+pub async fn scan_filesystem(w: tauri::Window, uid: &str, path: &str, options: Option<ScanOptions>) -> Result<&'static str, String> {
+    let options = options.unwrap_or_default();
+    info!("[{}] scan_filesystem start scanning root = {} options = {:?}", uid, path, options);
+    // Checked before anything else so a bad config fails the scan before it starts
+    let configs = options.visitor_configs()?;
+    VisitorConfig::validate_all(&configs)?;
+    let cancellation = CancellationToken::new();
+    let logger = TauriEventHandler { window: w };
+    ACTIVE_SCANS.lock().unwrap().insert(uid.to_owned(), cancellation.clone());
 
-        Ok("Successful scan")
-    });
+    let handle = tokio::spawn(scan(uid.to_owned(), path.to_owned(), configs, cancellation, logger));
 
     let result = handle.await.unwrap_or_else(|e| Err(format!("Failed to scan filesystem: {}", e)));
+    ACTIVE_SCANS.lock().unwrap().remove(uid);
     play_sound("sounds/notification_decorative-01.wav", 1000);
     info!("[{}] scan_filesystem end", uid);
     result
 }
+
+/// Scans `root` from the command line, with the visitors picked by `--visitor`, or else by
+/// `--visitors_file`, or else the defaults plus duplicate detection when it's turned on.
+/// Events are logged and recaps written to stdout.
+pub(crate) fn scan_from_cli(root: &str, visitors: Vec<VisitorConfig>, visitors_file: Option<String>, duplicate_detection: bool) -> Result<&'static str, String> {
+    let options = ScanOptions {
+        visitors: Some(visitors).filter(|v| !v.is_empty()),
+        visitors_file,
+        duplicate_detection: Some(duplicate_detection),
+        ..Default::default()
+    };
+    info!("[cli] scan start scanning root = {} options = {:?}", root, options);
+    let configs = options.visitor_configs()?;
+    VisitorConfig::validate_all(&configs)?;
+
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start the runtime: {}", e))?;
+    runtime.block_on(scan("cli".to_string(), root.to_string(), configs, CancellationToken::new(), LogEventHandler))
+}
+
+/// Runs a scan of `root` with `configs` and saves the registry and a snapshot of it.
+async fn scan<L: EventHandler>(uid: String, root: String, configs: Vec<VisitorConfig>, cancellation: CancellationToken, logger: L) -> Result<&'static str, String> {
+    // Snapshots of the same root are matched by it, `/a/b/` is the same root as `/a/b`
+    let root = trim_dir(&root);
+    let file_path = registry_path();
+    // Held until the registry is saved so concurrent scans can't interleave updates
+    let registry_lock = lock_registry(&uid).await.map_err(|e| {
+        error!("[{}] Failed to lock registry: {}", uid, e);
+        format!("Failed to lock registry: {}", e)
+    })?;
+
+    let previous = latest_snapshot(&root);
+    let mut pipeline = VisitorPipeline::build(&configs, &cancellation, previous.as_ref())?;
+
+    debug!("Register visitors:");
+    let mut visitors = pipeline.visitors();
+
+    for v in &mut *visitors {
+        debug!("Visitor registered: {}", v.name());
+    }
+
+    let start_time = Instant::now();
+    let mut scanner = ResourceScanner::new();
+    let mut registry = Registry::new();
+    let mut writer = BufWriter::new(io::stdout());
+
+    if Path::new(&file_path).exists() {
+        load_registry(&mut registry, &file_path).expect("Failed to load registry");
+        if !registry.contains_key(&root) {
+            let p = Path::new(&root);
+            let m = ResourceMetadata::new(&root, p.is_dir(), p.is_symlink(), 0, 0, false);
+            registry.insert(&m);
+        }
+        info!("Registry loaded with {} resources", registry.len());
+        scanner.incremental_scan(&root, &mut registry, &mut visitors, &mut writer, &logger);
+    } else {
+        info!("Starting full resource scan");
+        scanner.full_scan(&mut registry, &root, &mut visitors, &mut writer, &logger);
+        info!("Finished full resource scan elapsed time = {:?}", start_time.elapsed());
+    }
+
+    for visitable_instance in &mut visitors {
+        info!("executing {}", visitable_instance.name());
+        visitable_instance.recap(&mut writer, &logger);
+        writer.flush().unwrap();
+    }
+
+    // Keep the fingerprints duplicate detection computed so later scans don't rehash
+    drop(visitors);
+    for set in pipeline.duplicates() {
+        for p in &set.paths {
+            registry.set_fingerprint(p, set.fingerprint.clone());
+        }
+    }
+
+    info!("Registry holds {} resources in ~{} bytes", registry.len(), registry.memory_bytes());
+    save_registry(&mut registry, &file_path).expect("Failed to save registry");
+
+    let now = Utc::now();
+    let snapshot_id = now.format("%Y%m%dT%H%M%S%3f").to_string();
+    let snapshot = RegistrySnapshot::capture(&snapshot_id, now.timestamp(), &root, &registry, Some(SNAPSHOT_ENTRY_THRESHOLD_BYTES));
+    if let Err(e) = snapshot_store().save(&snapshot) {
+        error!("Failed to save snapshot {}: {}", snapshot_id, e);
+    }
+    drop(registry_lock);
+
+    Ok("Successful scan")
+}
+
+/// Asks the scan started by `uid` to stop hashing file content. The scan still finishes and
+/// saves what it has seen.
+#[command]
+pub async fn cancel_scan(uid: &str) -> Result<String, String> {
+    match ACTIVE_SCANS.lock().unwrap().get(uid) {
+        Some(cancellation) => {
+            info!("[{}] cancel_scan", uid);
+            cancellation.cancel();
+            Ok(format!("Cancelling scan {}", uid))
+        }
+        None => Err(format!("No scan in progress for {}", uid)),
+    }
+}
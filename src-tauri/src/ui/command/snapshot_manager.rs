@@ -0,0 +1,89 @@
+use std::env;
+use log::{error, info};
+use tauri::{command, Window};
+use crate::services::registry_api::snapshot_store::SnapshotStore;
+use crate::services::registry_impl::csv_snapshot_store::CsvSnapshotStore;
+use crate::services::scanner_api::event_handler::EventHandler;
+use crate::state::registry_snapshot::{size_series, RegistrySnapshot};
+use crate::ui::handler::tauri_event_handler::TauriEventHandler;
+use crate::util::util::trim_dir;
+
+const MAX_SNAPSHOTS: usize = 52;
+const DIFF_LIMIT: usize = 50;
+
+/// Files at least this large are kept individually in snapshots so new large files can be reported.
+pub(crate) const SNAPSHOT_ENTRY_THRESHOLD_BYTES: u64 = 100 * 1024 * 1024;
+
+pub(crate) fn snapshot_store() -> CsvSnapshotStore {
+    CsvSnapshotStore::new(env::temp_dir().join("snapshots"), MAX_SNAPSHOTS)
+}
+
+/// Most recent snapshot taken of `root`, if any can be read.
+pub(crate) fn latest_snapshot(root: &str) -> Option<RegistrySnapshot> {
+    let store = snapshot_store();
+    let ids = match store.list() {
+        Ok(ids) => ids,
+        Err(e) => {
+            error!("Failed to list snapshots: {}", e);
+            return None;
+        }
+    };
+    ids.iter().rev().find_map(|id| match store.load(id) {
+        Ok(snapshot) if trim_dir(snapshot.root()) == trim_dir(root) => Some(snapshot),
+        Ok(_) => None,
+        Err(e) => {
+            error!("Skipping unreadable snapshot {}: {}", id, e);
+            None
+        }
+    })
+}
+
+#[command]
+pub async fn list_snapshots(uid: &str) -> Result<Vec<String>, String> {
+    info!("[{}] list_snapshots", uid);
+    snapshot_store().list().map_err(|e| format!("Failed to list snapshots: {}", e))
+}
+
+#[command]
+pub async fn compare_snapshots(w: Window, uid: &str, from: &str, to: &str) -> Result<String, String> {
+    info!("[{}] compare_snapshots from = {} to = {}", uid, from, to);
+    let event_handler = TauriEventHandler { window: w };
+    let store = snapshot_store();
+
+    let load = |id: &str| store.load(id).map_err(|e| {
+        error!("Failed to load snapshot {}: {}", id, e);
+        format!("Failed to load snapshot {}: {}", id, e)
+    });
+    let before = load(from)?;
+    let after = load(to)?;
+
+    let diff = before.diff(&after, DIFF_LIMIT, SNAPSHOT_ENTRY_THRESHOLD_BYTES);
+    let payload = serde_json::to_string(&diff).map_err(|e| e.to_string())?;
+    event_handler.publish("snapshot-diff-event", payload);
+
+    Ok(format!("Compared {} with {}", from, to))
+}
+
+#[command]
+pub async fn directory_history(w: Window, uid: &str, path: &str) -> Result<String, String> {
+    info!("[{}] directory_history path = {}", uid, path);
+    let event_handler = TauriEventHandler { window: w };
+    let store = snapshot_store();
+
+    let ids = store.list().map_err(|e| format!("Failed to list snapshots: {}", e))?;
+    let snapshots: Vec<RegistrySnapshot> = ids.iter()
+        .filter_map(|id| match store.load(id) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                error!("Skipping unreadable snapshot {}: {}", id, e);
+                None
+            }
+        })
+        .collect();
+
+    let series = size_series(&snapshots, path);
+    let payload = serde_json::to_string(&series).map_err(|e| e.to_string())?;
+    event_handler.publish("snapshot-series-event", payload);
+
+    Ok(format!("Found {} data points for {}", series.len(), path))
+}
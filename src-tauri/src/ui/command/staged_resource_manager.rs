@@ -1,17 +1,131 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
 use log::{error, info};
 use serde::Deserialize;
+use serde_json::json;
 use tauri::{command, Window};
+use crate::services::file_api::content_comparator::ContentComparator;
 use crate::services::file_api::file_management::{DeletionStatus, FileManagement};
 use crate::services::file_impl::file_management_impl::FileManagementImpl;
+use crate::services::file_impl::streaming_content_comparator::StreamingContentComparator;
 use crate::services::scanner_api::event_handler::EventHandler;
+use crate::services::scanner_impl::visitor::junk_visitor::DELETE_DIRECTORY;
+use crate::services::scanner_impl::visitor::symlink_visitor::{resolve, Resolution, DELETE_DANGLING_LINK};
+use crate::state::junk_rule::JunkRule;
+use crate::state::registry::Registry;
+use crate::state::resource_metadata::ResourceMetadata;
 use crate::ui::handler::tauri_event_handler::TauriEventHandler;
 use crate::util::util::play_sound;
+use crate::{load_registry, lock_registry, registry_path, save_registry};
 
 #[derive(Deserialize, Debug)]
 pub struct Action {
     action: String,
     path: String,
     bytes: usize,
+    /// Copy that is kept when `path` is deleted as a duplicate of it or linked to it.
+    #[serde(default)]
+    duplicate_of: Option<String>,
+    /// Junk rule a directory staged with `delete_directory` matched.
+    #[serde(default)]
+    rule: Option<JunkRule>,
+}
+
+/// Compares `path` with the copy that is kept byte for byte.
+fn confirm_duplicate(path: &str, original: &str) -> Result<(), String> {
+    match StreamingContentComparator::default().identical(path, original, &mut |_| {}) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("content differs from the kept copy {}", original)),
+        Err(e) => Err(format!("comparison with {} failed: {}", original, e)),
+    }
+}
+
+/// Replaces the duplicate at `action.path` with a hardlink to `action.duplicate_of` and records
+/// the link in the registry. Both files must be in the registry, they're only linked if they
+/// haven't changed since they were scanned. Returns the reclaimed bytes.
+fn link_duplicate(registry: &mut Registry, action: &Action) -> Result<u64, String> {
+    let keeper_path = action.duplicate_of.as_ref().ok_or("hardlink needs the duplicate_of copy to link to")?;
+    let duplicate = registry.get(&action.path).ok_or_else(|| format!("{} hasn't been scanned", action.path))?;
+    let keeper = registry.get(keeper_path).ok_or_else(|| format!("{} hasn't been scanned", keeper_path))?;
+    confirm_duplicate(&action.path, keeper_path)?;
+
+    match FileManagementImpl.replace_with_hardlink(&duplicate, &keeper) {
+        DeletionStatus::Success => {
+            // The link shares the keeper's inode, so it takes on its metadata
+            let linked = ResourceMetadata::new(&action.path, false, false, keeper.modified(), keeper.size_bytes(), false)
+                .with_fingerprint(keeper.fingerprint().cloned())
+                .with_ownership(keeper.ownership());
+            registry.insert(&linked);
+            Ok(duplicate.size_bytes())
+        },
+        DeletionStatus::Failure(msg) => Err(msg),
+    }
+}
+
+/// Removes the symlink at `path` if it still doesn't resolve, links fixed since the scan stay.
+fn delete_dangling_link(path: &str) -> Result<(), String> {
+    let metadata = fs::symlink_metadata(path).map_err(|e| format!("failed to inspect the link: {}", e))?;
+    if !metadata.file_type().is_symlink() {
+        return Err("it isn't a symlink anymore".to_string());
+    }
+    match resolve(path) {
+        Ok(Resolution::Dangling) => {}
+        Ok(_) => return Err("the link resolves again".to_string()),
+        Err(e) => return Err(format!("failed to resolve the link: {}", e)),
+    }
+    match FileManagementImpl.delete_file(path) {
+        DeletionStatus::Success => Ok(()),
+        DeletionStatus::Failure(msg) => Err(msg),
+    }
+}
+
+/// Removes the junk directory at `action.path` if it's still a real directory below a scanned
+/// root and the rule it was staged with still matches it, marker included. Whatever got removed
+/// is dropped from the registry and added to `reclaimed_bytes`, also when the removal fails
+/// halfway.
+fn delete_junk_directory(registry: &mut Registry, action: &Action, reclaimed_bytes: &mut u64) -> Result<(), String> {
+    let rule = action.rule.as_ref().ok_or("delete_directory needs the junk rule that matched")?;
+    rule.validate()?;
+    if !registry.get(&action.path).is_some_and(|m| m.is_dir()) {
+        return Err(format!("{} hasn't been scanned as a directory", action.path));
+    }
+    // Only scan roots have no scanned parent
+    let parent = Path::new(&action.path).parent().map(|p| p.to_string_lossy().to_string());
+    if !parent.is_some_and(|p| registry.contains_key(&p)) {
+        return Err("it is the root of a scan".to_string());
+    }
+    let metadata = fs::symlink_metadata(&action.path).map_err(|e| format!("failed to inspect the directory: {}", e))?;
+    if metadata.file_type().is_symlink() || !metadata.is_dir() {
+        return Err("it isn't a directory anymore".to_string());
+    }
+    if !rule.matches(&action.path, true) {
+        return Err(format!("it doesn't match the junk rule {} anymore", rule.pattern));
+    }
+
+    let status = FileManagementImpl.delete_directory(&action.path);
+    *reclaimed_bytes += forget_removed(registry, &action.path);
+    match status {
+        DeletionStatus::Success => Ok(()),
+        DeletionStatus::Failure(msg) => Err(msg),
+    }
+}
+
+/// Drops the entries at and below `path` that are gone from disk, returns the bytes of their files.
+fn forget_removed(registry: &mut Registry, path: &str) -> u64 {
+    match fs::symlink_metadata(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let bytes = registry.walk(path)
+                .into_iter()
+                .filter_map(|id| registry.get_by_id(id))
+                .filter(|m| !m.is_dir())
+                .map(|m| m.size_bytes())
+                .sum();
+            registry.remove_subtree(path);
+            bytes
+        }
+        _ => registry.children(path).iter().map(|child| forget_removed(registry, child)).sum(),
+    }
 }
 
 #[command]
@@ -19,7 +133,19 @@ pub async fn commit(w: Window, uid: &str, actions: Vec<Action>) -> Result<String
     info!("[{}] commit start", uid);
     let event_handler = TauriEventHandler {window: w};
 
+    let uid_owned = uid.to_owned();
+
     let result = tauri::async_runtime::spawn(async move {
+        // Actions change files the registry describes, keep scans out until it's updated
+        let _registry_lock = lock_registry(&uid_owned).await.map_err(|e| format!("Failed to lock registry: {}", e))?;
+        let file_path = registry_path();
+        let mut registry = Registry::new();
+        if file_path.exists() {
+            load_registry(&mut registry, &file_path).map_err(|e| format!("Failed to load registry: {}", e))?;
+        }
+        let mut registry_changed = false;
+        let mut reclaimed_bytes: u64 = 0;
+
         // Enumerate and log each action
         for action in actions {
             info!("Processing Action: {}, Path: {}, Bytes: {}", action.action, action.path, action.bytes);
@@ -29,10 +155,21 @@ pub async fn commit(w: Window, uid: &str, actions: Vec<Action>) -> Result<String
                     // Here, implement what should happen when the action is "upload"
                     info!("Deleting from path: {}", action.path);
 
+                    // Never trust a fingerprint alone with a delete, the copy that stays must match now
+                    if let Some(original) = &action.duplicate_of {
+                        if let Err(reason) = confirm_duplicate(&action.path, original) {
+                            error!("Not deleting {}: {}", action.path, reason);
+                            event_handler.publish("commit-event", json!({"status": "failed", "path": action.path, "reason": reason}).to_string());
+                            continue;
+                        }
+                    }
+
                     let deleter = FileManagementImpl;
                     match deleter.delete_file(&action.path) {
                         DeletionStatus::Success => {
                             info!("Deleted {}", action.path);
+                            reclaimed_bytes += action.bytes as u64;
+                            registry_changed |= registry.remove(&action.path).is_some();
                             event_handler.publish("commit-event", format!("{{\"status\" : \"success\", \"path\": {:?}}}", action.path))
                         },
                         DeletionStatus::Failure(msg) => {
@@ -41,6 +178,47 @@ pub async fn commit(w: Window, uid: &str, actions: Vec<Action>) -> Result<String
                         },
                     }
                 },
+                "hardlink" => {
+                    info!("Replacing {} with a hardlink to {:?}", action.path, action.duplicate_of);
+                    match link_duplicate(&mut registry, &action) {
+                        Ok(bytes) => {
+                            reclaimed_bytes += bytes;
+                            registry_changed = true;
+                            event_handler.publish("commit-event", json!({"status": "success", "path": action.path, "reclaimed_bytes": bytes}).to_string());
+                        },
+                        Err(reason) => {
+                            error!("Not linking {}: {}", action.path, reason);
+                            event_handler.publish("commit-event", json!({"status": "failed", "path": action.path, "reason": reason}).to_string());
+                        },
+                    }
+                },
+                DELETE_DIRECTORY => {
+                    info!("Deleting directory {}", action.path);
+                    // Partly removed directories change the registry too
+                    registry_changed = true;
+                    match delete_junk_directory(&mut registry, &action, &mut reclaimed_bytes) {
+                        Ok(()) => {
+                            event_handler.publish("commit-event", json!({"status": "success", "path": action.path}).to_string());
+                        },
+                        Err(reason) => {
+                            error!("Not deleting {}: {}", action.path, reason);
+                            event_handler.publish("commit-event", json!({"status": "failed", "path": action.path, "reason": reason}).to_string());
+                        },
+                    }
+                },
+                DELETE_DANGLING_LINK => {
+                    info!("Deleting dangling link {}", action.path);
+                    match delete_dangling_link(&action.path) {
+                        Ok(()) => {
+                            registry_changed |= registry.remove(&action.path).is_some();
+                            event_handler.publish("commit-event", json!({"status": "success", "path": action.path}).to_string());
+                        },
+                        Err(reason) => {
+                            error!("Not deleting {}: {}", action.path, reason);
+                            event_handler.publish("commit-event", json!({"status": "failed", "path": action.path, "reason": reason}).to_string());
+                        },
+                    }
+                },
                 "compressing" => {
                     // Implement the download action
                     info!("Compressing file at path: {}", action.path);
@@ -53,7 +231,12 @@ pub async fn commit(w: Window, uid: &str, actions: Vec<Action>) -> Result<String
             }
         }
 
-        Ok("Hello from commit! You've been greeted from Rust asynchronously!".to_string())
+        if registry_changed {
+            save_registry(&mut registry, &file_path).map_err(|e| format!("Failed to save registry: {}", e))?;
+        }
+        event_handler.publish("commit-summary-event", json!({"reclaimed_bytes": reclaimed_bytes}).to_string());
+
+        Ok(format!("Reclaimed {} bytes", reclaimed_bytes))
     }).await.unwrap_or_else(|e| Err(format!("Failed to scan filesystem: {}", e)));
 
     play_sound("sounds/hero_decorative-celebration-02.wav", 1750);
@@ -0,0 +1,19 @@
+use log::info;
+use tauri::{command, Window};
+use crate::services::scanner_impl::visitor::type_breakdown_visitor::TypeBreakdownVisitor;
+use crate::state::visitor_config::DEFAULT_SUBTREE_DEPTH;
+use crate::ui::handler::tauri_event_handler::TauriEventHandler;
+use crate::replay_registry;
+
+/// Publishes the space by type below `path` from the registry as a `type-breakdown-event`,
+/// exported to `csv_path` when given, without scanning again.
+#[command]
+pub async fn type_breakdown(w: Window, uid: &str, path: &str, depth: Option<usize>, csv_path: Option<String>) -> Result<String, String> {
+    info!("[{}] type_breakdown path = {} depth = {:?} csv_path = {:?}", uid, path, depth, csv_path);
+    let logger = TauriEventHandler { window: w };
+
+    let visitor = || TypeBreakdownVisitor::new(depth.unwrap_or(DEFAULT_SUBTREE_DEPTH)).with_csv(csv_path);
+    replay_registry(uid, path, visitor, &logger).await?;
+
+    Ok(format!("Published the type breakdown below {}", path))
+}
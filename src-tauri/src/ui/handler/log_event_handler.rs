@@ -0,0 +1,11 @@
+use log::debug;
+use crate::services::scanner_api::event_handler::EventHandler;
+
+/// Logs events instead of emitting them to a window, for scans run from the command line.
+pub struct LogEventHandler;
+
+impl EventHandler for LogEventHandler {
+    fn publish(&self, event: &str, message: String) {
+        debug!("{}: {}", event, message);
+    }
+}
@@ -1 +1,2 @@
-pub(crate) mod tauri_event_handler;
\ No newline at end of file
+pub(crate) mod tauri_event_handler;
+pub(crate) mod log_event_handler;
\ No newline at end of file
@@ -1,3 +1,4 @@
+use log::error;
 use tauri::Window;
 use crate::services::scanner_api::event_handler::EventHandler;
 
@@ -7,6 +8,9 @@ pub struct TauriEventHandler {
 impl EventHandler for TauriEventHandler {
     fn publish(&self, event: &str,  message: String) {
 
-        self.window.emit(event, &message).expect("Failed to emit log event");
+        // A lost event only leaves a view stale, it mustn't stop the scan sending it
+        if let Err(e) = self.window.emit(event, &message) {
+            error!("Failed to emit {}: {}", event, e);
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+/// Metric tree over the Hamming distance of 64 bit hashes, finds every hash within a distance
+/// without comparing against all of them. Each hash carries the index of what it was computed from.
+#[derive(Default)]
+pub struct BkTree {
+    // (hash, item, children by distance to this node)
+    nodes: Vec<(u64, usize, HashMap<u32, usize>)>,
+}
+
+impl BkTree {
+    pub fn insert(&mut self, hash: u64, item: usize) {
+        let new = self.nodes.len();
+        let mut current = 0;
+        while current < new {
+            let distance = (self.nodes[current].0 ^ hash).count_ones();
+            match self.nodes[current].2.get(&distance) {
+                Some(&child) => current = child,
+                None => {
+                    self.nodes[current].2.insert(distance, new);
+                    break;
+                }
+            }
+        }
+        self.nodes.push((hash, item, HashMap::new()));
+    }
+
+    /// Items whose hash is at most `max_distance` bits away from `hash`.
+    pub fn within(&self, hash: u64, max_distance: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(node) = pending.pop() {
+            let (node_hash, item, children) = &self.nodes[node];
+            let distance = (node_hash ^ hash).count_ones();
+            if distance <= max_distance {
+                found.push(*item);
+            }
+            pending.extend(children.iter().filter(|(&d, _)| d.abs_diff(distance) <= max_distance).map(|(_, &c)| c));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_within() {
+        let mut tree = BkTree::default();
+        for (i, hash) in [0b0000u64, 0b0001, 0b0011, 0b0111, 0b1111].iter().enumerate() {
+            tree.insert(*hash, i);
+        }
+        let mut found = tree.within(0b0001, 1);
+        found.sort();
+        assert_eq!(found, vec![0, 1, 2]);
+        assert!(BkTree::default().within(0, 64).is_empty());
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+/// Union find over the items `0..len`, used to turn pairs of similar items into clusters.
+pub struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    pub fn new(len: usize) -> Self {
+        DisjointSet { parents: (0..len).collect() }
+    }
+
+    pub fn find(&mut self, mut item: usize) -> usize {
+        while self.parents[item] != item {
+            self.parents[item] = self.parents[self.parents[item]];
+            item = self.parents[item];
+        }
+        item
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a.max(b)] = a.min(b);
+    }
+
+    /// Groups of two or more items, in no particular order.
+    pub fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+        for item in 0..self.parents.len() {
+            let root = self.find(item);
+            groups.entry(root).or_default().push(item);
+        }
+        groups.into_values().filter(|g| g.len() > 1).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups() {
+        let mut set = DisjointSet::new(6);
+        set.union(0, 3);
+        set.union(3, 5);
+        set.union(1, 2);
+
+        let mut groups = set.groups();
+        groups.sort();
+        assert_eq!(groups, vec![vec![0, 3, 5], vec![1, 2]]);
+    }
+}
@@ -1 +1,7 @@
 pub mod util;
+pub mod cancellation_token;
+pub mod bk_tree;
+pub mod disjoint_set;
+pub mod rate_limiter;
+pub mod mount_info;
+pub mod user_database;
\ No newline at end of file
@@ -0,0 +1,139 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::Serialize;
+
+const MOUNTS: &str = "/proc/self/mounts";
+
+/// A line of the mount table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mount {
+    pub mount_point: String,
+    pub fs_type: String,
+    pub options: Vec<String>,
+}
+
+impl Mount {
+    /// Access times aren't updated at all on reads.
+    pub fn is_noatime(&self) -> bool {
+        self.options.iter().any(|o| o == "noatime")
+    }
+}
+
+/// Inode counts of a filesystem.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct InodeUsage {
+    pub total: u64,
+    pub free: u64,
+    /// Free for unprivileged users
+    pub available: u64,
+}
+
+impl InodeUsage {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.free)
+    }
+
+    /// Share of the inodes in use, 0 for filesystems without a fixed inode count.
+    pub fn used_ratio(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.used() as f64 / total as f64,
+        }
+    }
+}
+
+/// Inode usage of the filesystem `path` is on, from statvfs.
+pub fn inode_usage(path: &str) -> io::Result<InodeUsage> {
+    let path = CString::new(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(InodeUsage { total: stats.f_files as u64, free: stats.f_ffree as u64, available: stats.f_favail as u64 })
+}
+
+/// The mount table, empty when it can't be read.
+pub fn mounts() -> Vec<Mount> {
+    fs::read_to_string(MOUNTS).map(|m| parse_mounts(&m)).unwrap_or_default()
+}
+
+/// Parses `/proc/self/mounts`, mount points have their spaces and tabs escaped in octal.
+pub fn parse_mounts(content: &str) -> Vec<Mount> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_device, mount_point, fs_type, options) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+            Some(Mount {
+                mount_point: unescape(mount_point),
+                fs_type: fs_type.to_string(),
+                options: options.split(',').map(str::to_string).collect(),
+            })
+        })
+        .collect()
+}
+
+/// The mount `path` is on, the one with the longest mount point above it. Later mounts over
+/// the same point hide earlier ones.
+pub fn find_mount<'a>(mounts: &'a [Mount], path: &str) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| Path::new(path).starts_with(&m.mount_point))
+        .max_by_key(|(i, m)| (m.mount_point.len(), *i))
+        .map(|(_, m)| m)
+}
+
+fn unescape(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(position) = rest.find('\\') {
+        result.push_str(&rest[..position]);
+        let code = rest.get(position + 1..position + 4).and_then(|c| u8::from_str_radix(c, 8).ok());
+        match code {
+            Some(code) => {
+                result.push(code as char);
+                rest = &rest[position + 4..];
+            }
+            None => {
+                result.push('\\');
+                rest = &rest[position + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_mount() {
+        let mounts = parse_mounts("/dev/sda1 / ext4 rw,relatime 0 0\n\
+            /dev/sdb1 /mnt/media\\040share xfs rw,noatime,attr2 0 0\n\
+            tmpfs /mnt/media tmpfs rw 0 0\n\
+            /dev/sdc1 /mnt/media\\040share ext4 rw,strictatime 0 0\n");
+
+        assert_eq!(mounts[1].mount_point, "/mnt/media share");
+        assert_eq!(find_mount(&mounts, "/home/user").unwrap().options, vec!["rw", "relatime"]);
+        assert_eq!(find_mount(&mounts, "/mnt/media/x").unwrap().fs_type, "tmpfs");
+        let share = find_mount(&mounts, "/mnt/media share/movies").unwrap();
+        assert_eq!(share.fs_type, "ext4");
+        assert!(!share.is_noatime());
+        assert!(mounts[1].is_noatime());
+        assert_eq!(find_mount(&mounts, "/mnt/media shares").unwrap().mount_point, "/");
+    }
+
+    #[test]
+    fn test_inode_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = inode_usage(&dir.path().to_string_lossy()).unwrap();
+        assert!(usage.free <= usage.total);
+        assert!((0.0..=1.0).contains(&usage.used_ratio()));
+        assert!(inode_usage("/does/not/exist").is_err());
+    }
+}
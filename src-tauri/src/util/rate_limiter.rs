@@ -0,0 +1,53 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Unused budget carried over from an idle period, so a pause doesn't turn into a burst.
+const MAX_BURST: Duration = Duration::from_secs(1);
+
+/// Caps how many units, like bytes read, threads sharing the limiter use per second on average.
+/// Callers report what they used and sleep until the budget caught up with it.
+#[derive(Debug)]
+pub struct RateLimiter {
+    units_per_second: u64,
+    next_free: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(units_per_second: u64) -> Self {
+        RateLimiter { units_per_second: units_per_second.max(1), next_free: Mutex::new(None) }
+    }
+
+    /// Books `units` and sleeps until they fit the rate.
+    pub fn acquire(&self, units: u64) {
+        let wait = {
+            let mut next_free = self.next_free.lock().unwrap();
+            let now = Instant::now();
+            let earliest = now.checked_sub(MAX_BURST).unwrap_or(now);
+            let start = next_free.map_or(now, |n| n.max(earliest));
+            let end = start + Duration::from_secs_f64(units as f64 / self.units_per_second as f64);
+            *next_free = Some(end);
+            end.saturating_duration_since(now)
+        };
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caps_rate_across_threads() {
+        let limiter = RateLimiter::new(10_000);
+        let start = Instant::now();
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| (0..3).for_each(|_| limiter.acquire(1_000)));
+            }
+        });
+        assert!(start.elapsed() >= Duration::from_millis(550), "{:?}", start.elapsed());
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::{io, mem, ptr};
+use log::debug;
+
+const INITIAL_BUFFER_BYTES: usize = 1024;
+const MAX_BUFFER_BYTES: usize = 1024 * 1024;
+
+/// User and group names by id, looked up through NSS so accounts from directory services like
+/// LDAP or SSSD are known too. Every id is looked up once, misses included.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserDatabase {
+    /// Without NSS only the parsed ids are known
+    nss: bool,
+    users: HashMap<u32, Option<String>>,
+    groups: HashMap<u32, Option<String>>,
+}
+
+impl UserDatabase {
+    /// The system's database, through `getpwuid_r` and `getgrgid_r`.
+    pub fn load() -> Self {
+        UserDatabase { nss: true, ..UserDatabase::default() }
+    }
+
+    /// A fixed database of passwd and group file contents, both `:` separated with the name
+    /// first and the id third, `name:x:id:...`.
+    #[cfg(test)]
+    pub fn parse(passwd: &str, group: &str) -> Self {
+        UserDatabase { nss: false, users: parse_ids(passwd), groups: parse_ids(group) }
+    }
+
+    pub fn has_users(&self) -> bool {
+        self.nss || self.users.values().any(Option::is_some)
+    }
+
+    pub fn has_groups(&self) -> bool {
+        self.nss || self.groups.values().any(Option::is_some)
+    }
+
+    pub fn user_name(&mut self, uid: u32) -> Option<&str> {
+        let nss = self.nss;
+        self.users.entry(uid).or_insert_with(|| if nss { lookup_user(uid) } else { None }).as_deref()
+    }
+
+    pub fn group_name(&mut self, gid: u32) -> Option<&str> {
+        let nss = self.nss;
+        self.groups.entry(gid).or_insert_with(|| if nss { lookup_group(gid) } else { None }).as_deref()
+    }
+}
+
+fn lookup_user(uid: u32) -> Option<String> {
+    lookup(|buffer| {
+        let mut entry: libc::passwd = unsafe { mem::zeroed() };
+        let mut result = ptr::null_mut();
+        let code = unsafe { libc::getpwuid_r(uid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        // The name points into the buffer, so it's copied before the buffer is reused
+        (code, (!result.is_null()).then(|| unsafe { CStr::from_ptr(entry.pw_name) }.to_string_lossy().into_owned()))
+    })
+}
+
+fn lookup_group(gid: u32) -> Option<String> {
+    lookup(|buffer| {
+        let mut entry: libc::group = unsafe { mem::zeroed() };
+        let mut result = ptr::null_mut();
+        let code = unsafe { libc::getgrgid_r(gid, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        (code, (!result.is_null()).then(|| unsafe { CStr::from_ptr(entry.gr_name) }.to_string_lossy().into_owned()))
+    })
+}
+
+/// Runs a reentrant lookup, growing its buffer while the entry doesn't fit. Failures are
+/// logged and count as unknown ids.
+fn lookup(mut call: impl FnMut(&mut [libc::c_char]) -> (i32, Option<String>)) -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; INITIAL_BUFFER_BYTES];
+    loop {
+        match call(&mut buffer) {
+            (0, name) => return name,
+            (libc::ERANGE, _) if buffer.len() < MAX_BUFFER_BYTES => buffer.resize(buffer.len() * 2, 0),
+            (code, _) => {
+                debug!("User database lookup failed: {}", io::Error::from_raw_os_error(code));
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn parse_ids(content: &str) -> HashMap<u32, Option<String>> {
+    let mut ids = HashMap::new();
+    for line in content.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let mut fields = line.split(':');
+        if let (Some(name), Some(id)) = (fields.next(), fields.nth(1).and_then(|id| id.parse::<u32>().ok())) {
+            // The first entry of an id wins, as with getpwuid
+            ids.entry(id).or_insert_with(|| Some(name.to_string()));
+        }
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut database = UserDatabase::parse(
+            "root:x:0:0:root:/root:/bin/bash\n# comment\nalice:x:1000:1000::/home/alice:/bin/sh\ntoor:x:0:0::/root:/bin/sh\nbroken:x:nope:0::/:\n",
+            "root:x:0:\nstaff:x:50:alice,bob\n",
+        );
+
+        assert_eq!(database.users.len(), 2);
+        assert_eq!(database.user_name(0), Some("root"));
+        assert_eq!(database.user_name(1000), Some("alice"));
+        assert_eq!(database.user_name(1001), None);
+        assert_eq!(database.group_name(50), Some("staff"));
+        assert_eq!(database.group_name(1000), None);
+        assert!(database.has_users());
+        assert!(!UserDatabase::default().has_users());
+    }
+
+    #[test]
+    fn test_nss_lookups_are_cached() {
+        let mut database = UserDatabase::load();
+
+        assert_eq!(database.user_name(0), Some("root"));
+        assert!(database.group_name(0).is_some());
+        assert_eq!(database.user_name(u32::MAX - 7), None);
+        assert_eq!(database.users.get(&(u32::MAX - 7)), Some(&None));
+        assert!(database.has_users() && database.has_groups());
+    }
+}
@@ -1,5 +1,6 @@
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
 use std::time::Duration;
 use rodio::{Decoder, OutputStream, Source};
 use tokio::task;
@@ -92,6 +93,29 @@ pub fn add_groupings_u64(number: u64) -> String {
     result.iter().rev().collect()
 }
 
+/// `path` without trailing slashes, `/` stays as is.
+pub fn trim_dir(path: &str) -> String {
+    match path.trim_end_matches('/') {
+        "" => "/".to_string(),
+        path => path.to_string(),
+    }
+}
+
+/// `root` and the directories below it, up to `depth` levels, that `path` is in.
+pub fn subtrees(root: &str, path: &str, depth: usize) -> Vec<String> {
+    let mut subtrees = vec![root.to_string()];
+    let relative = match Path::new(path).parent().and_then(|p| p.strip_prefix(root).ok()) {
+        Some(relative) => relative,
+        None => return subtrees,
+    };
+    let mut subtree = Path::new(root).to_path_buf();
+    for component in relative.components().take(depth) {
+        subtree.push(component);
+        subtrees.push(subtree.to_string_lossy().to_string());
+    }
+    subtrees
+}
+
 pub fn play_sound(file_path: &str, sleep_ms: u64) {
     let file_path = file_path.to_owned();  // Clone the file_path to own it.
 
@@ -126,6 +150,16 @@ mod tests {
         assert_eq!(add_groupings_usize(0), "0");
     }
 
+    #[test]
+    fn test_subtrees() {
+        assert_eq!(subtrees("/r", "/r/a/b/c/f", 2), vec!["/r", "/r/a", "/r/a/b"]);
+        assert_eq!(subtrees("/r", "/r/f", 2), vec!["/r"]);
+        assert_eq!(subtrees("/", "/a/f", 1), vec!["/", "/a"]);
+        assert_eq!(subtrees("/r", "/other/f", 1), vec!["/r"]);
+        assert_eq!(trim_dir("/r/"), "/r");
+        assert_eq!(trim_dir("/"), "/");
+    }
+
     #[test]
     fn test_add_groupings_with_u32() {
         assert_eq!(add_groupings_u32(1234567890), "1,234,567,890");
@@ -7,6 +7,9 @@ import './ReactTabsOverrides.css'
 import StagingTab from "./storage/StagingTab.jsx";
 import InspectionTab from "./storage/InspectionTab.jsx";
 import ScanTab from "./storage/ScanTab.jsx";
+import SymlinkTab from "./storage/SymlinkTab.jsx";
+import JunkTab from "./storage/JunkTab.jsx";
+import KeeperTab from "./storage/KeeperTab.jsx";
 
 import StorageIcon from '@mui/icons-material/Storage';
 import ComputeIcon from '@mui/icons-material/Computer'; // Assuming this icon for Compute
@@ -15,6 +18,9 @@ import NetworkCheckIcon from '@mui/icons-material/NetworkCheck'; // Assuming thi
 import SearchIcon from '@mui/icons-material/Search';
 import FindInPageIcon from '@mui/icons-material/FindInPage';
 import LayersIcon from '@mui/icons-material/Layers';
+import LinkOffIcon from '@mui/icons-material/LinkOff';
+import DeleteSweepIcon from '@mui/icons-material/DeleteSweep';
+import ContentCopyIcon from '@mui/icons-material/ContentCopy';
 import Co2Icon from '@mui/icons-material/Co2';
 
 
@@ -66,6 +72,21 @@ function App() {
                             <FindInPageIcon style={{marginRight: '8px'}}/>Inspect
                         </div>
                     </Tab>
+                    <Tab>
+                        <div style={{display: 'flex', alignItems: 'center'}}>
+                            <LinkOffIcon style={{marginRight: '8px'}}/>Symlinks
+                        </div>
+                    </Tab>
+                    <Tab>
+                        <div style={{display: 'flex', alignItems: 'center'}}>
+                            <DeleteSweepIcon style={{marginRight: '8px'}}/>Junk
+                        </div>
+                    </Tab>
+                    <Tab>
+                        <div style={{display: 'flex', alignItems: 'center'}}>
+                            <ContentCopyIcon style={{marginRight: '8px'}}/>Keepers
+                        </div>
+                    </Tab>
                     <Tab>
                         <div style={{display: 'flex', alignItems: 'center'}}>
                             <LayersIcon style={{marginRight: '8px'}}/>Staging
@@ -82,6 +103,21 @@ function App() {
                         <InspectionTab reset={reset} setActions={setActions}></InspectionTab>
                     </div>
                 </TabPanel>
+                <TabPanel>
+                    <div className="scrollable-content">
+                        <SymlinkTab reset={reset} setActions={setActions}></SymlinkTab>
+                    </div>
+                </TabPanel>
+                <TabPanel>
+                    <div className="scrollable-content">
+                        <JunkTab reset={reset} setActions={setActions}></JunkTab>
+                    </div>
+                </TabPanel>
+                <TabPanel>
+                    <div className="scrollable-content">
+                        <KeeperTab reset={reset} setActions={setActions}></KeeperTab>
+                    </div>
+                </TabPanel>
                 <TabPanel>
                     <div className="scrollable-content">
                         <StagingTab reset={reset} actions={actions} setActions={setActions}></StagingTab>
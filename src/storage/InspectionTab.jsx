@@ -63,31 +63,57 @@ function getMimeTypeIcon(compressible, mime_type) {
     }
 }
 
+const RANKINGS = {
+    "top-k-event": "Largest files",
+    "top-k-dir-size-event": "Largest directories",
+    "top-k-dir-files-event": "Directories by file count",
+    "top-k-dir-growth-event": "Fastest growing directories",
+};
+
+// Clusters of look-alike images, the first image of each is the one to keep
+const IMAGE_SIMILARITY = "image-similarity-event";
+
 function InspectionTab({ reset, setActions }) {
-    const [topKFiles, setTopKFiles] = useState([]);
+    const [rankings, setRankings] = useState({});
+    const [ranking, setRanking] = useState("top-k-event");
+    const [imageClusters, setImageClusters] = useState(null);
     const [selected, setSelected] = useState({});
+    const topKFiles = rankings[ranking] || [];
 
     useEffect(() => {
         if (reset) {
-            setTopKFiles([]); // Clears the table
+            setRankings({}); // Clears the table
+            setImageClusters(null);
             setSelected({});
         }
     }, [reset]);
 
     useEffect(() => {
-        const handleTopKEvent = (event) => {
+        const unsubscribe = listen(IMAGE_SIMILARITY, (event) => {
             try {
-                const data = JSON.parse(event.payload);
-                setTopKFiles(data);
+                setImageClusters(JSON.parse(event.payload).clusters);
             } catch (e) {
                 logger.error(`Error parsing JSON:`, e);
             }
+        });
+
+        return () => {
+            unsubscribe.then((unsub) => unsub());
         };
+    }, []);
 
-        const unsubscribe = listen("top-k-event", handleTopKEvent);
+    useEffect(() => {
+        const unsubscribes = Object.keys(RANKINGS).map((name) => listen(name, (event) => {
+            try {
+                const data = JSON.parse(event.payload);
+                setRankings(prev => ({ ...prev, [name]: data }));
+            } catch (e) {
+                logger.error(`Error parsing JSON:`, e);
+            }
+        }));
 
         return () => {
-            unsubscribe.then((unsub) => unsub());
+            unsubscribes.forEach((unsubscribe) => unsubscribe.then((unsub) => unsub()));
         };
     }, []);
 
@@ -118,6 +144,43 @@ function InspectionTab({ reset, setActions }) {
 
     return (
         <div className="inspect-container">
+            <select value={ranking} onChange={(event) => setRanking(event.target.value)}>
+                {Object.entries(RANKINGS).map(([name, label]) => (
+                    <option key={name} value={name} disabled={!rankings[name]}>{label}</option>
+                ))}
+                <option value={IMAGE_SIMILARITY} disabled={!imageClusters}>Similar images</option>
+            </select>
+            {ranking === IMAGE_SIMILARITY ? (
+            <table>
+                <thead>
+                <tr>
+                    <th className="center-text">Action</th>
+                    <th className="center-text">Cluster</th>
+                    <th className="center-text">Distance</th>
+                    <th className="right-text">Bytes</th>
+                    <th className="left-text">Path</th>
+                </tr>
+                </thead>
+                <tbody>
+                {(imageClusters || []).flatMap((cluster, clusterIndex) => cluster.images.map((image, index) => (
+                    <tr key={image.path}>
+                        <td className="center-text">
+                            {index > 0 && (
+                                <DeleteIcon
+                                    style={{ color: selected[image.path] && selected[image.path].delete ? '#83f52c' : 'inherit' }}
+                                    onClick={(event) => handleIconClick(event, 'delete', image.path, image.size_bytes)}
+                                />
+                            )}
+                        </td>
+                        <td className="center-text">{clusterIndex + 1}</td>
+                        <td className="center-text">{index === 0 ? 'keep' : image.distance}</td>
+                        <td className="right-text">{image.size_bytes.toLocaleString("en-US")}</td>
+                        <td className="left-text">{image.path}</td>
+                    </tr>
+                )))}
+                </tbody>
+            </table>
+            ) : (
             <table>
                 <thead>
                 <tr>
@@ -157,6 +220,7 @@ function InspectionTab({ reset, setActions }) {
                 ))}
                 </tbody>
             </table>
+            )}
         </div>
     );
 }
@@ -0,0 +1,111 @@
+import React, {useEffect, useState} from 'react';
+import "./InspectionTab.css";
+import logger from "../logger.jsx";
+import {listen} from "@tauri-apps/api/event";
+
+import DeleteIcon from '@mui/icons-material/Delete';
+
+const EMPTY_JUNK = { reclaimable_bytes: 0, categories: [], findings: [] };
+
+function JunkTab({ reset, setActions }) {
+    const [junk, setJunk] = useState(EMPTY_JUNK);
+    const [staged, setStaged] = useState({});
+
+    useEffect(() => {
+        if (reset) {
+            setJunk(EMPTY_JUNK); // Clears the tables
+            setStaged({});
+        }
+    }, [reset]);
+
+    useEffect(() => {
+        const handleJunkEvent = (event) => {
+            try {
+                setJunk(JSON.parse(event.payload));
+            } catch (e) {
+                logger.error(`Error parsing JSON:`, e);
+            }
+        };
+
+        const unsubscribe = listen("junk-event", handleJunkEvent);
+
+        return () => {
+            unsubscribe.then((unsub) => unsub());
+        };
+    }, []);
+
+    // Findings come with the action the commit expects, directories with the rule it re-checks
+    const handleStage = (event, findings) => {
+        event.stopPropagation();
+        logger.info(`Staging ${findings.length} junk deletions`);
+        setActions(prevActions => [
+            ...prevActions,
+            ...findings
+                .filter(finding => !prevActions.some(a => a.path === finding.path))
+                .map(finding => ({ ...finding.action, status: 'pending' })),
+        ]);
+        setStaged(prev => ({ ...prev, ...Object.fromEntries(findings.map(finding => [finding.path, true])) }));
+    };
+
+    const findingsOf = (category) => junk.findings.filter(finding => finding.category === category);
+
+    return (
+        <div className="inspect-container">
+            <div>Reclaimable bytes: {junk.reclaimable_bytes.toLocaleString("en-US")}</div>
+            <table>
+                <thead>
+                <tr>
+                    <th className="center-text">Stage</th>
+                    <th className="left-text">Category</th>
+                    <th className="right-text">Findings</th>
+                    <th className="right-text">Bytes</th>
+                </tr>
+                </thead>
+                <tbody>
+                {junk.categories.map((row) => (
+                    <tr key={`category-${row.category}`}>
+                        <td className="center-text">
+                            <DeleteIcon
+                                style={{ color: findingsOf(row.category).every(f => staged[f.path]) ? '#83f52c' : 'inherit' }}
+                                onClick={(event) => handleStage(event, findingsOf(row.category))}
+                            />
+                        </td>
+                        <td className="left-text">{row.category}</td>
+                        <td className="right-text">{row.findings.toLocaleString("en-US")}</td>
+                        <td className="right-text">{row.size_bytes.toLocaleString("en-US")}</td>
+                    </tr>
+                ))}
+                </tbody>
+            </table>
+            <table>
+                <thead>
+                <tr>
+                    <th className="center-text">Stage</th>
+                    <th className="left-text">Category</th>
+                    <th className="left-text">Path</th>
+                    <th className="right-text">Files</th>
+                    <th className="right-text">Bytes</th>
+                </tr>
+                </thead>
+                <tbody>
+                {junk.findings.map((finding) => (
+                    <tr key={finding.path}>
+                        <td className="center-text">
+                            <DeleteIcon
+                                style={{ color: staged[finding.path] ? '#83f52c' : 'inherit' }}
+                                onClick={(event) => handleStage(event, [finding])}
+                            />
+                        </td>
+                        <td className="left-text">{finding.category}</td>
+                        <td className="left-text">{finding.directory ? `${finding.path}/` : finding.path}</td>
+                        <td className="right-text">{finding.files.toLocaleString("en-US")}</td>
+                        <td className="right-text">{finding.size_bytes.toLocaleString("en-US")}</td>
+                    </tr>
+                ))}
+                </tbody>
+            </table>
+        </div>
+    );
+}
+
+export default JunkTab;
@@ -0,0 +1,100 @@
+import React, {useEffect, useState} from 'react';
+import "./InspectionTab.css";
+import logger from "../logger.jsx";
+import {listen} from "@tauri-apps/api/event";
+
+import LayersIcon from '@mui/icons-material/Layers';
+
+function KeeperTab({ reset, setActions }) {
+    const [decisions, setDecisions] = useState([]);
+    const [stagedBytes, setStagedBytes] = useState(0);
+    const [staged, setStaged] = useState({});
+
+    useEffect(() => {
+        if (reset) {
+            setDecisions([]); // Clears the table
+            setStagedBytes(0);
+            setStaged({});
+        }
+    }, [reset]);
+
+    useEffect(() => {
+        const handleKeeperEvent = (event) => {
+            try {
+                const payload = JSON.parse(event.payload);
+                setDecisions(payload.decisions);
+                setStagedBytes(payload.staged_bytes);
+            } catch (e) {
+                logger.error(`Error parsing JSON:`, e);
+            }
+        };
+
+        const unsubscribe = listen("keeper-event", handleKeeperEvent);
+
+        return () => {
+            unsubscribe.then((unsub) => unsub());
+        };
+    }, []);
+
+    // The actions of a decision are staged as is, the commit re-checks each copy against its keeper
+    const handleStage = (event, actions) => {
+        event.stopPropagation();
+        logger.info(`Staging ${actions.length} keeper actions`);
+        setActions(prevActions => [
+            ...prevActions,
+            ...actions
+                .filter(action => !prevActions.some(a => a.path === action.path))
+                .map(action => ({ ...action, status: 'pending' })),
+        ]);
+        setStaged(prev => ({ ...prev, ...Object.fromEntries(actions.map(action => [action.path, true])) }));
+    };
+
+    return (
+        <div className="inspect-container">
+            <div>Bytes the keeper actions reclaim: {stagedBytes.toLocaleString("en-US")}</div>
+            <table>
+                <thead>
+                <tr>
+                    <th className="center-text">Stage</th>
+                    <th className="center-text">Action</th>
+                    <th className="left-text">Path</th>
+                    <th className="left-text">Why</th>
+                    <th className="right-text">Bytes</th>
+                </tr>
+                </thead>
+                <tbody>
+                {decisions.flatMap((decision) => [
+                    <tr key={`keep-${decision.keeper}`}>
+                        <td className="center-text">
+                            <LayersIcon
+                                style={{ color: decision.actions.every(a => staged[a.path]) ? '#83f52c' : 'inherit' }}
+                                onClick={(event) => handleStage(event, decision.actions)}
+                            />
+                        </td>
+                        <td className="center-text">keep</td>
+                        <td className="left-text">{decision.keeper}</td>
+                        <td className="left-text">{decision.reasons.join(", ")}</td>
+                        <td className="right-text"></td>
+                    </tr>,
+                    ...decision.actions.map((action) => (
+                        <tr key={`${action.action}-${action.path}`}>
+                            <td className="center-text">
+                                <LayersIcon
+                                    style={{ color: staged[action.path] ? '#83f52c' : 'inherit' }}
+                                    onClick={(event) => handleStage(event, [action])}
+                                />
+                            </td>
+                            <td className="center-text">{action.action}</td>
+                            <td className="left-text">{action.path}</td>
+                            <td className="left-text">copy of {action.duplicate_of}</td>
+                            <td className="right-text">{action.bytes.toLocaleString("en-US")}</td>
+                        </tr>
+                    )),
+                ])}
+                </tbody>
+            </table>
+        </div>
+    );
+}
+
+export default KeeperTab;
@@ -10,6 +10,11 @@ import styled from "styled-components";
 import {homeDir} from "@tauri-apps/api/path";
 import { v4 as uuidv4 } from 'uuid';
 
+// Visitors a scan can run, see VisitorConfig
+const VISITORS = ["progress", "scan_stats", "top_k", "directory_analyzer", "type_breakdown", "age_analysis", "symlinks",
+    "junk", "inode_hogs", "ownership", "duplicate_files", "image_similarity", "text_similarity"];
+const DEFAULT_VISITORS = ["progress", "scan_stats", "top_k", "symlinks"];
+
 function ScanTab({ reset, setReset }) {
 
     const ScanStatus = {
@@ -27,6 +32,10 @@ function ScanTab({ reset, setReset }) {
     const [elapsedTime, setElapsedTime] = useState(0);
     const [scanStatus, setScanStatus] = useState(ScanStatus.Stopped);
     const [timer, setTimer] = useState(null);
+    const [visitors, setVisitors] = useState(DEFAULT_VISITORS);
+    // JSON config of each visitor, e.g. {"keeperPolicies": [{"policy": "shortest_path"}]}
+    const [visitorConfigs, setVisitorConfigs] = useState({});
+    const [visitorsFile, setVisitorsFile] = useState('');
 
     useEffect(() => {
         if (inputRef.current) {
@@ -82,8 +91,9 @@ function ScanTab({ reset, setReset }) {
             setScanStatus(ScanStatus.Scanning);
 
             const uid = uuidv4();
+            const options = scanOptions();
             logger.info(`[${uid}] Rust call scan_fileystem start`);
-            const result = await invoke('scan_filesystem', {uid, path});
+            const result = await invoke('scan_filesystem', {uid, path, options});
             logger.info(`[${uid}] Rust call scan_fileystem finished`);
             setScanStatus(ScanStatus.Completed);
         } catch (error) {
@@ -92,6 +102,25 @@ function ScanTab({ reset, setReset }) {
         }
     }
 
+    // A visitors file picks the visitors on its own, otherwise the checked ones run
+    const scanOptions = () => {
+        if (visitorsFile.trim() !== '') {
+            return {visitorsFile: visitorsFile.trim()};
+        }
+        return {
+            visitors: visitors.map((name) => {
+                const config = (visitorConfigs[name] || '').trim();
+                return {...(config === '' ? {} : JSON.parse(config)), name};
+            }),
+        };
+    };
+
+    const toggleVisitor = (name) => {
+        setVisitors((current) => current.includes(name)
+            ? current.filter((v) => v !== name)
+            : VISITORS.filter((v) => v === name || current.includes(v)));
+    };
+
     const handleLogEvent = (event) => {
         try {
             const data = JSON.parse(event.payload);
@@ -128,6 +157,23 @@ function ScanTab({ reset, setReset }) {
                     <DirectionsRunIcon/>
                 </button>
             </div>
+            <div className="scantab-visitors">
+                {VISITORS.map((name) => (
+                    <div key={name} className="scantab-visitor">
+                        <label>
+                            <input type="checkbox" checked={visitors.includes(name)} disabled={visitorsFile.trim() !== ''}
+                                   onChange={() => toggleVisitor(name)}/>
+                            {name}
+                        </label>
+                        {visitors.includes(name) && visitorsFile.trim() === '' &&
+                            <input type="text" value={visitorConfigs[name] || ''} placeholder="{}"
+                                   onChange={(e) => setVisitorConfigs({...visitorConfigs, [name]: e.target.value})}/>}
+                    </div>
+                ))}
+                <input className="styled-input" type="text" value={visitorsFile}
+                       onChange={(e) => setVisitorsFile(e.target.value)}
+                       placeholder="Visitors file (JSON), replaces the visitors above"/>
+            </div>
             <ScanTabStats status={scanStatus} elapsedTime={elapsedTime} resources={resources} directories={directories}
                           files={files} size={size}></ScanTabStats>
             <ScanTabLog logs={logs}/>
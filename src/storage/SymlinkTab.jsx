@@ -0,0 +1,84 @@
+import React, {useEffect, useState} from 'react';
+import "./InspectionTab.css";
+import logger from "../logger.jsx";
+import {listen} from "@tauri-apps/api/event";
+
+import DeleteIcon from '@mui/icons-material/Delete';
+
+const KINDS = {
+    dangling: "Dangling",
+    escaping: "Escaping the root",
+    loops: "Loops",
+};
+
+function SymlinkTab({ reset, setActions }) {
+    const [symlinks, setSymlinks] = useState({ dangling: [], escaping: [], loops: [] });
+    const [staged, setStaged] = useState({});
+
+    useEffect(() => {
+        if (reset) {
+            setSymlinks({ dangling: [], escaping: [], loops: [] }); // Clears the table
+            setStaged({});
+        }
+    }, [reset]);
+
+    useEffect(() => {
+        const handleSymlinkEvent = (event) => {
+            try {
+                setSymlinks(JSON.parse(event.payload));
+            } catch (e) {
+                logger.error(`Error parsing JSON:`, e);
+            }
+        };
+
+        const unsubscribe = listen("symlink-event", handleSymlinkEvent);
+
+        return () => {
+            unsubscribe.then((unsub) => unsub());
+        };
+    }, []);
+
+    // Dangling links come with the action the commit expects for them
+    const handleStageDelete = (event, row) => {
+        event.stopPropagation();
+        logger.info(`Staging delete of dangling link ${row.path}`);
+        setActions(prevActions => prevActions.some(a => a.path === row.path)
+            ? prevActions
+            : [...prevActions, { ...row.action, status: 'pending' }]);
+        setStaged(prev => ({ ...prev, [row.path]: true }));
+    };
+
+    return (
+        <div className="inspect-container">
+            <table>
+                <thead>
+                <tr>
+                    <th className="center-text">Action</th>
+                    <th className="center-text">Kind</th>
+                    <th className="left-text">Path</th>
+                    <th className="left-text">Target</th>
+                </tr>
+                </thead>
+                <tbody>
+                {Object.entries(KINDS).flatMap(([kind, label]) => symlinks[kind].map((row) => (
+                    <tr key={`${kind}-${row.path}`}>
+                        <td className="center-text">
+                            {row.action && (
+                                <DeleteIcon
+                                    style={{ color: staged[row.path] ? '#83f52c' : 'inherit' }}
+                                    onClick={(event) => handleStageDelete(event, row)}
+                                />
+                            )}
+                        </td>
+                        <td className="center-text">{label}</td>
+                        <td className="left-text">{row.path}</td>
+                        <td className="left-text">{row.resolved ? `${row.target} (${row.resolved})` : row.target}</td>
+                    </tr>
+                )))}
+                </tbody>
+            </table>
+        </div>
+    );
+}
+
+export default SymlinkTab;